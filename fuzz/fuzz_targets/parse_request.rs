@@ -0,0 +1,10 @@
+//! Raw device reads. Exercises the header and length checks.
+#![no_main]
+
+use fusion::messages::request::Request;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let (reply_tx, _reply_rx) = fusion::create_reply_channel();
+    let _ = Request::parse(data, &reply_tx);
+});
@@ -0,0 +1,33 @@
+//! Well-framed messages with arbitrary opcode and payload. Exercises the per-opcode argument decoding that the raw
+//! target rarely reaches because its header length seldom matches.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use fusion::messages::request::Request;
+use libfuzzer_sys::fuzz_target;
+
+/// Size of `fuse_in_header`
+const SIZE_HEADER: usize = 40;
+
+#[derive(Arbitrary, Debug)]
+struct Message<'a> {
+    opcode: u16,
+    unique: u64,
+    nodeid: u64,
+    payload: &'a [u8],
+}
+
+fuzz_target!(|message: Message| {
+    let len = (SIZE_HEADER + message.payload.len()) as u32;
+
+    let mut data = Vec::with_capacity(len as usize);
+    data.extend_from_slice(&len.to_ne_bytes());
+    data.extend_from_slice(&u32::from(message.opcode).to_ne_bytes());
+    data.extend_from_slice(&message.unique.to_ne_bytes());
+    data.extend_from_slice(&message.nodeid.to_ne_bytes());
+    data.extend_from_slice(&[0u8; 16]); // uid, gid, pid, padding
+    data.extend_from_slice(message.payload);
+
+    let (reply_tx, _reply_rx) = fusion::create_reply_channel();
+    let _ = Request::parse(&data, &reply_tx);
+});
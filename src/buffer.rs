@@ -0,0 +1,110 @@
+//! Recycled read buffers.
+//!
+//! Each worker reads the next request into a buffer leased from a [BufferPool] shared by all workers. Once read, the
+//! buffer is frozen into a [Bytes] so that the payload of a `FUSE_WRITE` can be handed to the filesystem as a view of
+//! the buffer rather than a copy. The buffer returns to the pool when the last view is dropped.
+
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+/// Pool of fixed-size buffers
+#[derive(Clone)]
+pub(crate) struct BufferPool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// Size of every buffer
+    size: usize,
+    /// Maximum number of idle buffers kept. Buffers released while the pool is full are freed.
+    retain: usize,
+    free: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    pub(crate) fn new(size: usize, retain: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                size,
+                retain,
+                free: Mutex::new(Vec::with_capacity(retain)),
+            }),
+        }
+    }
+
+    /// Take an idle buffer, allocating one if there is none.
+    pub(crate) fn acquire(&self) -> Vec<u8> {
+        match self.shared.free.lock().unwrap().pop() {
+            Some(buffer) => buffer,
+            None => vec![0u8; self.shared.size],
+        }
+    }
+
+    /// Share the first `len` bytes of `buffer`. The buffer is released once the last view is dropped.
+    pub(crate) fn freeze(&self, buffer: Vec<u8>, len: usize) -> Bytes {
+        Bytes::from_owner(Lease {
+            buffer,
+            len,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl Shared {
+    fn release(&self, buffer: Vec<u8>) {
+        let mut free = self.free.lock().unwrap();
+
+        if buffer.len() == self.size && free.len() < self.retain {
+            free.push(buffer);
+        }
+    }
+}
+
+/// Owner of a frozen buffer
+struct Lease {
+    buffer: Vec<u8>,
+    len: usize,
+    shared: Arc<Shared>,
+}
+
+impl AsRef<[u8]> for Lease {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.shared.release(std::mem::take(&mut self.buffer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycle() {
+        let pool = BufferPool::new(64, 1);
+
+        let mut buffer = pool.acquire();
+        buffer[..3].copy_from_slice(b"abc");
+        let address = buffer.as_ptr();
+
+        let bytes = pool.freeze(buffer, 3);
+        let view = bytes.slice(1..);
+        drop(bytes);
+        assert_eq!(&view[..], b"bc");
+        assert!(pool.shared.free.lock().unwrap().is_empty());
+
+        drop(view);
+        let buffer = pool.acquire();
+        assert_eq!(buffer.as_ptr(), address);
+
+        // Only `retain` idle buffers are kept
+        pool.shared.release(buffer);
+        pool.shared.release(vec![0u8; 64]);
+        assert_eq!(pool.shared.free.lock().unwrap().len(), 1);
+    }
+}
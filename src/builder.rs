@@ -1,19 +1,28 @@
-use std::{
-    os::{
-        fd::{AsFd, AsRawFd, FromRawFd},
-        unix::fs::FileTypeExt,
-    },
-    path::PathBuf,
-};
+use std::{collections::HashMap, os::fd::OwnedFd, os::unix::fs::FileTypeExt, path::PathBuf, sync::Arc, time::Duration};
+#[cfg(feature = "abi-7-12")]
+use std::{ffi::OsString, fs::OpenOptions, os::unix::fs::OpenOptionsExt};
 
+#[cfg(not(target_os = "linux"))]
+use log::warn;
 use log::{debug, error};
+use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 
+#[cfg(feature = "abi-7-12")]
+use crate::init::CuseConfig;
 use crate::{
+    buffer::BufferPool,
+    device::Device,
     error::Errno,
+    in_flight::InFlight,
+    init::{KernelConfig, SIZE_HEADER_ROOM},
+    messages::fuse_abi::fuse_opcode,
     mount::{mount_options::MountOption, Mount},
+    notify::Notifier,
     session::{Inner, Session},
-    RequestTx, SIZE_BUFFER,
+    trace::{Recorder, RecordingTransport},
+    transport::Transport,
+    RequestTx, SIZE_BUFFER, SIZE_CHANNEL,
 };
 
 pub struct Builder {
@@ -27,6 +36,48 @@ pub struct Builder {
     outbound_fs_request_tx: Option<RequestTx>,
 
     cancellation_token: CancellationToken,
+
+    /// Number of workers reading from (clones of) the device
+    workers: usize,
+
+    /// Parameters for the INIT handshake
+    kernel_config: KernelConfig,
+
+    /// Reply for requests dropped by the filesystem without an answer
+    unanswered_errno: Errno,
+
+    /// How long requests may stay with the filesystem, keyed by opcode
+    deadlines: HashMap<u32, Duration>,
+    /// Reply for requests whose deadline passed
+    deadline_errno: Errno,
+
+    /// Splice requests from the device rather than read them
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    splice_read: bool,
+
+    /// Ask for `FUSE_PASSTHROUGH`
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    passthrough: bool,
+
+    /// Ask for `FUSE_SECURITY_CTX`
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    security_context: bool,
+    /// Ask for `FUSE_CREATE_SUPP_GROUP`
+    #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+    supplementary_group: bool,
+
+    /// Records the traffic with the kernel
+    recorder: Option<Arc<Recorder>>,
+
+    /// Device to register in the `CUSE_INIT` handshake. Set by [CuseBuilder].
+    #[cfg(feature = "abi-7-12")]
+    cuse: Option<CuseConfig>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Builder {
@@ -44,6 +95,22 @@ impl Builder {
             mount_options: default_mount_options,
             outbound_fs_request_tx: None,
             cancellation_token: CancellationToken::new(),
+            workers: 1,
+            kernel_config: KernelConfig::default(),
+            unanswered_errno: Errno::EIO,
+            deadlines: HashMap::new(),
+            deadline_errno: Errno::ETIMEDOUT,
+            #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+            splice_read: false,
+            #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+            passthrough: false,
+            #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+            security_context: false,
+            #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+            supplementary_group: false,
+            recorder: None,
+            #[cfg(feature = "abi-7-12")]
+            cuse: None,
         }
     }
 
@@ -80,6 +147,105 @@ impl Builder {
         self
     }
 
+    /// Number of workers that read requests from the kernel concurrently. Defaults to 1.
+    ///
+    /// Each additional worker clones the device file descriptor with `FUSE_DEV_IOC_CLONE`, owns its own buffer, and
+    /// writes the replies for the requests it read on its own descriptor. The ioctl is Linux only: elsewhere a mounted
+    /// session runs a single worker.
+    pub fn set_workers(&mut self, workers: usize) -> &mut Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Capabilities and limits requested from the kernel during the INIT handshake.
+    ///
+    /// The session answers `FUSE_INIT` itself; the filesystem never sees it. Defaults to [KernelConfig::default].
+    pub fn set_kernel_config(&mut self, config: KernelConfig) -> &mut Self {
+        self.kernel_config = config;
+        self
+    }
+
+    /// Error sent to the kernel for a request the filesystem drops without answering. Defaults to [Errno::EIO].
+    ///
+    /// Without it the process that made the call would block in the kernel until the filesystem is unmounted.
+    /// `FORGET` and `BATCH_FORGET` take no reply and are never answered.
+    pub fn set_unanswered_errno(&mut self, errno: Errno) -> &mut Self {
+        self.unanswered_errno = errno;
+        self
+    }
+
+    /// Answer requests with `opcode` that the filesystem has not answered within `deadline` with the deadline errno
+    /// and cancel their [crate::messages::request::Request::cancellation_token].
+    ///
+    /// The filesystem's reply, if it comes later, is dropped. Requests without a deadline wait as long as it takes.
+    pub fn set_deadline(&mut self, opcode: fuse_opcode, deadline: Duration) -> &mut Self {
+        self.deadlines.insert(opcode as u32, deadline);
+        self
+    }
+
+    /// Error sent to the kernel for a request whose deadline passed. Defaults to [Errno::ETIMEDOUT].
+    pub fn set_deadline_errno(&mut self, errno: Errno) -> &mut Self {
+        self.deadline_errno = errno;
+        self
+    }
+
+    /// Splice requests from the device into a pipe rather than read them. Off by default.
+    ///
+    /// Adds `FUSE_SPLICE_READ` to the INIT flags and takes effect once the kernel agrees to it. The payload of a large
+    /// `FUSE_WRITE` then stays in the pipe and reaches the filesystem as
+    /// [crate::messages::request::Write::pipe] rather than [crate::messages::request::Write::data], so that it can be
+    /// spliced onward into a backing file without being copied through userspace.
+    ///
+    /// Each worker needs a pipe that holds the largest request, [KernelConfig::max_write] plus 4 KiB. Without
+    /// `CAP_SYS_RESOURCE` pipes can't grow past `/proc/sys/fs/pipe-max-size` (1 MiB by default): with a larger
+    /// `max_write` the session logs a warning and keeps reading instead.
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    pub fn set_splice_read(&mut self, splice_read: bool) -> &mut Self {
+        self.splice_read = splice_read;
+        self
+    }
+
+    /// Let the kernel read and write backing files directly. Off by default.
+    ///
+    /// Adds `FUSE_PASSTHROUGH` to the INIT flags. Once the kernel agrees to it, register a backing file with
+    /// [Session::open_backing] and answer `OPEN` or `CREATE` with
+    /// [crate::messages::fuse_abi::fuse_open_out::set_backing_id]. See [KernelConfig::max_stack_depth].
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub fn set_passthrough(&mut self, passthrough: bool) -> &mut Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Have the kernel send the security context of new inodes. Off by default.
+    ///
+    /// Adds `FUSE_SECURITY_CTX` to the INIT flags. Once the kernel agrees to it, it leaves labelling new inodes to the
+    /// filesystem: `CREATE`, `MKDIR`, `MKNOD`, `SYMLINK` and `TMPFILE` carry the context in
+    /// [crate::messages::extension::Extensions::security_context], which the filesystem must store.
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    pub fn set_security_context(&mut self, security_context: bool) -> &mut Self {
+        self.security_context = security_context;
+        self
+    }
+
+    /// Have the kernel send the group of the parent directory when the caller only holds it as a supplementary
+    /// group. Off by default.
+    ///
+    /// Adds `FUSE_CREATE_SUPP_GROUP` to the INIT flags. See
+    /// [crate::messages::extension::Extensions::supplementary_groups].
+    #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+    pub fn set_supplementary_group(&mut self, supplementary_group: bool) -> &mut Self {
+        self.supplementary_group = supplementary_group;
+        self
+    }
+
+    /// Append every message exchanged with the kernel to a trace file. Off by default. See [crate::trace].
+    ///
+    /// Splicing is disabled while recording so that every message passes through `recorder`.
+    pub fn set_recorder(&mut self, recorder: Recorder) -> &mut Self {
+        self.recorder = Some(Arc::new(recorder));
+        self
+    }
+
     pub async fn open(&mut self) -> Result<Session, Errno> {
         debug!("BUILDER OPEN");
         if self.outbound_fs_request_tx.is_none() {
@@ -97,6 +263,27 @@ impl Builder {
             return Err(Errno::EINVAL);
         }
 
+        if self.workers == 0 {
+            error!("at least one worker required");
+            return Err(Errno::EINVAL);
+        }
+
+        self.kernel_config.validate()?;
+
+        // The builder's own config is left untouched so that a later open can ask for less
+        let mut kernel_config = self.kernel_config.clone();
+        self.request_extensions(&mut kernel_config);
+
+        #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+        if self.splice_read {
+            kernel_config.flags |= crate::init::InitFlags::SPLICE_READ;
+        }
+
+        #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+        if self.passthrough {
+            kernel_config.flags |= crate::init::InitFlags::PASSTHROUGH;
+        }
+
         if !tokio::fs::metadata(&self.device_path)
             .await?
             .file_type()
@@ -118,36 +305,271 @@ impl Builder {
 
         let (file, mount) = Mount::new(self.mount_path.as_ref().unwrap(), &self.mount_options)?;
 
-        let writer = unsafe { std::fs::File::from_raw_fd(file.as_fd().as_raw_fd()) };
+        let device = Arc::new(Device::new(OwnedFd::from(file.into_std().await))?);
 
-        let (reply_tx, reply_rx) = crate::create_reply_channel();
+        // Clone the device for the additional workers. Notifications are written on the first worker's descriptor.
+        let mut devices = Vec::with_capacity(self.workers);
+        devices.push(device.clone());
+        #[cfg(target_os = "linux")]
+        for _ in 1..self.workers {
+            devices.push(Arc::new(device.try_clone(&self.device_path)?));
+        }
+        #[cfg(not(target_os = "linux"))]
+        if self.workers > 1 {
+            warn!("the device cannot be cloned on this platform, running a single worker");
+        }
 
-        let mut inner = Inner {
-            _mount: mount,
-            file: Some(file),
-            writer,
-            buffer: vec![0u8; SIZE_BUFFER],
-            cancellation_token: self.cancellation_token.clone(),
-            inbound_fs_reply_tx: reply_tx,
-            inbound_fs_reply_rx: reply_rx,
-            outbound_fs_request_tx: self.outbound_fs_request_tx.as_ref().unwrap().clone(),
-        };
+        let workers = devices
+            .into_iter()
+            .map(|device| Worker {
+                transport: device.clone(),
+                #[cfg(target_os = "linux")]
+                device: Some(device),
+            })
+            .collect();
 
-        let session = Session {
-            cancellation_token: self.cancellation_token.clone(),
-            outbound_fs_request_tx: self.outbound_fs_request_tx.as_ref().unwrap().clone(),
-        };
+        Ok(self.spawn(kernel_config, Some(mount), workers))
+    }
+
+    /// Run the session over `transport` rather than the FUSE device. Nothing is mounted.
+    ///
+    /// The peer of `transport` takes the part of the kernel: it starts with `FUSE_INIT` and receives the replies. All
+    /// workers share `transport`. The device and mount settings are ignored, as are [Builder::set_splice_read] and
+    /// [Builder::set_passthrough].
+    pub async fn open_with_transport(&mut self, transport: Arc<dyn Transport>) -> Result<Session, Errno> {
+        if self.outbound_fs_request_tx.is_none() {
+            error!("outbound fs request channel required");
+            return Err(Errno::EINVAL);
+        }
+
+        if self.workers == 0 {
+            error!("at least one worker required");
+            return Err(Errno::EINVAL);
+        }
+
+        self.kernel_config.validate()?;
+
+        let mut kernel_config = self.kernel_config.clone();
+        self.request_extensions(&mut kernel_config);
+
+        let workers = (0..self.workers)
+            .map(|_| Worker {
+                transport: transport.clone(),
+                #[cfg(target_os = "linux")]
+                device: None,
+            })
+            .collect();
+
+        Ok(self.spawn(kernel_config, None, workers))
+    }
+
+    /// Add the INIT flags for the request extensions asked for to `kernel_config`.
+    #[allow(unused_variables)]
+    fn request_extensions(&self, kernel_config: &mut KernelConfig) {
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        if self.security_context {
+            kernel_config.flags |= crate::init::InitFlags::SECURITY_CTX;
+        }
 
-        // Start the actor
-        tokio::spawn(async move {
-            match inner.run().await {
-                Err(e) => {
-                    error!("session failed with {:?}", e);
+        #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+        if self.supplementary_group {
+            kernel_config.flags |= crate::init::InitFlags::CREATE_SUPP_GROUP;
+        }
+    }
+
+    /// Start one [Inner] per worker negotiating with `kernel_config`. The workers share `mount`, which is unmounted
+    /// once the last of them stops.
+    fn spawn(&self, kernel_config: KernelConfig, mount: Option<Mount>, mut workers: Vec<Worker>) -> Session {
+        // Backing files are registered on the device even while recording
+        #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+        let device = workers[0].device.clone();
+
+        if let Some(recorder) = &self.recorder {
+            for worker in workers.iter_mut() {
+                worker.transport = Arc::new(RecordingTransport {
+                    inner: worker.transport.clone(),
+                    recorder: recorder.clone(),
+                });
+                #[cfg(target_os = "linux")]
+                {
+                    worker.device = None;
                 }
-                Ok(_) => {}
             }
-        });
+        }
+
+        // Notifications are sent on the first worker's transport
+        let notifier = Notifier::new(workers[0].transport.clone());
+
+        let (connection_info_tx, connection_info_rx) = watch::channel(None);
+        let connection_info_tx = Arc::new(connection_info_tx);
+        let in_flight = Arc::new(InFlight::default());
+        let mount = mount.map(Arc::new);
+
+        // Enough idle buffers for every worker and a full request channel
+        let pool = BufferPool::new(
+            kernel_config.max_write as usize + SIZE_HEADER_ROOM,
+            self.workers + SIZE_CHANNEL,
+        );
+
+        // The remaining workers share the first worker's cancellation token
+        for (index, worker) in workers.into_iter().enumerate() {
+            let (reply_tx, reply_rx) = crate::create_reply_channel();
+
+            let mut inner = Inner {
+                _mount: mount.clone(),
+                transport: worker.transport,
+                #[cfg(target_os = "linux")]
+                device: worker.device,
+                notifier: notifier.clone(),
+                kernel_config: kernel_config.clone(),
+                #[cfg(feature = "abi-7-12")]
+                cuse: self.cuse.clone(),
+                connection_info: connection_info_tx.clone(),
+                in_flight: in_flight.clone(),
+                unanswered_errno: self.unanswered_errno,
+                deadlines: self.deadlines.clone(),
+                deadline_errno: self.deadline_errno,
+                read_buffer: pool.acquire(),
+                pool: pool.clone(),
+                buffer: vec![0u8; SIZE_BUFFER],
+                #[cfg(target_os = "linux")]
+                pipe: None,
+                #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+                splice_read: self.splice_read,
+                #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+                receive_pipe: None,
+                cancellation_token: self.cancellation_token.clone(),
+                inbound_fs_reply_tx: reply_tx,
+                inbound_fs_reply_rx: reply_rx,
+                outbound_fs_request_tx: self.outbound_fs_request_tx.as_ref().unwrap().clone(),
+            };
+
+            // Start the actor
+            tokio::spawn(async move {
+                if let Err(e) = inner.run().await {
+                    error!("worker {} failed with {:?}", index, e);
+                }
+            });
+        }
+
+        Session {
+            cancellation_token: self.cancellation_token.clone(),
+            outbound_fs_request_tx: self.outbound_fs_request_tx.as_ref().unwrap().clone(),
+            notifier,
+            connection_info: connection_info_rx,
+            #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+            device,
+        }
+    }
+}
+
+/// Builds a session that serves a character device through CUSE rather than a mounted filesystem.
+///
+/// Opens `/dev/cuse`. Once the session has answered `CUSE_INIT`, the kernel creates `/dev/<name>` and forwards the
+/// calls made on it as requests, as it does for a filesystem: mostly `OPEN`, `READ`, `WRITE`, `IOCTL`, `POLL`,
+/// `FLUSH` and `RELEASE`. The device goes away when the session stops.
+///
+/// Settings shared with filesystem sessions, such as the request channel, deadlines and the recorder, are made on
+/// [CuseBuilder::builder].
+#[cfg(feature = "abi-7-12")]
+pub struct CuseBuilder {
+    builder: Builder,
+    config: CuseConfig,
+}
+
+#[cfg(feature = "abi-7-12")]
+impl CuseBuilder {
+    /// Serve a device named `name`, created as `/dev/<name>`
+    pub fn new(name: impl Into<OsString>) -> Self {
+        let mut builder = Builder::new();
+        builder.device_path = PathBuf::from("/dev/cuse");
+
+        Self {
+            builder,
+            config: CuseConfig::new(name),
+        }
+    }
+
+    /// Settings shared with filesystem sessions. [Builder::set_outbound_fs_request_tx] is required.
+    ///
+    /// The device path defaults to `/dev/cuse`. The largest write is [KernelConfig::max_write]. Mount settings,
+    /// splicing and passthrough are ignored, and the session runs a single worker since `/dev/cuse` can't be cloned.
+    pub fn builder(&mut self) -> &mut Builder {
+        &mut self.builder
+    }
+
+    /// Major and minor number of the device. By default the kernel allocates a major and the minor is 0.
+    pub fn set_dev_number(&mut self, major: u32, minor: u32) -> &mut Self {
+        self.config.dev_major = major;
+        self.config.dev_minor = minor;
+        self
+    }
+
+    /// Ask for `CUSE_UNRESTRICTED_IOCTL`. Off by default. See [CuseConfig::unrestricted_ioctl].
+    pub fn set_unrestricted_ioctl(&mut self, unrestricted_ioctl: bool) -> &mut Self {
+        self.config.unrestricted_ioctl = unrestricted_ioctl;
+        self
+    }
 
-        Ok(session)
+    /// Maximum size of a single read. Defaults to 128 KiB.
+    pub fn set_max_read(&mut self, max_read: u32) -> &mut Self {
+        self.config.max_read = max_read;
+        self
     }
+
+    pub async fn open(&mut self) -> Result<Session, Errno> {
+        if self.builder.outbound_fs_request_tx.is_none() {
+            error!("outbound fs request channel required");
+            return Err(Errno::EINVAL);
+        }
+
+        self.config.validate()?;
+        self.builder.kernel_config.validate()?;
+
+        let path = self.builder.device_path.clone();
+        let file = tokio::task::spawn_blocking(move || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_CLOEXEC)
+                .open(&path)
+        })
+        .await
+        .map_err(|e| {
+            error!("cannot open {:?}: {}", self.builder.device_path, e);
+            Errno::EIO
+        })?
+        .map_err(|e| {
+            error!("cannot open {:?}: {}", self.builder.device_path, e);
+            Errno::from(e)
+        })?;
+        let device = Arc::new(Device::new(OwnedFd::from(file))?);
+
+        let workers = vec![Worker {
+            transport: device.clone(),
+            #[cfg(target_os = "linux")]
+            device: Some(device),
+        }];
+
+        self.builder.workers = 1;
+        self.builder.cuse = Some(self.config.clone());
+        Ok(self.builder.spawn(self.builder.kernel_config.clone(), None, workers))
+    }
+
+    /// Run the session over `transport` rather than `/dev/cuse`. See [Builder::open_with_transport].
+    pub async fn open_with_transport(&mut self, transport: Arc<dyn Transport>) -> Result<Session, Errno> {
+        self.config.validate()?;
+
+        self.builder.workers = 1;
+        self.builder.cuse = Some(self.config.clone());
+        self.builder.open_with_transport(transport).await
+    }
+}
+
+/// What a single worker talks to the kernel over
+struct Worker {
+    transport: Arc<dyn Transport>,
+    /// Set when [Worker::transport] is the device
+    #[cfg(target_os = "linux")]
+    device: Option<Arc<Device>>,
 }
@@ -33,6 +33,12 @@ pub const FOPEN_NONSEEKABLE: u32 = 1 << 2; // the file is not seekable
 pub const FOPEN_CACHE_DIR: u32 = 1 << 3; // allow caching this directory
 #[cfg(feature = "abi-7-31")]
 pub const FOPEN_STREAM: u32 = 1 << 4; // the file is stream-like (no file position at all)
+#[cfg(feature = "abi-7-35")]
+pub const FOPEN_NOFLUSH: u32 = 1 << 5; // don't flush data cache on close (unless FUSE_WRITEBACK_CACHE)
+#[cfg(feature = "abi-7-37")]
+pub const FOPEN_PARALLEL_DIRECT_WRITES: u32 = 1 << 6; // allow concurrent direct writes on the same inode
+#[cfg(feature = "abi-7-40")]
+pub const FOPEN_PASSTHROUGH: u32 = 1 << 7; // passthrough read/write io for this open file
 
 #[cfg(target_os = "macos")]
 pub const FOPEN_PURGE_ATTR: u32 = 1 << 30;
@@ -91,6 +97,36 @@ pub const FUSE_CACHE_SYMLINKS: u32 = 1 << 23; // cache READLINK responses
 pub const FUSE_NO_OPENDIR_SUPPORT: u32 = 1 << 24; // kernel supports zero-message opendir
 #[cfg(feature = "abi-7-30")]
 pub const FUSE_EXPLICIT_INVAL_DATA: u32 = 1 << 25; // only invalidate cached pages on explicit request
+#[cfg(all(feature = "abi-7-31", not(target_os = "macos")))]
+pub const FUSE_MAP_ALIGNMENT: u32 = 1 << 26; // init_out.map_alignment contains log2(byte alignment) for DAX
+#[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+pub const FUSE_SUBMOUNTS: u32 = 1 << 27; // kernel supports auto-mounting directory submounts
+#[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+pub const FUSE_HANDLE_KILLPRIV_V2: u32 = 1 << 28; // fs kills suid/sgid/cap on write/chown/trunc
+#[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+pub const FUSE_SETXATTR_EXT: u32 = 1 << 29; // server supports extended struct fuse_setxattr_in
+#[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+pub const FUSE_INIT_EXT: u32 = 1 << 30; // extended fuse_init_in and fuse_init_out: flags2 holds the upper half
+#[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+pub const FUSE_INIT_RESERVED: u32 = 1 << 31; // reserved, do not use
+
+// Init request/reply flags carried in flags2, as bits of the combined 64 bit flags
+#[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+pub const FUSE_SECURITY_CTX: u64 = 1 << 32; // add security context to create, mkdir, symlink, and mknod
+#[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+pub const FUSE_HAS_INODE_DAX: u64 = 1 << 33; // use per inode DAX
+#[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+pub const FUSE_CREATE_SUPP_GROUP: u64 = 1 << 34; // add supplementary group info to create, mkdir, symlink and mknod
+#[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+pub const FUSE_HAS_EXPIRE_ONLY: u64 = 1 << 35; // kernel supports expiry-only entry invalidation
+#[cfg(all(feature = "abi-7-39", not(target_os = "macos")))]
+pub const FUSE_DIRECT_IO_ALLOW_MMAP: u64 = 1 << 36; // allow shared mmap in FOPEN_DIRECT_IO mode
+#[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+pub const FUSE_PASSTHROUGH: u64 = 1 << 37; // passthrough mode for read/write io
+#[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+pub const FUSE_NO_EXPORT_SUPPORT: u64 = 1 << 38; // explicitly disable export support
+#[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+pub const FUSE_HAS_RESEND: u64 = 1 << 39; // kernel supports resending pending requests
 
 #[cfg(target_os = "macos")]
 pub const FUSE_ALLOCATE: u32 = 1 << 27;
@@ -157,3 +193,7 @@ pub const FUSE_FSYNC_FDATASYNC: u32 = 1 << 0; // Sync data only, not metadata
 
 // The read buffer is required to be at least 8k, but may be much larger
 pub const FUSE_MIN_READ_BUFFER: usize = 8192;
+
+// Device ioctls
+#[cfg(target_os = "linux")]
+pub const FUSE_DEV_IOC_MAGIC: u8 = 229;
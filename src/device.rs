@@ -0,0 +1,165 @@
+//! Non-blocking access to the FUSE device.
+//!
+//! The device file descriptor is switched to `O_NONBLOCK` and registered with the tokio reactor through
+//! [AsyncFd] so that neither reads nor writes tie up a runtime (or blocking pool) thread.
+
+#[cfg(target_os = "linux")]
+use std::{fs::OpenOptions, os::fd::BorrowedFd, os::unix::fs::OpenOptionsExt, path::Path};
+use std::{
+    io::{self, IoSlice},
+    os::fd::{AsFd, AsRawFd, OwnedFd},
+};
+
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use tokio::io::unix::AsyncFd;
+
+#[cfg(target_os = "linux")]
+use crate::constants::FUSE_DEV_IOC_MAGIC;
+#[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+use crate::messages::fuse_abi::fuse_backing_map;
+
+#[cfg(target_os = "linux")]
+nix::ioctl_read!(fuse_dev_ioc_clone, FUSE_DEV_IOC_MAGIC, 0, u32);
+#[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+nix::ioctl_write_ptr!(fuse_dev_ioc_backing_open, FUSE_DEV_IOC_MAGIC, 1, fuse_backing_map);
+#[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+nix::ioctl_write_ptr!(fuse_dev_ioc_backing_close, FUSE_DEV_IOC_MAGIC, 2, u32);
+
+/// Duplex handle on an open `/dev/fuse` file descriptor, or a socket standing in for it (see [crate::transport])
+pub(crate) struct Device {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl Device {
+    /// Take ownership of `fd`, switch it to non-blocking mode and register it with the reactor.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub(crate) fn new(fd: OwnedFd) -> io::Result<Self> {
+        let flags = OFlag::from_bits_truncate(fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL)?);
+        fcntl(fd.as_raw_fd(), FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+
+        Ok(Self { fd: AsyncFd::new(fd)? })
+    }
+
+    /// Open a new file descriptor on the device at `path` and attach it to the same connection as `self`.
+    ///
+    /// Uses the `FUSE_DEV_IOC_CLONE` ioctl. Requests read from the clone must be answered on the clone.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn try_clone(&self, path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(path)?;
+
+        let mut session_fd = self.fd.as_raw_fd() as u32;
+        unsafe { fuse_dev_ioc_clone(file.as_raw_fd(), &mut session_fd) }?;
+
+        Self::new(OwnedFd::from(file))
+    }
+
+    /// Register `fd` as a backing file with the `FUSE_DEV_IOC_BACKING_OPEN` ioctl and return its id.
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub(crate) fn backing_open(&self, fd: BorrowedFd<'_>) -> io::Result<i32> {
+        let map = fuse_backing_map {
+            fd: fd.as_raw_fd(),
+            flags: 0,
+            padding: 0,
+        };
+
+        Ok(unsafe { fuse_dev_ioc_backing_open(self.fd.as_raw_fd(), &map) }?)
+    }
+
+    /// Drop the backing file registered as `backing_id` with the `FUSE_DEV_IOC_BACKING_CLOSE` ioctl.
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub(crate) fn backing_close(&self, backing_id: i32) -> io::Result<()> {
+        let backing_id = backing_id as u32;
+        unsafe { fuse_dev_ioc_backing_close(self.fd.as_raw_fd(), &backing_id) }?;
+
+        Ok(())
+    }
+
+    /// Read a single kernel message into `buffer`, waiting for the device to become readable.
+    pub(crate) async fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+
+            match guard.try_io(|fd| Ok(nix::unistd::read(fd.as_raw_fd(), buffer)?)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write a single message from `buffer`, waiting for the device to become writable.
+    pub(crate) async fn write(&self, buffer: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.writable().await?;
+
+            match guard.try_io(|fd| Ok(nix::unistd::write(fd.get_ref().as_fd(), buffer)?)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Move a single kernel message of at most `len` bytes into the pipe `pipe` with `splice(2)`.
+    #[cfg(target_os = "linux")]
+    pub(crate) async fn splice_to(&self, pipe: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+        use nix::fcntl::{splice, SpliceFFlags};
+
+        loop {
+            let mut guard = self.fd.readable().await?;
+
+            match guard.try_io(|fd| {
+                Ok(splice(
+                    fd.as_raw_fd(),
+                    None,
+                    pipe.as_raw_fd(),
+                    None,
+                    len,
+                    SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+                )?)
+            }) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write a single message gathered from `buffers`.
+    pub(crate) async fn write_vectored(&self, buffers: &[IoSlice<'_>]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.writable().await?;
+
+            match guard.try_io(|fd| Ok(nix::sys::uio::writev(fd.get_ref().as_fd(), buffers)?)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write a single message of `len` bytes held in the pipe `pipe` with `splice(2)`.
+    #[cfg(target_os = "linux")]
+    pub(crate) async fn splice_from(&self, pipe: BorrowedFd<'_>, len: usize) -> io::Result<usize> {
+        use nix::fcntl::{splice, SpliceFFlags};
+
+        loop {
+            let mut guard = self.fd.writable().await?;
+
+            match guard.try_io(|fd| {
+                Ok(splice(
+                    pipe.as_raw_fd(),
+                    None,
+                    fd.as_raw_fd(),
+                    None,
+                    len,
+                    SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+                )?)
+            }) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
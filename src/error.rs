@@ -15,7 +15,7 @@ macro_rules! errno {
 }
 
 /// Represents an error code to be returned to the caller
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Errno(pub NonZeroI32);
 impl Errno {
     /// Operation not permitted
@@ -237,3 +237,61 @@ impl Display for Errno {
         write!(f, "{}", self.0)
     }
 }
+
+/// Why a message read from the device could not be decoded into a [crate::messages::request::Request]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Raw opcode from the header. `0` if the header itself is incomplete.
+    pub opcode: u32,
+    /// Unique id from the header. `0` if the header itself is incomplete.
+    pub unique: u64,
+    pub kind: ParseErrorKind,
+}
+
+/// What went wrong while decoding a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Fewer bytes than a `fuse_in_header`
+    ShortHeader { read: usize },
+    /// `fuse_in_header.len` does not match the number of bytes read
+    LengthMismatch { header: u32, read: usize },
+    /// Opcode unknown to (or not enabled in) this build
+    UnknownOpcode,
+    /// The message ends before the named argument does
+    Truncated { what: &'static str },
+    /// The named string is not NUL terminated
+    MissingNul { what: &'static str },
+}
+
+impl ParseError {
+    /// Error to answer the request with: [Errno::ENOSYS] for unknown opcodes, [Errno::EIO] otherwise.
+    pub fn errno(&self) -> Errno {
+        match self.kind {
+            ParseErrorKind::UnknownOpcode => Errno::ENOSYS,
+            _ => Errno::EIO,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "opcode {} unique {}: ", self.opcode, self.unique)?;
+        match &self.kind {
+            ParseErrorKind::ShortHeader { read } => write!(f, "short header ({read} bytes)"),
+            ParseErrorKind::LengthMismatch { header, read } => {
+                write!(f, "header length {header} does not match {read} bytes read")
+            }
+            ParseErrorKind::UnknownOpcode => write!(f, "unknown opcode"),
+            ParseErrorKind::Truncated { what } => write!(f, "truncated {what}"),
+            ParseErrorKind::MissingNul { what } => write!(f, "{what} is not NUL terminated"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Errno {
+    fn from(err: ParseError) -> Self {
+        err.errno()
+    }
+}
@@ -0,0 +1,243 @@
+//! Table of requests handed to the filesystem and not yet answered.
+//!
+//! Each entry carries the [CancellationToken] attached to the [crate::messages::request::Request]. An incoming
+//! `FUSE_INTERRUPT` cancels the token of its target. When the target is not in the table (it has either been answered
+//! already or has not been read yet) the interrupt is held as pending; if the target shows up before the requeue delay
+//! runs out it starts out cancelled, otherwise the session answers the interrupt with `EAGAIN`.
+//!
+//! Requests with a deadline (see [crate::builder::Builder::set_deadline]) that are still in the table when it passes
+//! are answered by the session. Their `unique` is remembered so that the filesystem's late reply can be dropped,
+//! until the reply turns up, the kernel reuses the `unique` or [TIMED_OUT_EXPIRY] passes.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio_util::sync::CancellationToken;
+
+/// How long an interrupt for an unknown request is held before answering it with `EAGAIN`
+pub(crate) const INTERRUPT_REQUEUE_DELAY: Duration = Duration::from_millis(10);
+
+/// How long the late reply to a request answered on its deadline is waited for, so that a filesystem that never
+/// answers does not grow the table for the life of the mount
+pub(crate) const TIMED_OUT_EXPIRY: Duration = Duration::from_secs(600);
+
+/// Shared by all workers of a session
+#[derive(Default)]
+pub(crate) struct InFlight {
+    inner: Mutex<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    /// Outstanding requests keyed by `unique`
+    requests: HashMap<u64, Entry>,
+    /// Interrupts whose target was unknown keyed by the target's `unique`. Cancelling the token aborts the `EAGAIN`.
+    interrupts: HashMap<u64, CancellationToken>,
+    /// Requests answered by the session when their deadline passed, until the filesystem's reply turns up. Keyed by
+    /// `unique`, with the time the entry expires.
+    timed_out: HashMap<u64, Instant>,
+}
+
+struct Entry {
+    token: CancellationToken,
+    /// Room the kernel has for the reply. See [crate::messages::request::Operation::reply_size].
+    reply_size: Option<u32>,
+    /// Cancelled once the request is answered, set while a deadline is being watched
+    done: Option<CancellationToken>,
+}
+
+/// Outcome of [InFlight::complete]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Completion {
+    /// Write the reply within the room the kernel has for it
+    Reply(Option<u32>),
+    /// The session already answered the request when its deadline passed. Drop the reply.
+    Late,
+}
+
+impl InFlight {
+    /// Record a request about to be handed to the filesystem and return its token.
+    ///
+    /// The token is already cancelled if an interrupt for `unique` arrived first.
+    pub(crate) fn register(&self, unique: u64, reply_size: Option<u32>) -> CancellationToken {
+        let mut tables = self.inner.lock().unwrap();
+        let token = CancellationToken::new();
+
+        if let Some(pending) = tables.interrupts.remove(&unique) {
+            pending.cancel();
+            token.cancel();
+        }
+
+        // The kernel moved on from a request that timed out and reused its `unique`
+        tables.timed_out.remove(&unique);
+
+        tables.requests.insert(
+            unique,
+            Entry {
+                token: token.clone(),
+                reply_size,
+                done: None,
+            },
+        );
+        token
+    }
+
+    /// Forget a request once the filesystem has answered it.
+    pub(crate) fn complete(&self, unique: u64) -> Completion {
+        let mut tables = self.inner.lock().unwrap();
+
+        if tables.timed_out.remove(&unique).is_some() {
+            return Completion::Late;
+        }
+
+        match tables.requests.remove(&unique) {
+            Some(entry) => {
+                if let Some(done) = entry.done {
+                    done.cancel();
+                }
+                Completion::Reply(entry.reply_size)
+            }
+            None => Completion::Reply(None),
+        }
+    }
+
+    /// Start watching the deadline of request `unique`. The returned token is cancelled once the request is answered.
+    pub(crate) fn deadline(&self, unique: u64) -> Option<CancellationToken> {
+        let mut tables = self.inner.lock().unwrap();
+        let entry = tables.requests.get_mut(&unique)?;
+
+        Some(entry.done.get_or_insert_with(CancellationToken::new).clone())
+    }
+
+    /// Take over request `unique` when its deadline passes and cancel its token.
+    ///
+    /// Returns `false` if the request was answered in the meantime. Otherwise the caller answers it and the
+    /// filesystem's reply is dropped by [InFlight::complete].
+    pub(crate) fn time_out(&self, unique: u64) -> bool {
+        self.time_out_at(unique, Instant::now())
+    }
+
+    fn time_out_at(&self, unique: u64, now: Instant) -> bool {
+        let mut tables = self.inner.lock().unwrap();
+
+        tables.timed_out.retain(|_, expiry| *expiry > now);
+
+        match tables.requests.remove(&unique) {
+            Some(entry) => {
+                entry.token.cancel();
+                tables.timed_out.insert(unique, now + TIMED_OUT_EXPIRY);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel the request `unique`.
+    ///
+    /// Returns `None` if the request was found. Otherwise the interrupt is recorded as pending and the returned token
+    /// is cancelled if the target turns up before [InFlight::expire] is called.
+    pub(crate) fn interrupt(&self, unique: u64) -> Option<CancellationToken> {
+        let mut tables = self.inner.lock().unwrap();
+
+        if let Some(entry) = tables.requests.get(&unique) {
+            entry.token.cancel();
+            return None;
+        }
+
+        let pending = CancellationToken::new();
+        if let Some(previous) = tables.interrupts.insert(unique, pending.clone()) {
+            previous.cancel();
+        }
+        Some(pending)
+    }
+
+    /// Drop a pending interrupt whose requeue delay ran out.
+    ///
+    /// Returns `false` if the target turned up (or a newer interrupt replaced it) in the meantime.
+    pub(crate) fn expire(&self, unique: u64, pending: &CancellationToken) -> bool {
+        let mut tables = self.inner.lock().unwrap();
+
+        if pending.is_cancelled() {
+            return false;
+        }
+
+        tables.interrupts.remove(&unique);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_known_request() {
+        let in_flight = InFlight::default();
+        let token = in_flight.register(10, None);
+
+        assert!(in_flight.interrupt(10).is_none());
+        assert!(token.is_cancelled());
+
+        in_flight.complete(10);
+        assert!(in_flight.interrupt(10).is_some());
+    }
+
+    #[test]
+    fn interrupt_before_request() {
+        let in_flight = InFlight::default();
+
+        let pending = in_flight.interrupt(12).unwrap();
+        let token = in_flight.register(12, None);
+
+        assert!(token.is_cancelled());
+        assert!(pending.is_cancelled());
+        assert!(!in_flight.expire(12, &pending));
+    }
+
+    #[test]
+    fn interrupt_expires() {
+        let in_flight = InFlight::default();
+
+        let pending = in_flight.interrupt(14).unwrap();
+        assert!(in_flight.expire(14, &pending));
+        assert!(!in_flight.register(14, None).is_cancelled());
+    }
+
+    #[test]
+    fn deadline() {
+        let in_flight = InFlight::default();
+
+        let token = in_flight.register(16, Some(4096));
+        let done = in_flight.deadline(16).unwrap();
+        assert!(in_flight.time_out(16));
+        assert!(token.is_cancelled());
+        assert!(!done.is_cancelled());
+        assert_eq!(in_flight.complete(16), Completion::Late);
+
+        in_flight.register(18, Some(4096));
+        let done = in_flight.deadline(18).unwrap();
+        assert_eq!(in_flight.complete(18), Completion::Reply(Some(4096)));
+        assert!(done.is_cancelled());
+        assert!(!in_flight.time_out(18));
+    }
+
+    #[test]
+    fn timed_out_forgotten() {
+        let in_flight = InFlight::default();
+        let now = Instant::now();
+
+        // Never answered
+        in_flight.register(20, None);
+        assert!(in_flight.time_out_at(20, now));
+        in_flight.register(22, None);
+        assert!(in_flight.time_out_at(22, now + TIMED_OUT_EXPIRY));
+        assert_eq!(in_flight.inner.lock().unwrap().timed_out.len(), 1);
+
+        // Reused by the kernel
+        in_flight.register(22, None);
+        assert_eq!(in_flight.complete(22), Completion::Reply(None));
+    }
+}
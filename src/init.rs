@@ -0,0 +1,577 @@
+//! INIT handshake.
+//!
+//! The session answers the kernel's `FUSE_INIT` itself from the [KernelConfig] set on [crate::builder::Builder].
+//! The outcome of the negotiation is published as a [ConnectionInfo], available from
+//! [crate::session::Session::connection_info].
+//!
+//! A CUSE session answers `CUSE_INIT` instead, registering the character device described by a [CuseConfig].
+
+#[cfg(feature = "abi-7-12")]
+use std::{ffi::OsString, os::unix::ffi::OsStrExt};
+
+use bitflags::bitflags;
+use log::{error, info, warn};
+use zerocopy::FromZeros;
+
+use crate::{constants::*, error::Errno, messages::fuse_abi::*, supported_init_flags, SIZE_BUFFER};
+
+/// Room reserved in the read buffer for the request header and the `WRITE` arguments
+pub(crate) const SIZE_HEADER_ROOM: usize = 4096;
+
+bitflags! {
+    /// Capability flags of the INIT handshake. See the init flags in [crate::constants].
+    ///
+    /// The lower half travels in `flags`. From 7.36 the upper half travels in `flags2`, which either side only reads
+    /// when `INIT_EXT` is set.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct InitFlags: u64 {
+        const ASYNC_READ = FUSE_ASYNC_READ as u64;
+        const POSIX_LOCKS = FUSE_POSIX_LOCKS as u64;
+        #[cfg(feature = "abi-7-9")]
+        const FILE_OPS = FUSE_FILE_OPS as u64;
+        #[cfg(feature = "abi-7-9")]
+        const ATOMIC_O_TRUNC = FUSE_ATOMIC_O_TRUNC as u64;
+        #[cfg(feature = "abi-7-10")]
+        const EXPORT_SUPPORT = FUSE_EXPORT_SUPPORT as u64;
+        #[cfg(feature = "abi-7-9")]
+        const BIG_WRITES = FUSE_BIG_WRITES as u64;
+        #[cfg(feature = "abi-7-12")]
+        const DONT_MASK = FUSE_DONT_MASK as u64;
+        #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
+        const SPLICE_WRITE = FUSE_SPLICE_WRITE as u64;
+        #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
+        const SPLICE_MOVE = FUSE_SPLICE_MOVE as u64;
+        #[cfg(all(feature = "abi-7-14", not(target_os = "macos")))]
+        const SPLICE_READ = FUSE_SPLICE_READ as u64;
+        #[cfg(feature = "abi-7-17")]
+        const FLOCK_LOCKS = FUSE_FLOCK_LOCKS as u64;
+        #[cfg(feature = "abi-7-18")]
+        const HAS_IOCTL_DIR = FUSE_HAS_IOCTL_DIR as u64;
+        #[cfg(feature = "abi-7-20")]
+        const AUTO_INVAL_DATA = FUSE_AUTO_INVAL_DATA as u64;
+        #[cfg(feature = "abi-7-21")]
+        const DO_READDIRPLUS = FUSE_DO_READDIRPLUS as u64;
+        #[cfg(feature = "abi-7-21")]
+        const READDIRPLUS_AUTO = FUSE_READDIRPLUS_AUTO as u64;
+        #[cfg(feature = "abi-7-22")]
+        const ASYNC_DIO = FUSE_ASYNC_DIO as u64;
+        #[cfg(feature = "abi-7-23")]
+        const WRITEBACK_CACHE = FUSE_WRITEBACK_CACHE as u64;
+        #[cfg(feature = "abi-7-23")]
+        const NO_OPEN_SUPPORT = FUSE_NO_OPEN_SUPPORT as u64;
+        #[cfg(feature = "abi-7-25")]
+        const PARALLEL_DIROPS = FUSE_PARALLEL_DIROPS as u64;
+        #[cfg(feature = "abi-7-26")]
+        const HANDLE_KILLPRIV = FUSE_HANDLE_KILLPRIV as u64;
+        #[cfg(feature = "abi-7-26")]
+        const POSIX_ACL = FUSE_POSIX_ACL as u64;
+        #[cfg(feature = "abi-7-27")]
+        const ABORT_ERROR = FUSE_ABORT_ERROR as u64;
+        #[cfg(feature = "abi-7-28")]
+        const MAX_PAGES = FUSE_MAX_PAGES as u64;
+        #[cfg(feature = "abi-7-28")]
+        const CACHE_SYMLINKS = FUSE_CACHE_SYMLINKS as u64;
+        #[cfg(feature = "abi-7-29")]
+        const NO_OPENDIR_SUPPORT = FUSE_NO_OPENDIR_SUPPORT as u64;
+        #[cfg(feature = "abi-7-30")]
+        const EXPLICIT_INVAL_DATA = FUSE_EXPLICIT_INVAL_DATA as u64;
+        #[cfg(all(feature = "abi-7-31", not(target_os = "macos")))]
+        const MAP_ALIGNMENT = FUSE_MAP_ALIGNMENT as u64;
+        #[cfg(all(feature = "abi-7-32", not(target_os = "macos")))]
+        const SUBMOUNTS = FUSE_SUBMOUNTS as u64;
+        #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+        const HANDLE_KILLPRIV_V2 = FUSE_HANDLE_KILLPRIV_V2 as u64;
+        #[cfg(all(feature = "abi-7-33", not(target_os = "macos")))]
+        const SETXATTR_EXT = FUSE_SETXATTR_EXT as u64;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        const INIT_EXT = FUSE_INIT_EXT as u64;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        const INIT_RESERVED = FUSE_INIT_RESERVED as u64;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        const SECURITY_CTX = FUSE_SECURITY_CTX;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        const HAS_INODE_DAX = FUSE_HAS_INODE_DAX;
+        #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+        const CREATE_SUPP_GROUP = FUSE_CREATE_SUPP_GROUP;
+        #[cfg(all(feature = "abi-7-38", not(target_os = "macos")))]
+        const HAS_EXPIRE_ONLY = FUSE_HAS_EXPIRE_ONLY;
+        #[cfg(all(feature = "abi-7-39", not(target_os = "macos")))]
+        const DIRECT_IO_ALLOW_MMAP = FUSE_DIRECT_IO_ALLOW_MMAP;
+        #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+        const PASSTHROUGH = FUSE_PASSTHROUGH;
+        #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+        const NO_EXPORT_SUPPORT = FUSE_NO_EXPORT_SUPPORT;
+        #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+        const HAS_RESEND = FUSE_HAS_RESEND;
+        #[cfg(target_os = "macos")]
+        const ALLOCATE = FUSE_ALLOCATE as u64;
+        #[cfg(target_os = "macos")]
+        const EXCHANGE_DATA = FUSE_EXCHANGE_DATA as u64;
+        #[cfg(target_os = "macos")]
+        const CASE_INSENSITIVE = FUSE_CASE_INSENSITIVE as u64;
+        #[cfg(target_os = "macos")]
+        const VOL_RENAME = FUSE_VOL_RENAME as u64;
+        #[cfg(target_os = "macos")]
+        const XTIMES = FUSE_XTIMES as u64;
+    }
+}
+
+impl InitFlags {
+    /// Flags offered by the kernel in `arg`
+    pub fn from_init_in(arg: &fuse_init_in) -> Self {
+        #[allow(unused_mut)]
+        let mut flags = arg.flags as u64;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        if arg.flags & FUSE_INIT_EXT != 0 {
+            flags |= (arg.flags2 as u64) << 32;
+        }
+        Self::from_bits_retain(flags)
+    }
+
+    /// Flags agreed in the INIT reply `arg`
+    pub fn from_init_out(arg: &fuse_init_out) -> Self {
+        #[allow(unused_mut)]
+        let mut flags = arg.flags as u64;
+        #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+        if arg.flags & FUSE_INIT_EXT != 0 {
+            flags |= (arg.flags2 as u64) << 32;
+        }
+        Self::from_bits_retain(flags)
+    }
+
+    /// `flags` and `flags2` of an INIT message
+    pub fn split(self) -> (u32, u32) {
+        (self.bits() as u32, (self.bits() >> 32) as u32)
+    }
+}
+
+/// Capabilities and limits the filesystem asks for during the INIT handshake
+///
+/// Flags the kernel does not offer are dropped. The limits are sent as they are; the kernel clamps them where it needs
+/// to.
+#[derive(Debug, Clone)]
+pub struct KernelConfig {
+    /// Requested capability flags. Defaults to [supported_init_flags]. Flags beyond the lower 32 bits are only sent
+    /// with [InitFlags::INIT_EXT].
+    pub flags: InitFlags,
+    /// Maximum size of a single write. Requests are read into buffers of `max_write` plus [SIZE_HEADER_ROOM] bytes,
+    /// which must not exceed [SIZE_BUFFER].
+    pub max_write: u32,
+    /// Maximum number of pages in a single request. Only sent when `FUSE_MAX_PAGES` is agreed.
+    pub max_pages: u16,
+    /// Maximum number of outstanding background requests
+    pub max_background: u16,
+    /// Number of outstanding background requests at which the kernel considers the filesystem congested
+    pub congestion_threshold: u16,
+    /// Timestamp granularity in nanoseconds. Must be a power of ten between 1 and 1,000,000,000.
+    pub time_gran: u32,
+    /// How deeply backing files may themselves be stacked on other filesystems, at least 1. Only sent when
+    /// `FUSE_PASSTHROUGH` is agreed; the kernel refuses passthrough if it exceeds its own limit (2).
+    #[cfg(feature = "abi-7-40")]
+    pub max_stack_depth: u32,
+}
+
+impl Default for KernelConfig {
+    fn default() -> Self {
+        Self {
+            flags: supported_init_flags(),
+            max_write: 1024 * 1024,
+            max_pages: 256,
+            max_background: 16,
+            congestion_threshold: 12,
+            time_gran: 1,
+            #[cfg(feature = "abi-7-40")]
+            max_stack_depth: 1,
+        }
+    }
+}
+
+impl KernelConfig {
+    /// Check that the limits are usable before anything is mounted.
+    pub(crate) fn validate(&self) -> Result<(), Errno> {
+        if self.max_write < 4096 || self.max_write as usize + SIZE_HEADER_ROOM > SIZE_BUFFER {
+            error!("max_write {} does not fit the read buffer", self.max_write);
+            return Err(Errno::EINVAL);
+        }
+
+        if self.congestion_threshold > self.max_background {
+            error!(
+                "congestion_threshold {} exceeds max_background {}",
+                self.congestion_threshold, self.max_background
+            );
+            return Err(Errno::EINVAL);
+        }
+
+        if !(0..=9).any(|exp| 10u32.pow(exp) == self.time_gran) {
+            error!("time_gran {} is not a power of ten", self.time_gran);
+            return Err(Errno::EINVAL);
+        }
+
+        #[cfg(feature = "abi-7-40")]
+        if !(1..=2).contains(&self.max_stack_depth) {
+            error!("max_stack_depth {} is not 1 or 2", self.max_stack_depth);
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(())
+    }
+}
+
+/// Character device a CUSE session registers during the `CUSE_INIT` handshake. See
+/// [crate::builder::CuseBuilder].
+#[cfg(feature = "abi-7-12")]
+#[derive(Debug, Clone)]
+pub struct CuseConfig {
+    /// Name of the device node, created as `/dev/<name>`
+    pub name: OsString,
+    /// Major device number. `0` lets the kernel allocate one.
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    /// Ask for `CUSE_UNRESTRICTED_IOCTL`: `IOCTL` requests may then be answered with a retry naming the buffers
+    /// the command reads and writes, as needed for commands that do not encode their argument size
+    pub unrestricted_ioctl: bool,
+    /// Maximum size of a single read
+    pub max_read: u32,
+}
+
+#[cfg(feature = "abi-7-12")]
+impl CuseConfig {
+    pub fn new(name: impl Into<OsString>) -> Self {
+        Self {
+            name: name.into(),
+            dev_major: 0,
+            dev_minor: 0,
+            unrestricted_ioctl: false,
+            max_read: 128 * 1024,
+        }
+    }
+
+    /// Check that the name is usable before the device is opened.
+    pub(crate) fn validate(&self) -> Result<(), Errno> {
+        if self.name.is_empty() || self.name.as_bytes().contains(&0) {
+            error!("invalid device name {:?}", self.name);
+            return Err(Errno::EINVAL);
+        }
+
+        Ok(())
+    }
+}
+
+/// Result of the INIT handshake
+///
+/// After `CUSE_INIT` only the versions and `max_write` are set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Protocol major version. Always [FUSE_KERNEL_VERSION].
+    pub major: u32,
+    /// Protocol minor version: the lower of the kernel's and ours. Requests are parsed and replies written in its
+    /// layout.
+    pub minor: u32,
+    /// Capability flags offered by the kernel
+    pub kernel_flags: InitFlags,
+    /// Capability flags both sides agreed on
+    pub flags: InitFlags,
+    pub max_readahead: u32,
+    pub max_write: u32,
+    /// `0` unless `FUSE_MAX_PAGES` was agreed
+    pub max_pages: u16,
+    pub max_background: u16,
+    pub congestion_threshold: u16,
+    pub time_gran: u32,
+}
+
+/// Outcome of [negotiate]
+pub(crate) enum Negotiation {
+    /// The kernel speaks a newer major version. Reply with ours only and wait for the kernel to retry.
+    Retry(fuse_init_out),
+    /// Handshake complete
+    Done(fuse_init_out, ConnectionInfo),
+}
+
+/// Compute the reply to the kernel's `FUSE_INIT`.
+pub(crate) fn negotiate(config: &KernelConfig, arg: &fuse_init_in) -> Result<Negotiation, Errno> {
+    if arg.major < FUSE_KERNEL_VERSION {
+        error!("unsupported kernel protocol {}.{}", arg.major, arg.minor);
+        return Err(Errno::EPROTO);
+    }
+
+    let mut out = fuse_init_out::new_zeroed();
+    out.major = FUSE_KERNEL_VERSION;
+    out.minor = FUSE_KERNEL_MINOR_VERSION;
+
+    if arg.major > FUSE_KERNEL_VERSION {
+        warn!(
+            "kernel protocol {}.{} is newer, offering {}.{}",
+            arg.major, arg.minor, FUSE_KERNEL_VERSION, FUSE_KERNEL_MINOR_VERSION
+        );
+        return Ok(Negotiation::Retry(out));
+    }
+
+    let kernel_flags = InitFlags::from_init_in(arg);
+    #[allow(unused_mut)]
+    let mut flags = config.flags & kernel_flags;
+
+    // The upper half is only read along with INIT_EXT
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    if !flags.contains(InitFlags::INIT_EXT) {
+        flags &= InitFlags::from_bits_retain(u32::MAX as u64);
+    }
+
+    out.max_readahead = arg.max_readahead;
+    (out.flags, _) = flags.split();
+    #[cfg(feature = "abi-7-36")]
+    {
+        (_, out.flags2) = flags.split();
+    }
+    out.max_write = config.max_write;
+    #[cfg(feature = "abi-7-13")]
+    {
+        out.max_background = config.max_background;
+        out.congestion_threshold = config.congestion_threshold;
+    }
+    #[cfg(feature = "abi-7-23")]
+    {
+        out.time_gran = config.time_gran;
+    }
+    #[allow(unused_mut)]
+    let mut max_pages = 0;
+    #[cfg(feature = "abi-7-28")]
+    if flags.contains(InitFlags::MAX_PAGES) {
+        max_pages = config.max_pages;
+        out.max_pages = max_pages;
+    }
+    #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+    if flags.contains(InitFlags::PASSTHROUGH) {
+        out.max_stack_depth = config.max_stack_depth;
+    }
+
+    let info = ConnectionInfo {
+        major: FUSE_KERNEL_VERSION,
+        minor: arg.minor.min(FUSE_KERNEL_MINOR_VERSION),
+        kernel_flags,
+        flags,
+        max_readahead: out.max_readahead,
+        max_write: out.max_write,
+        max_pages,
+        max_background: config.max_background,
+        congestion_threshold: config.congestion_threshold,
+        time_gran: config.time_gran,
+    };
+
+    info!(
+        "kernel protocol {}.{}, negotiated {}.{} flags {:#x}",
+        arg.major,
+        arg.minor,
+        info.major,
+        info.minor,
+        info.flags.bits()
+    );
+
+    Ok(Negotiation::Done(out, info))
+}
+
+/// Compute the reply to the kernel's `CUSE_INIT`: the `cuse_init_out` and the device info following it. The limits
+/// other than `max_read` are taken from `kernel`.
+#[cfg(feature = "abi-7-12")]
+pub(crate) fn negotiate_cuse(
+    config: &CuseConfig,
+    kernel: &KernelConfig,
+    arg: &cuse_init_in,
+) -> Result<(cuse_init_out, Vec<u8>, ConnectionInfo), Errno> {
+    if arg.major != FUSE_KERNEL_VERSION {
+        error!("unsupported kernel protocol {}.{}", arg.major, arg.minor);
+        return Err(Errno::EPROTO);
+    }
+
+    let mut out = cuse_init_out::new_zeroed();
+    out.major = FUSE_KERNEL_VERSION;
+    out.minor = FUSE_KERNEL_MINOR_VERSION;
+    if config.unrestricted_ioctl {
+        out.flags = arg.flags & CUSE_UNRESTRICTED_IOCTL;
+    }
+    out.max_read = config.max_read;
+    out.max_write = kernel.max_write;
+    out.dev_major = config.dev_major;
+    out.dev_minor = config.dev_minor;
+
+    let dev_info = [b"DEVNAME=", config.name.as_bytes(), b"\0"].concat();
+
+    let info = ConnectionInfo {
+        major: FUSE_KERNEL_VERSION,
+        minor: arg.minor.min(FUSE_KERNEL_MINOR_VERSION),
+        kernel_flags: InitFlags::empty(),
+        flags: InitFlags::empty(),
+        max_readahead: 0,
+        max_write: out.max_write,
+        max_pages: 0,
+        max_background: 0,
+        congestion_threshold: 0,
+        time_gran: 0,
+    };
+
+    info!(
+        "kernel protocol {}.{}, negotiated {}.{} device {:?} {}:{}",
+        arg.major, arg.minor, info.major, info.minor, config.name, config.dev_major, config.dev_minor
+    );
+
+    Ok((out, dev_info, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_in(major: u32, minor: u32, flags: u32) -> fuse_init_in {
+        let mut arg = fuse_init_in::new_zeroed();
+        arg.major = major;
+        arg.minor = minor;
+        arg.max_readahead = 131072;
+        arg.flags = flags;
+        arg
+    }
+
+    #[test]
+    fn negotiate_intersects_flags() {
+        let config = KernelConfig {
+            flags: InitFlags::ASYNC_READ | InitFlags::from_bits_retain(1 << 29),
+            ..Default::default()
+        };
+
+        match negotiate(&config, &init_in(7, 8, FUSE_ASYNC_READ)).unwrap() {
+            Negotiation::Done(out, info) => {
+                assert_eq!(out.major, 7);
+                assert_eq!(out.minor, FUSE_KERNEL_MINOR_VERSION);
+                assert_eq!(out.flags, FUSE_ASYNC_READ);
+                assert_eq!(out.max_readahead, 131072);
+                assert_eq!(info.minor, 8);
+                assert_eq!(info.flags, InitFlags::ASYNC_READ);
+                assert_eq!(info.max_write, config.max_write);
+            }
+            Negotiation::Retry(_) => panic!("expected handshake to complete"),
+        }
+    }
+
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    #[test]
+    fn negotiate_flags2() {
+        let mut arg = init_in(7, 36, FUSE_ASYNC_READ | FUSE_INIT_EXT);
+        arg.flags2 = ((FUSE_SECURITY_CTX | FUSE_HAS_INODE_DAX) >> 32) as u32;
+        let config = KernelConfig {
+            flags: InitFlags::ASYNC_READ | InitFlags::INIT_EXT | InitFlags::SECURITY_CTX,
+            ..Default::default()
+        };
+
+        match negotiate(&config, &arg).unwrap() {
+            Negotiation::Done(out, info) => {
+                assert_eq!(out.flags, FUSE_ASYNC_READ | FUSE_INIT_EXT);
+                assert_eq!(out.flags2, (FUSE_SECURITY_CTX >> 32) as u32);
+                assert_eq!(InitFlags::from_init_out(&out), info.flags);
+                assert!(info.kernel_flags.contains(InitFlags::HAS_INODE_DAX));
+            }
+            Negotiation::Retry(_) => panic!("expected handshake to complete"),
+        }
+
+        // Without INIT_EXT the upper half is not sent
+        let config = KernelConfig {
+            flags: InitFlags::ASYNC_READ | InitFlags::SECURITY_CTX,
+            ..Default::default()
+        };
+        match negotiate(&config, &arg).unwrap() {
+            Negotiation::Done(out, info) => {
+                assert_eq!(out.flags2, 0);
+                assert_eq!(info.flags, InitFlags::ASYNC_READ);
+            }
+            Negotiation::Retry(_) => panic!("expected handshake to complete"),
+        }
+
+        // Nor is it read from a kernel that does not set INIT_EXT
+        arg.flags = FUSE_ASYNC_READ;
+        assert_eq!(InitFlags::from_init_in(&arg), InitFlags::ASYNC_READ);
+    }
+
+    #[cfg(all(feature = "abi-7-40", not(target_os = "macos")))]
+    #[test]
+    fn negotiate_passthrough() {
+        let mut arg = init_in(7, 40, FUSE_INIT_EXT);
+        arg.flags2 = (FUSE_PASSTHROUGH >> 32) as u32;
+        let config = KernelConfig {
+            flags: InitFlags::INIT_EXT | InitFlags::PASSTHROUGH,
+            max_stack_depth: 2,
+            ..Default::default()
+        };
+        match negotiate(&config, &arg).unwrap() {
+            Negotiation::Done(out, info) => {
+                assert_eq!(out.max_stack_depth, 2);
+                assert!(info.flags.contains(InitFlags::PASSTHROUGH));
+            }
+            Negotiation::Retry(_) => panic!("expected handshake to complete"),
+        }
+
+        // Not sent unless the kernel offers passthrough
+        arg.flags2 = 0;
+        match negotiate(&config, &arg).unwrap() {
+            Negotiation::Done(out, _) => assert_eq!(out.max_stack_depth, 0),
+            Negotiation::Retry(_) => panic!("expected handshake to complete"),
+        }
+    }
+
+    #[test]
+    fn negotiate_versions() {
+        let config = KernelConfig::default();
+
+        assert!(matches!(negotiate(&config, &init_in(6, 0, 0)), Err(Errno::EPROTO)));
+        assert!(matches!(
+            negotiate(&config, &init_in(8, 0, 0)),
+            Ok(Negotiation::Retry(out)) if out.major == 7 && out.flags == 0
+        ));
+    }
+
+    #[cfg(feature = "abi-7-12")]
+    #[test]
+    fn negotiate_cuse_devname() {
+        let mut arg = cuse_init_in::new_zeroed();
+        arg.major = 7;
+        arg.minor = 31;
+        arg.flags = CUSE_UNRESTRICTED_IOCTL;
+        let config = CuseConfig {
+            dev_major: 240,
+            dev_minor: 1,
+            unrestricted_ioctl: true,
+            ..CuseConfig::new("ttyFOO")
+        };
+
+        let (out, dev_info, info) = negotiate_cuse(&config, &KernelConfig::default(), &arg).unwrap();
+        assert_eq!((out.dev_major, out.dev_minor), (240, 1));
+        assert_eq!(out.flags, CUSE_UNRESTRICTED_IOCTL);
+        assert_eq!(dev_info, b"DEVNAME=ttyFOO\0");
+        assert_eq!(info.minor, 31);
+
+        arg.major = 8;
+        assert!(matches!(
+            negotiate_cuse(&config, &KernelConfig::default(), &arg),
+            Err(Errno::EPROTO)
+        ));
+        assert!(CuseConfig::new("").validate().is_err());
+    }
+
+    #[test]
+    fn validate_limits() {
+        assert!(KernelConfig::default().validate().is_ok());
+        for config in [
+            KernelConfig {
+                max_write: SIZE_BUFFER as u32,
+                ..Default::default()
+            },
+            KernelConfig {
+                congestion_threshold: 20,
+                ..Default::default()
+            },
+            KernelConfig {
+                time_gran: 20,
+                ..Default::default()
+            },
+        ] {
+            assert!(config.validate().is_err());
+        }
+    }
+}
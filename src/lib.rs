@@ -1,15 +1,27 @@
 //! TODO Init flags
 //! Emit FUSE_PARALLEL_DIROPS on init
 
-use constants::*;
+use init::InitFlags;
 use messages::{reply::Reply, request::Request};
 
+mod buffer;
 pub mod builder;
 pub mod constants;
+mod device;
 pub mod error;
+mod in_flight;
+pub mod init;
 pub mod messages;
 pub mod mount;
+pub mod notify;
+#[cfg(target_os = "linux")]
+mod pipe;
 pub mod session;
+pub mod strace;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod transport;
 
 pub const MEBI: u64 = 2u64.pow(20);
 pub const SIZE_CHANNEL: usize = 32;
@@ -29,36 +41,40 @@ pub fn create_reply_channel() -> (ReplyTx, ReplyRx) {
     tokio::sync::mpsc::channel::<Reply>(SIZE_CHANNEL)
 }
 
-pub const INIT_FLAGS: u32 = FUSE_ASYNC_READ
-    | FUSE_BIG_WRITES
-    | FUSE_ASYNC_DIO
-    | FUSE_FSYNC_FDATASYNC
-    | FUSE_FILE_OPS
-    | FUSE_ATOMIC_O_TRUNC
-    | FUSE_EXPORT_SUPPORT
-    | FUSE_BIG_WRITES;
+/// Capabilities asked for by default. See [supported_init_flags].
+pub const INIT_FLAGS: InitFlags = InitFlags::ASYNC_READ
+    .union(InitFlags::BIG_WRITES)
+    .union(InitFlags::ASYNC_DIO)
+    .union(InitFlags::FILE_OPS)
+    .union(InitFlags::ATOMIC_O_TRUNC)
+    .union(InitFlags::EXPORT_SUPPORT);
 
-pub fn supported_init_flags() -> u32 {
+pub fn supported_init_flags() -> InitFlags {
     let mut init = INIT_FLAGS;
 
     #[cfg(feature = "abi-7-12")]
     {
-        init |= FUSE_DONT_MASK
+        init |= InitFlags::DONT_MASK
     }
 
     #[cfg(feature = "abi-7-17")]
     {
-        init |= FUSE_FLOCK_LOCKS
+        init |= InitFlags::FLOCK_LOCKS
     }
 
     #[cfg(feature = "abi-7-28")]
     {
-        init |= FUSE_MAX_PAGES;
+        init |= InitFlags::MAX_PAGES;
+    }
+
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    {
+        init |= InitFlags::INIT_EXT;
     }
 
     #[cfg(target_os = "macos")]
     {
-        init |= FUSE_ASYNC_READ | FUSE_VOL_RENAME | FUSE_XTIMES;
+        init |= InitFlags::ASYNC_READ | InitFlags::VOL_RENAME | InitFlags::XTIMES;
     }
 
     init
@@ -1,17 +1,49 @@
-use zerocopy::{FromBytes, Immutable, KnownLayout};
+use std::{ffi::OsString, os::unix::ffi::OsStrExt};
 
-/// Treats the incoming buffer as being NUL terminated string and converts to UTF8 with lossy encoding
-pub fn get_string(buffer: &mut [u8]) -> (String, &mut [u8]) {
-    let len = memchr::memchr(0, buffer).expect("NUL terminated string");
-    let (out, rest) = buffer.split_at_mut(len);
-    let string = String::from_utf8_lossy(out).into_owned();
-    (string, &mut rest[1..])
+use zerocopy::{FromBytes, IntoBytes};
+
+/// Read a `T` from the front of the buffer. Return [Option::None] if there are not enough bytes. Also return the
+/// remaining buffer
+pub fn get_arg<T: FromBytes>(buffer: &[u8]) -> Option<(T, &[u8])> {
+    T::read_from_prefix(buffer).ok()
+}
+
+/// Read a `T` from the first `size` bytes of the buffer and zero the rest of it, for arguments that older protocol
+/// versions send shorter. Return [Option::None] if there are fewer than `size` bytes. Also return the remaining buffer
+pub fn get_arg_prefix<T: FromBytes + IntoBytes>(buffer: &[u8], size: usize) -> Option<(T, &[u8])> {
+    let (prefix, rest) = buffer.split_at_checked(size)?;
+    let mut arg = T::new_zeroed();
+    let len = size.min(size_of::<T>());
+    arg.as_mut_bytes()[..len].copy_from_slice(&prefix[..len]);
+    Some((arg, rest))
+}
+
+/// Treats the incoming buffer as being a NUL terminated name and copies its bytes as they are (names need not be
+/// UTF-8). Return [Option::None] if there is no NUL. Also return the remaining buffer
+pub fn get_name(buffer: &[u8]) -> Option<(OsString, &[u8])> {
+    let len = memchr::memchr(0, buffer)?;
+    let (out, rest) = buffer.split_at(len);
+    let name = std::ffi::OsStr::from_bytes(out).to_os_string();
+    Some((name, &rest[1..]))
+}
+
+/// Split `len` bytes off the front of the buffer. Return [Option::None] if there are not enough bytes. Also return
+/// the remaining buffer
+pub fn get_bytes(buffer: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+    buffer.split_at_checked(len)
 }
 
 /// Get a slice of typed T. Return [Option::None] if there are not enough bytes. Also return
 /// the remaining buffer
-#[cfg(feature = "abi-7-16")]
-pub fn get_vec<T: FromBytes + Immutable + KnownLayout + Copy>(buffer: &[u8], count: usize) -> Vec<T> {
-    let elements = <[T]>::ref_from_prefix_with_elems(buffer, count).unwrap().0;
-    elements.to_vec()
+///
+/// Elements are copied out one by one since they need not be aligned in the buffer (e.g. the entries following the
+/// 4 byte `fuse_removemapping_in`).
+pub fn get_vec<T: FromBytes>(buffer: &[u8], count: usize) -> Option<(Vec<T>, &[u8])> {
+    let size = size_of::<T>();
+    let (elements, rest) = buffer.split_at_checked(size.checked_mul(count)?)?;
+    let elements = elements
+        .chunks_exact(size)
+        .map(|element| T::read_from_bytes(element).ok())
+        .collect::<Option<Vec<T>>>()?;
+    Some((elements, rest))
 }
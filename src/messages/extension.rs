@@ -0,0 +1,194 @@
+//! Extensions the kernel appends to a request after its arguments.
+//!
+//! From 7.36 the arguments of `CREATE`, `MKDIR`, `MKNOD`, `SYMLINK` and `TMPFILE` may be followed by the security
+//! context of the new inode (with `FUSE_SECURITY_CTX`) and, from 7.38, by the group it should belong to (with
+//! `FUSE_CREATE_SUPP_GROUP`). They take up the last `fuse_in_header.total_extlen` 8 byte units of the message. Each
+//! starts with a [fuse_ext_header] whose `size` includes the header and the padding to 8 bytes.
+
+use std::{ffi::OsString, os::unix::ffi::OsStrExt};
+
+use zerocopy::IntoBytes;
+
+#[cfg(feature = "abi-7-38")]
+use crate::messages::argument::get_vec;
+use crate::messages::argument::{get_arg, get_bytes, get_name};
+use crate::messages::fuse_abi::*;
+
+/// Extensions of a single request. See [crate::messages::request::Request::extensions]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// Security contexts to label the new inode with. `None` unless `FUSE_SECURITY_CTX` was agreed; empty when no
+    /// security module supplied one.
+    pub security_context: Option<Vec<SecurityContext>>,
+    /// Group of the parent directory, sent with `FUSE_CREATE_SUPP_GROUP` when the caller belongs to it through a
+    /// supplementary group only, for the filesystem to give the new inode that group where the parent is set-group-ID
+    #[cfg(feature = "abi-7-38")]
+    pub supplementary_groups: Option<Vec<u32>>,
+}
+
+/// Security context of a new inode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityContext {
+    /// Extended attribute to store the context in, e.g. `security.selinux`
+    pub name: OsString,
+    /// Context as the security module produced it. SELinux includes the trailing NUL.
+    pub value: Vec<u8>,
+}
+
+impl Extensions {
+    /// Decode the extensions at the end of a request. Unknown types are skipped. Return [Option::None] if they are
+    /// malformed
+    pub(crate) fn parse(mut buffer: &[u8]) -> Option<Self> {
+        let mut extensions = Self::default();
+
+        while !buffer.is_empty() {
+            let (header, _) = get_arg::<fuse_ext_header>(buffer)?;
+            let (extension, rest) = get_bytes(buffer, header.size as usize)?;
+            let body = extension.get(size_of::<fuse_ext_header>()..)?;
+
+            match header.typ {
+                0..=FUSE_MAX_NR_SECCTX => {
+                    extensions.security_context = Some(security_context(body, header.typ as usize)?);
+                }
+                #[cfg(feature = "abi-7-38")]
+                FUSE_EXT_GROUPS => {
+                    let (arg, rest) = get_arg::<fuse_supp_groups>(body)?;
+                    let (groups, _padding) = get_vec::<u32>(rest, arg.nr_groups as usize)?;
+                    extensions.supplementary_groups = Some(groups);
+                }
+                _ => {}
+            }
+
+            buffer = rest;
+        }
+
+        Some(extensions)
+    }
+
+    /// Append the extensions as the kernel sends them. The inverse of [Extensions::parse].
+    pub(crate) fn encode(&self, buffer: &mut Vec<u8>) {
+        let base = buffer.len();
+
+        if let Some(contexts) = &self.security_context {
+            let start = buffer.len();
+            let header = fuse_secctx_header {
+                size: 0,
+                nr_secctx: contexts.len() as u32,
+            };
+            buffer.extend_from_slice(header.as_bytes());
+            for context in contexts {
+                let arg = fuse_secctx {
+                    size: context.value.len() as u32,
+                    padding: 0,
+                };
+                buffer.extend_from_slice(arg.as_bytes());
+                buffer.extend_from_slice(context.name.as_bytes());
+                buffer.push(0);
+                buffer.extend_from_slice(&context.value);
+                pad(buffer, base);
+            }
+            let size = (buffer.len() - start) as u32;
+            buffer[start..start + 4].copy_from_slice(&size.to_ne_bytes());
+        }
+
+        #[cfg(feature = "abi-7-38")]
+        if let Some(groups) = &self.supplementary_groups {
+            let start = buffer.len();
+            buffer.extend_from_slice(
+                fuse_ext_header {
+                    size: 0,
+                    typ: FUSE_EXT_GROUPS,
+                }
+                .as_bytes(),
+            );
+            let arg = fuse_supp_groups {
+                nr_groups: groups.len() as u32,
+            };
+            buffer.extend_from_slice(arg.as_bytes());
+            buffer.extend_from_slice(groups.as_bytes());
+            pad(buffer, base);
+            let size = (buffer.len() - start) as u32;
+            buffer[start..start + 4].copy_from_slice(&size.to_ne_bytes());
+        }
+    }
+
+    /// Whether the request carried no extensions at all
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Decode `count` security contexts, each padded to 8 bytes
+fn security_context(mut buffer: &[u8], count: usize) -> Option<Vec<SecurityContext>> {
+    let mut contexts = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let (arg, rest) = get_arg::<fuse_secctx>(buffer)?;
+        let (name, rest) = get_name(rest)?;
+        let (value, rest) = get_bytes(rest, arg.size as usize)?;
+        let len = size_of::<fuse_secctx>() + name.len() + 1 + value.len();
+        let (_padding, rest) = get_bytes(rest, len.next_multiple_of(8) - len)?;
+
+        contexts.push(SecurityContext {
+            name,
+            value: value.to_vec(),
+        });
+        buffer = rest;
+    }
+
+    Some(contexts)
+}
+
+/// Zero-pad `buffer` to a multiple of 8 bytes past `base`
+fn pad(buffer: &mut Vec<u8>, base: usize) {
+    let len = (buffer.len() - base).next_multiple_of(8) + base;
+    buffer.resize(len, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let extensions = Extensions {
+            security_context: Some(vec![SecurityContext {
+                name: OsString::from("security.selinux"),
+                value: b"system_u:object_r:container_file_t:s0\0".to_vec(),
+            }]),
+            #[cfg(feature = "abi-7-38")]
+            supplementary_groups: Some(vec![1000]),
+        };
+
+        let mut buffer = b"name\0".to_vec();
+        extensions.encode(&mut buffer);
+        assert_eq!((buffer.len() - 5) % 8, 0);
+        assert_eq!(Extensions::parse(&buffer[5..]), Some(extensions.clone()));
+
+        // A security context extension without contexts is told apart from none at all
+        let empty = Extensions {
+            security_context: Some(Vec::new()),
+            #[cfg(feature = "abi-7-38")]
+            supplementary_groups: None,
+        };
+        let mut buffer = Vec::new();
+        empty.encode(&mut buffer);
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(Extensions::parse(&buffer), Some(empty));
+
+        // Sizes that overrun the message
+        let mut buffer = Vec::new();
+        extensions.encode(&mut buffer);
+        let size = buffer.len() as u32 + 8;
+        buffer[..4].copy_from_slice(&size.to_ne_bytes());
+        assert_eq!(Extensions::parse(&buffer), None);
+        assert_eq!(Extensions::parse(fuse_ext_header { size: 4, typ: 0 }.as_bytes()), None);
+    }
+
+    #[test]
+    fn unknown_skipped() {
+        let mut buffer = fuse_ext_header { size: 16, typ: 99 }.as_bytes().to_vec();
+        buffer.extend_from_slice(&[0xff; 8]);
+        assert_eq!(Extensions::parse(&buffer), Some(Extensions::default()));
+    }
+}
@@ -23,6 +23,8 @@
 
 #[cfg(feature = "abi-7-9")]
 use crate::constants::{FATTR_ATIME_NOW, FATTR_MTIME_NOW};
+#[cfg(feature = "abi-7-40")]
+use crate::constants::FOPEN_PASSTHROUGH;
 use std::convert::TryFrom;
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes};
 
@@ -74,13 +76,54 @@ pub const FUSE_KERNEL_MINOR_VERSION: u32 = 28;
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 29;
 #[cfg(all(feature = "abi-7-30", not(feature = "abi-7-31")))]
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 30;
-#[cfg(feature = "abi-7-31")]
+#[cfg(all(feature = "abi-7-31", not(feature = "abi-7-32")))]
 pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+#[cfg(all(feature = "abi-7-32", not(feature = "abi-7-33")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 32;
+#[cfg(all(feature = "abi-7-33", not(feature = "abi-7-34")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 33;
+#[cfg(all(feature = "abi-7-34", not(feature = "abi-7-35")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 34;
+#[cfg(all(feature = "abi-7-35", not(feature = "abi-7-36")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 35;
+#[cfg(all(feature = "abi-7-36", not(feature = "abi-7-37")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 36;
+#[cfg(all(feature = "abi-7-37", not(feature = "abi-7-38")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 37;
+#[cfg(all(feature = "abi-7-38", not(feature = "abi-7-39")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 38;
+#[cfg(all(feature = "abi-7-39", not(feature = "abi-7-40")))]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 39;
+#[cfg(feature = "abi-7-40")]
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 40;
+
+// Sizes of the structs that grew, as exchanged with kernels speaking an older protocol minor. The session parses and
+// writes these when the minor agreed in INIT is older than the one the struct was extended in.
+/// `fuse_entry_out` before 7.9, without `fuse_attr::blksize`
+pub const FUSE_COMPAT_ENTRY_OUT_SIZE: usize = 120;
+/// `fuse_attr_out` before 7.9, without `fuse_attr::blksize`
+pub const FUSE_COMPAT_ATTR_OUT_SIZE: usize = 96;
+/// `fuse_mknod_in` before 7.12, without `umask`
+pub const FUSE_COMPAT_MKNOD_IN_SIZE: usize = 8;
+/// `fuse_write_in` before 7.9, without `lock_owner` and `flags`
+pub const FUSE_COMPAT_WRITE_IN_SIZE: usize = 24;
+/// `fuse_init_out` before 7.5
+pub const FUSE_COMPAT_INIT_OUT_SIZE: usize = 8;
+/// `fuse_init_out` before 7.23, without `time_gran` and `max_pages`
+pub const FUSE_COMPAT_22_INIT_OUT_SIZE: usize = 24;
+/// `fuse_read_in` before 7.9, without `read_flags`, `lock_owner` and `flags`. Not in `fuse_kernel.h`.
+pub const FUSE_COMPAT_READ_IN_SIZE: usize = 24;
+/// `fuse_lk_in` before 7.9, without `lk_flags`. Not in `fuse_kernel.h`.
+pub const FUSE_COMPAT_LK_IN_SIZE: usize = 40;
+/// `CREATE` sent a `fuse_open_in` before 7.12: `flags` and `mode` only. Not in `fuse_kernel.h`.
+pub const FUSE_COMPAT_CREATE_IN_SIZE: usize = 8;
+/// `fuse_init_in` before 7.36, without `flags2`. Not in `fuse_kernel.h`.
+pub const FUSE_COMPAT_INIT_IN_SIZE: usize = 16;
 
 pub const FUSE_ROOT_ID: u64 = 1;
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, Clone, Copy, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, FromBytes, Clone, Copy, KnownLayout, Immutable)]
 pub struct fuse_attr {
     /// Inode number
     pub ino: u64,
@@ -126,7 +169,7 @@ pub struct fuse_attr {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 /// See [statfs man page](https://www.man7.org/linux/man-pages/man2/statfs.2.html)
 pub struct fuse_kstatfs {
     /// Total number of blocks (in units of frsize)
@@ -374,7 +417,7 @@ impl TryFrom<u32> for fuse_notify_code {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_entry_out {
     /// Reference into a fixed size inode table
     pub nodeid: u64,
@@ -387,14 +430,14 @@ pub struct fuse_entry_out {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Copy, Clone)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Copy, Clone)]
 pub struct fuse_forget_in {
     pub nlookup: u64,
 }
 
 #[cfg(feature = "abi-7-16")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Copy, Clone)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Copy, Clone)]
 pub struct fuse_forget_one {
     pub nodeid: u64,
     pub nlookup: u64,
@@ -402,7 +445,7 @@ pub struct fuse_forget_one {
 
 #[cfg(feature = "abi-7-16")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_batch_forget_in {
     pub count: u32,
     pub dummy: u32,
@@ -410,7 +453,7 @@ pub struct fuse_batch_forget_in {
 
 #[cfg(feature = "abi-7-9")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_getattr_in {
     pub getattr_flags: u32,
     pub dummy: u32,
@@ -418,7 +461,7 @@ pub struct fuse_getattr_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Copy, Clone)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Copy, Clone)]
 pub struct fuse_attr_out {
     pub attr_valid: u64,
     pub attr_valid_nsec: u32,
@@ -428,7 +471,7 @@ pub struct fuse_attr_out {
 
 #[cfg(target_os = "macos")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Copy, Clone)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Copy, Clone)]
 pub struct fuse_getxtimes_out {
     pub bkuptime: u64,
     pub crtime: u64,
@@ -485,7 +528,7 @@ pub struct fuse_rename2_in {
 
 #[cfg(target_os = "macos")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_exchange_in {
     pub olddir: u64,
     pub newdir: u64,
@@ -499,7 +542,7 @@ pub struct fuse_link_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone)]
 pub struct fuse_setattr_in {
     pub valid: u32,
     pub padding: u32,
@@ -571,7 +614,7 @@ impl fuse_setattr_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_open_in {
     // NOTE: this field is defined as u32 in fuse_kernel.h in libfuse. However, it is then cast
     // to an i32 when invoking the filesystem's open method and this matches the open() syscall
@@ -605,24 +648,38 @@ pub struct fuse_create_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_create_out {
     pub entry: fuse_entry_out,
     pub open: fuse_open_out,
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_open_out {
     /// File descriptor
     pub fh: u64,
     /// FUSE flags starting with FUSE_OPEN_ ...
     pub open_flags: u32,
+    #[cfg(not(feature = "abi-7-40"))]
     pub padding: u32,
+    /// Backing file the kernel reads and writes directly. See [fuse_open_out::set_backing_id]
+    #[cfg(feature = "abi-7-40")]
+    pub backing_id: i32,
+}
+
+#[cfg(feature = "abi-7-40")]
+impl fuse_open_out {
+    /// Pass reads and writes through to the backing file registered as `backing_id` with
+    /// [crate::session::Session::open_backing]. Sets [FOPEN_PASSTHROUGH].
+    pub fn set_backing_id(&mut self, backing_id: i32) {
+        self.open_flags |= FOPEN_PASSTHROUGH;
+        self.backing_id = backing_id;
+    }
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_release_in {
     pub fh: u64,
     // NOTE: this field is defined as u32 in fuse_kernel.h in libfuse. However, it is then cast
@@ -633,7 +690,7 @@ pub struct fuse_release_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_flush_in {
     pub fh: u64,
     pub unused: u32,
@@ -642,13 +699,15 @@ pub struct fuse_flush_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_read_in {
     pub fh: u64,
     // NOTE: this field is defined as u64 in fuse_kernel.h in libfuse. However, it is then cast
     // to an i64 when invoking the filesystem's read method
     pub offset: i64,
     pub size: u32,
+    #[cfg(not(feature = "abi-7-9"))]
+    pub padding: u32,
     #[cfg(feature = "abi-7-9")]
     pub read_flags: u32,
     #[cfg(feature = "abi-7-9")]
@@ -662,7 +721,7 @@ pub struct fuse_read_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_write_in {
     pub fh: u64,
     // NOTE: this field is defined as u64 in fuse_kernel.h in libfuse. However, it is then cast
@@ -681,20 +740,20 @@ pub struct fuse_write_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_write_out {
     pub size: u32,
     pub padding: u32,
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_statfs_out {
     pub st: fuse_kstatfs,
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_fsync_in {
     pub fh: u64,
     pub fsync_flags: u32,
@@ -726,14 +785,14 @@ pub struct fuse_getxattr_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_getxattr_out {
     pub size: u32,
     pub padding: u32,
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_lk_in {
     pub fh: u64,
     pub owner: u64,
@@ -745,13 +804,13 @@ pub struct fuse_lk_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_lk_out {
     pub lk: fuse_file_lock,
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_access_in {
     // NOTE: this field is defined as u32 in fuse_kernel.h in libfuse. However, it is then cast
     // to an i32 when invoking the filesystem's access method
@@ -760,16 +819,21 @@ pub struct fuse_access_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_init_in {
     pub major: u32,
     pub minor: u32,
     pub max_readahead: u32,
     pub flags: u32,
+    /// Upper half of the flags. Only valid with `FUSE_INIT_EXT` set in `flags`.
+    #[cfg(feature = "abi-7-36")]
+    pub flags2: u32,
+    #[cfg(feature = "abi-7-36")]
+    pub unused: [u32; 11],
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable)]
 /// Iniitialization parameters see: [fuse_common.h](https://github.com/libfuse/libfuse/blob/master/include/fuse_common.h)
 ///
 /// Also see [crate::constants]
@@ -800,13 +864,23 @@ pub struct fuse_init_out {
     pub max_pages: u16,
     #[cfg(feature = "abi-7-28")]
     pub unused2: u16,
-    #[cfg(feature = "abi-7-28")]
+    #[cfg(all(feature = "abi-7-28", not(feature = "abi-7-36")))]
     pub reserved: [u32; 8],
+    /// Upper half of the flags. Only read by the kernel with `FUSE_INIT_EXT` set in `flags`.
+    #[cfg(feature = "abi-7-36")]
+    pub flags2: u32,
+    #[cfg(all(feature = "abi-7-36", not(feature = "abi-7-40")))]
+    pub reserved: [u32; 7],
+    /// How deep backing files of passthrough opens may be stacked
+    #[cfg(feature = "abi-7-40")]
+    pub max_stack_depth: u32,
+    #[cfg(feature = "abi-7-40")]
+    pub reserved: [u32; 6],
 }
 
 #[cfg(feature = "abi-7-12")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct cuse_init_in {
     pub major: u32,
     pub minor: u32,
@@ -816,7 +890,7 @@ pub struct cuse_init_in {
 
 #[cfg(feature = "abi-7-12")]
 #[repr(C)]
-#[derive(Debug, KnownLayout, Immutable)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct cuse_init_out {
     pub major: u32,
     pub minor: u32,
@@ -830,13 +904,13 @@ pub struct cuse_init_out {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_interrupt_in {
     pub unique: u64,
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_bmap_in {
     pub block: u64,
     pub blocksize: u32,
@@ -844,14 +918,14 @@ pub struct fuse_bmap_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_bmap_out {
     pub block: u64,
 }
 
 #[cfg(feature = "abi-7-11")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_ioctl_in {
     pub fh: u64,
     pub flags: u32,
@@ -870,7 +944,7 @@ pub struct fuse_ioctl_iovec {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_ioctl_out {
     pub result: i32,
     pub flags: u32,
@@ -880,7 +954,7 @@ pub struct fuse_ioctl_out {
 
 #[cfg(feature = "abi-7-11")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_poll_in {
     pub fh: u64,
     pub kh: u64,
@@ -893,7 +967,7 @@ pub struct fuse_poll_in {
 
 #[cfg(feature = "abi-7-11")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_poll_out {
     pub revents: u32,
     pub padding: u32,
@@ -901,14 +975,14 @@ pub struct fuse_poll_out {
 
 #[cfg(feature = "abi-7-11")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_poll_wakeup_out {
     pub kh: u64,
 }
 
 #[cfg(feature = "abi-7-19")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_fallocate_in {
     pub fh: u64,
     // NOTE: this field is defined as u64 in fuse_kernel.h in libfuse. However, it is treated as signed
@@ -930,7 +1004,13 @@ pub struct fuse_in_header {
     pub uid: u32,
     pub gid: u32,
     pub pid: u32,
+    #[cfg(not(feature = "abi-7-36"))]
     pub padding: u32,
+    /// Length of the extensions at the end of the message in units of 8 bytes
+    #[cfg(feature = "abi-7-36")]
+    pub total_extlen: u16,
+    #[cfg(feature = "abi-7-36")]
+    pub padding: u16,
 }
 
 #[repr(C)]
@@ -963,7 +1043,7 @@ impl From<fuse_in_header> for fuse_out_header {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_dirent {
     pub ino: u64,
     // NOTE: this field is defined as u64 in fuse_kernel.h in libfuse. However, it is treated as signed
@@ -985,7 +1065,7 @@ impl fuse_dirent {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_direntplus {
     pub entry_out: fuse_entry_out,
     pub dirent: fuse_dirent,
@@ -993,7 +1073,7 @@ pub struct fuse_direntplus {
 
 #[cfg(feature = "abi-7-12")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_inval_inode_out {
     pub ino: u64,
     pub off: i64,
@@ -1002,7 +1082,7 @@ pub struct fuse_notify_inval_inode_out {
 
 #[cfg(feature = "abi-7-12")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_inval_entry_out {
     pub parent: u64,
     pub namelen: u32,
@@ -1011,7 +1091,7 @@ pub struct fuse_notify_inval_entry_out {
 
 #[cfg(feature = "abi-7-18")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_delete_out {
     pub parent: u64,
     pub child: u64,
@@ -1021,7 +1101,7 @@ pub struct fuse_notify_delete_out {
 
 #[cfg(feature = "abi-7-15")]
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_store_out {
     pub nodeid: u64,
     pub offset: u64,
@@ -1031,7 +1111,7 @@ pub struct fuse_notify_store_out {
 
 #[cfg(feature = "abi-7-15")]
 #[repr(C)]
-#[derive(Debug, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_retrieve_out {
     pub notify_unique: u64,
     pub nodeid: u64,
@@ -1042,7 +1122,7 @@ pub struct fuse_notify_retrieve_out {
 
 #[cfg(feature = "abi-7-15")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_notify_retrieve_in {
     // matches the size of fuse_write_in
     pub dummy1: u64,
@@ -1054,7 +1134,7 @@ pub struct fuse_notify_retrieve_in {
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_lseek_in {
     pub fh: u64,
     pub offset: u64,
@@ -1064,13 +1144,13 @@ pub struct fuse_lseek_in {
 }
 
 #[repr(C)]
-#[derive(Debug, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, IntoBytes, FromBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_lseek_out {
     pub offset: u64,
 }
 
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_copy_file_range_in {
     pub fh_in: u64,
     // NOTE: this field is defined as u64 in fuse_kernel.h in libfuse. However, it is treated as signed
@@ -1085,7 +1165,7 @@ pub struct fuse_copy_file_range_in {
 
 #[cfg(feature = "abi-7-31")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy, Default)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy, Default)]
 pub struct fuse_setupmapping_in {
     pub fh: u64,
     pub foffset: u64,
@@ -1096,30 +1176,24 @@ pub struct fuse_setupmapping_in {
 
 #[cfg(feature = "abi-7-31")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy, Default)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy, Default)]
 pub struct fuse_removemapping_in {
     pub count: u32,
 }
 
 #[cfg(feature = "abi-7-31")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
 pub struct fuse_removemapping_one {
-    moffset: u64,
-    len: u64,
+    pub moffset: u64,
+    pub len: u64,
 }
 
 #[cfg(feature = "abi-7-34")]
 #[repr(C)]
-#[derive(Debug, FromBytes, KnownLayout, Immutable, Clone, Copy)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy, Default)]
 pub struct fuse_syncfs_in {
-    padding: u64,
-}
-
-impl Default for fuse_syncfs_in {
-    fn default() -> Self {
-        Self { padding: 0 }
-    }
+    pub padding: u64,
 }
 
 #[cfg(feature = "abi-7-39")]
@@ -1179,3 +1253,56 @@ pub struct fuse_statx_out {
     pub spare: [u64; 2],
     pub stat: fuse_statx,
 }
+
+/// Argument of the `FUSE_DEV_IOC_BACKING_OPEN` ioctl
+#[cfg(feature = "abi-7-40")]
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+pub struct fuse_backing_map {
+    pub fd: i32,
+    pub flags: u32,
+    pub padding: u64,
+}
+
+/// Extension types 0 to [FUSE_MAX_NR_SECCTX] are a [fuse_secctx_header] with that many contexts
+#[cfg(feature = "abi-7-36")]
+pub const FUSE_MAX_NR_SECCTX: u32 = 31;
+/// Extension type of [fuse_supp_groups]
+#[cfg(feature = "abi-7-38")]
+pub const FUSE_EXT_GROUPS: u32 = 32;
+
+/// Header of each extension appended to a request. `size` includes the header and the padding to 8 bytes.
+#[cfg(feature = "abi-7-36")]
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+pub struct fuse_ext_header {
+    pub size: u32,
+    pub typ: u32,
+}
+
+/// Security context extension. Doubles as its [fuse_ext_header], with `nr_secctx` as the type.
+#[cfg(feature = "abi-7-36")]
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+pub struct fuse_secctx_header {
+    pub size: u32,
+    pub nr_secctx: u32,
+}
+
+/// A single security context, followed by the NUL terminated xattr name and `size` bytes of context, padded to 8
+/// bytes
+#[cfg(feature = "abi-7-36")]
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+pub struct fuse_secctx {
+    pub size: u32,
+    pub padding: u32,
+}
+
+/// Supplementary groups extension, followed by `nr_groups` group ids
+#[cfg(feature = "abi-7-38")]
+#[repr(C)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Clone, Copy)]
+pub struct fuse_supp_groups {
+    pub nr_groups: u32,
+}
@@ -1,5 +1,9 @@
 pub mod argument;
+#[cfg(feature = "abi-7-36")]
+pub mod extension;
 #[allow(non_camel_case_types)]
 pub mod fuse_abi;
 pub mod reply;
 pub mod request;
+#[cfg(test)]
+mod strategy;
@@ -1,24 +1,63 @@
 //! Reply message, interior operations, and serializers.
 //!
+//! Serializers write into a buffer whose length is the capacity of the reply and fail with [Errno::EIO] rather
+//! than overrun it.
+//!
 //! # TODO
-//! * Create a derive macro to implement the write_too function
+//! * Create a derive macro to implement the write function
+
+use std::{
+    ffi::{OsStr, OsString},
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    os::unix::ffi::OsStrExt,
+    sync::Arc,
+};
 
-use std::{ffi::OsString, os::unix::ffi::OsStrExt};
+use bytes::Bytes;
 
-use zerocopy::{Immutable, IntoBytes, KnownLayout, TryFromBytes};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes};
 
-use crate::error::Errno;
+use crate::error::{Errno, ParseError, ParseErrorKind};
+use crate::messages::argument::{get_arg, get_bytes};
 #[allow(unused)]
 use crate::messages::fuse_abi::*;
 #[allow(unused)]
 use crate::messages::request::Request;
 
-/// For objects that can write themselves as a byte array to an [io::Write]r
+/// For objects that can write themselves as a byte array into a buffer
 pub trait IWrite {
-    fn write(&mut self, buffer: &mut [u8]) -> usize;
+    /// Serialize into the front of `buffer` and return the number of bytes written.
+    ///
+    /// Fails with [Errno::EIO] without writing anything past `buffer.len()` if the serialized form does not fit.
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno>;
+}
+
+/// Copy `bytes` to the front of `buffer` if they fit
+pub(crate) fn write_bytes(buffer: &mut [u8], bytes: &[u8]) -> Result<usize, Errno> {
+    match buffer.get_mut(..bytes.len()) {
+        Some(dst) => {
+            dst.copy_from_slice(bytes);
+            Ok(bytes.len())
+        }
+        None => Err(Errno::EIO),
+    }
+}
+
+/// Write a directory entry header followed by its name, padded to an 8 byte boundary
+fn write_dirent(buffer: &mut [u8], entry: &[u8], name: &[u8]) -> Result<usize, Errno> {
+    let len = entry.len() + name.len();
+    let padded = len.next_multiple_of(8);
+
+    let dst = buffer.get_mut(..padded).ok_or(Errno::EIO)?;
+    dst[..entry.len()].copy_from_slice(entry);
+    dst[entry.len()..len].copy_from_slice(name);
+    dst[len..].fill(0);
+
+    Ok(padded)
 }
 
 /// Reply to [Filesystem] [Request]s
+#[derive(Debug)]
 pub struct Reply {
     /// Common header for all operations
     pub header: fuse_out_header,
@@ -36,6 +75,256 @@ impl Reply {
     pub fn set_error(&mut self, error: Errno) {
         self.header.error = error.into()
     }
+
+    /// Serialize into `buffer`, holding the payload to the `size` the kernel asked for in the request (READ,
+    /// READDIR, READDIRPLUS, GETXATTR and LISTXATTR).
+    ///
+    /// xattr answers follow the size-probe semantics of [XAttr]; a value that does not fit turns the reply into
+    /// [Errno::ERANGE].
+    pub fn write_sized(&mut self, buffer: &mut [u8], size: Option<u32>) -> Result<usize, Errno> {
+        let mut limit = buffer.len();
+
+        if let Some(size) = size {
+            let fit = match &mut self.operation {
+                Some(Operation::GetXAttr(xattr)) | Some(Operation::ListXAttr(xattr)) => xattr.fit(size),
+                _ => Ok(()),
+            };
+            if let Err(e) = fit {
+                self.set_error(e);
+                self.operation = None;
+            }
+
+            // A probe is answered with the length, everything else must fit in `size` bytes
+            if !matches!(
+                self.operation,
+                Some(Operation::GetXAttr(XAttr::Size(_))) | Some(Operation::ListXAttr(XAttr::Size(_)))
+            ) {
+                limit = limit.min(size_of::<fuse_out_header>() + size as usize);
+            }
+        }
+
+        self.write(&mut buffer[..limit])
+    }
+
+    /// Same as [Reply::write_sized], in the layout expected by a kernel speaking protocol minor `minor`.
+    ///
+    /// Structs that older kernels expect shorter (`fuse_attr` before 7.9, `fuse_init_out` before 7.23, see the
+    /// `FUSE_COMPAT_*` sizes in [crate::messages::fuse_abi]) are cut to their old size. Every other layout is the one
+    /// selected by the `abi-7-N` features.
+    pub fn write_versioned(&mut self, buffer: &mut [u8], size: Option<u32>, minor: u32) -> Result<usize, Errno> {
+        let count = self.write_sized(buffer, size)?;
+
+        // Bytes of the payload that the older struct ends before. `fuse_attr` grew at its end, which is where it sits
+        // in `fuse_entry_out` and `fuse_attr_out`, and `CREATE` follows the entry with the `fuse_open_out`.
+        let cut = match &self.operation {
+            Some(
+                Operation::Lookup(_)
+                | Operation::SymLink(_)
+                | Operation::MkNod(_)
+                | Operation::MkDir(_)
+                | Operation::Link(_)
+                | Operation::Create(_),
+            ) if minor < 9 => FUSE_COMPAT_ENTRY_OUT_SIZE..size_of::<fuse_entry_out>(),
+            Some(Operation::GetAttr(_) | Operation::SetAttr(_)) if minor < 9 => {
+                FUSE_COMPAT_ATTR_OUT_SIZE..size_of::<fuse_attr_out>()
+            }
+            Some(Operation::Init(_)) if minor < 5 => FUSE_COMPAT_INIT_OUT_SIZE..size_of::<fuse_init_out>(),
+            Some(Operation::Init(_)) if minor < 23 => FUSE_COMPAT_22_INIT_OUT_SIZE..size_of::<fuse_init_out>(),
+            _ => return Ok(count),
+        };
+
+        if self.header.error != 0 || cut.is_empty() {
+            return Ok(count);
+        }
+
+        let offset = size_of::<fuse_out_header>();
+        buffer.copy_within(offset + cut.end..count, offset + cut.start);
+        let count = count - cut.len();
+
+        let (header, _rest) = fuse_out_header::try_mut_from_prefix(buffer).map_err(|_e| Errno::EIO)?;
+        header.len = count as u32;
+
+        Ok(count)
+    }
+
+    /// Decode a reply as written to the device, given the opcode of the request it answers. The inverse of
+    /// [IWrite::write].
+    ///
+    /// Error replies, and replies to requests that are answered without a payload, have no operation. A `GETXATTR` or
+    /// `LISTXATTR` reply decodes as [XAttr::Data]: without the request it cannot be told apart from the answer to a
+    /// size probe, whose data is a `fuse_getxattr_out`. Never panics on malformed input.
+    pub fn decode(buffer: &[u8], opcode: u32) -> Result<Self, ParseError> {
+        let Ok((header, payload)) = fuse_out_header::try_read_from_prefix(buffer) else {
+            return Err(ParseError {
+                opcode,
+                unique: 0,
+                kind: ParseErrorKind::ShortHeader { read: buffer.len() },
+            });
+        };
+
+        let error = |kind| ParseError {
+            opcode,
+            unique: header.unique,
+            kind,
+        };
+        let truncated = |what| error(ParseErrorKind::Truncated { what });
+
+        if header.len as usize != buffer.len() {
+            return Err(error(ParseErrorKind::LengthMismatch {
+                header: header.len,
+                read: buffer.len(),
+            }));
+        }
+
+        if header.error != 0 {
+            return Ok(Self {
+                header,
+                operation: None,
+            });
+        }
+
+        let operation = match fuse_opcode::try_from(opcode) {
+            Err(_e) => return Err(error(ParseErrorKind::UnknownOpcode)),
+            Ok(opcode) => match opcode {
+                fuse_opcode::FUSE_LOOKUP => Operation::Lookup(Lookup {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_entry_out"))?.0,
+                }),
+                fuse_opcode::FUSE_GETATTR => Operation::GetAttr(GetAttr {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_attr_out"))?.0,
+                }),
+                fuse_opcode::FUSE_SETATTR => Operation::SetAttr(SetAttr {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_attr_out"))?.0,
+                }),
+                fuse_opcode::FUSE_READLINK => Operation::ReadLink(ReadLink {
+                    data: OsStr::from_bytes(payload).to_os_string(),
+                }),
+                fuse_opcode::FUSE_SYMLINK => Operation::SymLink(SymLink {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_entry_out"))?.0,
+                }),
+                fuse_opcode::FUSE_MKNOD => Operation::MkNod(MkNod {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_entry_out"))?.0,
+                }),
+                fuse_opcode::FUSE_MKDIR => Operation::MkDir(MkDir {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_entry_out"))?.0,
+                }),
+                fuse_opcode::FUSE_LINK => Operation::Link(Link {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_entry_out"))?.0,
+                }),
+                fuse_opcode::FUSE_OPEN => Operation::Open(Open {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_open_out"))?.0,
+                }),
+                fuse_opcode::FUSE_READ => Operation::Read(Read::Data(payload.to_vec())),
+                fuse_opcode::FUSE_WRITE => Operation::Write(Write {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_write_out"))?.0,
+                }),
+                fuse_opcode::FUSE_STATFS => Operation::StatFs(StatFs {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_statfs_out"))?.0,
+                }),
+                fuse_opcode::FUSE_GETXATTR => Operation::GetXAttr(XAttr::Data(payload.to_vec())),
+                fuse_opcode::FUSE_LISTXATTR => Operation::ListXAttr(XAttr::Data(payload.to_vec())),
+                fuse_opcode::FUSE_INIT => Operation::Init(Init {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_init_out"))?.0,
+                }),
+                fuse_opcode::FUSE_OPENDIR => Operation::OpenDir(OpenDir {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_open_out"))?.0,
+                }),
+                fuse_opcode::FUSE_READDIR => Operation::ReadDir(ReadDir {
+                    entries: get_dirents(payload, |x: &fuse_dirent| x.namelen)
+                        .ok_or_else(|| truncated("fuse_dirent"))?
+                        .into_iter()
+                        .map(|(entry, name)| DirectoryEntry { entry, name })
+                        .collect(),
+                }),
+                fuse_opcode::FUSE_GETLK => Operation::GetLk(GetLk {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_lk_out"))?.0,
+                }),
+                // The kernel expects no payload, though one may be sent
+                fuse_opcode::FUSE_SETLK | fuse_opcode::FUSE_SETLKW if !payload.is_empty() => {
+                    Operation::SetLk(SetLk {
+                        arg: get_arg(payload).ok_or_else(|| truncated("fuse_lk_out"))?.0,
+                    })
+                }
+                fuse_opcode::FUSE_CREATE => Operation::Create(Create {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_create_out"))?.0,
+                }),
+                fuse_opcode::FUSE_BMAP => Operation::BMap(BMap {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_bmap_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-11")]
+                fuse_opcode::FUSE_IOCTL => Operation::IoCtl(IoCtl {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_ioctl_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-11")]
+                fuse_opcode::FUSE_POLL => Operation::Poll(Poll {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_poll_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-21")]
+                fuse_opcode::FUSE_READDIRPLUS => Operation::ReadDirPlus(ReadDirPlus {
+                    entries: get_dirents(payload, |x: &fuse_direntplus| x.dirent.namelen)
+                        .ok_or_else(|| truncated("fuse_direntplus"))?
+                        .into_iter()
+                        .map(|(entry, name)| DirectoryEntryPlus { entry, name })
+                        .collect(),
+                }),
+                #[cfg(feature = "abi-7-24")]
+                fuse_opcode::FUSE_LSEEK => Operation::Lseek(Lseek {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_lseek_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-28")]
+                fuse_opcode::FUSE_COPY_FILE_RANGE => Operation::CopyFileRange(CopyFileRange {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_write_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-37")]
+                fuse_opcode::FUSE_TMPFILE => Operation::TmpFile(TmpFile {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_create_out"))?.0,
+                }),
+                #[cfg(feature = "abi-7-39")]
+                fuse_opcode::FUSE_STATX => Operation::StatX(StatX {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_statx_out"))?.0,
+                }),
+                #[cfg(target_os = "macos")]
+                fuse_opcode::FUSE_GETX_TIMES => Operation::GetXTimes(GetXTimes {
+                    arg: get_arg(payload).ok_or_else(|| truncated("fuse_getxtimes_out"))?.0,
+                }),
+                fuse_opcode::CUSE_INIT => {
+                    let (arg, dev_info) = get_arg(payload).ok_or_else(|| truncated("cuse_init_out"))?;
+                    Operation::CuseInit(CuseInit {
+                        arg,
+                        dev_info: dev_info.to_vec(),
+                    })
+                }
+                // Answered without a payload
+                _ => {
+                    return Ok(Self {
+                        header,
+                        operation: None,
+                    })
+                }
+            },
+        };
+
+        Ok(Self {
+            header,
+            operation: Some(operation),
+        })
+    }
+}
+
+/// Split a `READDIR` or `READDIRPLUS` payload into entries of type `T` followed by a name of `namelen(entry)` bytes,
+/// each padded to 8 bytes. Return [Option::None] if an entry is cut short.
+fn get_dirents<T: FromBytes>(mut buffer: &[u8], namelen: impl Fn(&T) -> u32) -> Option<Vec<(T, OsString)>> {
+    let mut entries = Vec::new();
+
+    while !buffer.is_empty() {
+        let (entry, rest) = get_arg::<T>(buffer)?;
+        let (name, _rest) = get_bytes(rest, namelen(&entry) as usize)?;
+        let padded = (size_of::<T>() + name.len()).next_multiple_of(8);
+
+        entries.push((entry, OsStr::from_bytes(name).to_os_string()));
+        buffer = buffer.get(padded..).unwrap_or_default();
+    }
+
+    Some(entries)
 }
 
 impl From<&Request> for Reply {
@@ -52,24 +341,18 @@ impl From<&Request> for Reply {
 }
 
 impl IWrite for Reply {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let mut count = 0usize;
-
-        let len = self.header.as_bytes().len();
-
-        buffer[..len].copy_from_slice(self.header.as_bytes());
-
-        count += len;
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        let mut count = write_bytes(buffer, self.header.as_bytes())?;
 
         if let Some(ref mut op) = self.operation {
-            count += op.write(&mut buffer[count..]);
+            count += op.write(&mut buffer[count..])?;
         }
 
         // Update the header length
-        let (header, _rest) = fuse_out_header::try_mut_from_prefix(buffer).unwrap();
+        let (header, _rest) = fuse_out_header::try_mut_from_prefix(buffer).map_err(|_e| Errno::EIO)?;
         header.len = count as u32;
 
-        count
+        Ok(count)
     }
 }
 
@@ -78,55 +361,29 @@ impl IWrite for Reply {
 pub struct DirectoryEntry {
     pub entry: fuse_dirent,
     /// Serialized as an array of bytes
-    pub name: String,
+    pub name: OsString,
 }
 
 impl IWrite for DirectoryEntry {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        self.entry.namelen = self.name.as_bytes().len() as u32;
-
-        let mut count = 0;
-        buffer[0..self.entry.as_bytes().len()].copy_from_slice(self.entry.as_bytes());
-        count += self.entry.as_bytes().len();
-        buffer[count..count + self.name.len()].copy_from_slice(self.name.as_bytes());
-        count += self.name.as_bytes().len();
-
-        // Align the output to 8 byte boundary
-        let r = count % 8;
-        if r > 0 {
-            let diff = 8 - r;
-            buffer[count..count + diff].fill(0);
-            count += diff;
-        }
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        self.entry.namelen = self.name.len() as u32;
 
-        count
+        write_dirent(buffer, self.entry.as_bytes(), self.name.as_bytes())
     }
 }
 
+#[derive(Debug)]
 pub struct DirectoryEntryPlus {
-    entry: fuse_direntplus,
-    name: OsString,
+    pub entry: fuse_direntplus,
+    /// Serialized as an array of bytes
+    pub name: OsString,
 }
 
 impl IWrite for DirectoryEntryPlus {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        self.entry.dirent.namelen = self.name.as_bytes().len() as u32;
-
-        let mut count = 0;
-        buffer[0..self.entry.as_bytes().len()].copy_from_slice(self.entry.as_bytes());
-        count += self.entry.as_bytes().len();
-        buffer[count..count + self.name.len()].copy_from_slice(self.name.as_bytes());
-        count += self.name.as_bytes().len();
-
-        // Align the output to 8 byte boundary
-        let r = count % 8;
-        if r > 0 {
-            let diff = 8 - r;
-            buffer[count..count + diff].fill(0);
-            count += diff;
-        }
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        self.entry.dirent.namelen = self.name.len() as u32;
 
-        count
+        write_dirent(buffer, self.entry.as_bytes(), self.name.as_bytes())
     }
 }
 
@@ -137,22 +394,18 @@ pub struct Lookup {
 }
 
 impl IWrite for Lookup {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct Forget {}
 
 impl IWrite for Forget {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
@@ -163,166 +416,184 @@ pub struct GetAttr {
 }
 
 impl IWrite for GetAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct SetAttr {
     pub arg: fuse_attr_out,
 }
 
 impl IWrite for SetAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+#[derive(Debug)]
 pub struct ReadLink {
-    pub data: String,
+    /// Target of the link. Need not be UTF-8.
+    pub data: OsString,
 }
 
 impl IWrite for ReadLink {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.data.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.data.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.data.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct SymLink {
     pub arg: fuse_entry_out,
 }
 
 impl IWrite for SymLink {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
-pub struct MkNod {}
+pub struct MkNod {
+    pub arg: fuse_entry_out,
+}
 
 impl IWrite for MkNod {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct MkDir {
     pub arg: fuse_entry_out,
 }
 
 impl IWrite for MkDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct Unlink {}
 
 impl IWrite for Unlink {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, Immutable, KnownLayout)]
+#[derive(Debug, IntoBytes, Immutable, KnownLayout)]
 #[repr(transparent)]
 pub struct RmDir {}
 
 impl IWrite for RmDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Rename {}
 
 impl IWrite for Rename {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Link {
     pub arg: fuse_entry_out,
 }
 
 impl IWrite for Link {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Open {
     pub arg: fuse_open_out,
 }
 
 impl IWrite for Open {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-pub struct Read {
-    pub data: Vec<u8>,
+/// File contents. Never more than the size the kernel asked for.
+#[derive(Debug)]
+pub enum Read {
+    /// Copied into the session's reply buffer
+    Data(Vec<u8>),
+    /// Written to the device along with the header in a single `writev`, without copying
+    Segments(Vec<Bytes>),
+    /// Up to `len` bytes of `fd` starting at `offset`; fewer at the end of the file.
+    ///
+    /// Spliced from `fd` into the device without passing through userspace when `FUSE_SPLICE_WRITE` was agreed in the
+    /// INIT handshake (see [crate::init::KernelConfig::flags]). Otherwise the session reads it on the blocking pool
+    /// before copying it into the reply buffer, so a file that is slow to read does not stall the runtime.
+    File { fd: Arc<OwnedFd>, offset: i64, len: u32 },
 }
 
 impl IWrite for Read {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.data.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.data.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        match self {
+            Read::Data(data) => write_bytes(buffer, data),
+            Read::Segments(segments) => {
+                let mut count = 0;
+                for segment in segments.iter() {
+                    count += write_bytes(&mut buffer[count..], segment)?;
+                }
+                Ok(count)
+            }
+            Read::File { fd, offset, len } => {
+                let dst = buffer.get_mut(..*len as usize).ok_or(Errno::EIO)?;
+                read_file(fd.as_fd(), *offset, dst)
+            }
+        }
+    }
+}
+
+/// Fill `dst` from `fd` starting at `offset`. Returns fewer bytes at the end of the file.
+///
+/// Blocks on the file. The session calls it on the blocking pool (see [Read::File]).
+pub(crate) fn read_file(fd: BorrowedFd<'_>, offset: i64, dst: &mut [u8]) -> Result<usize, Errno> {
+    let mut count = 0;
+    while count < dst.len() {
+        match nix::sys::uio::pread(fd, &mut dst[count..], offset + count as i64) {
+            Ok(0) => break,
+            Ok(n) => count += n,
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(std::io::Error::from(e).into()),
+        }
     }
+    Ok(count)
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Write {
     pub arg: fuse_write_out,
 }
 
 impl IWrite for Write {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
@@ -334,131 +605,134 @@ pub struct StatFs {
 }
 
 impl IWrite for StatFs {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Release {}
 
 impl IWrite for Release {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct FSync {}
 
 impl IWrite for FSync {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct SetXAttr {}
 
 impl IWrite for SetXAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-pub struct GetXAttr {
-    arg: fuse_getxattr_out,
-    data: Vec<u8>,
+/// Answer to [crate::messages::request::GetXAttr] and [crate::messages::request::ListXAttr]
+///
+/// The kernel first probes with a `size` of zero for the length of the value (or name list), then asks for it with
+/// room for `size` bytes. Answer either with [XAttr::Data]: the session turns it into the length for a probe, and
+/// into [Errno::ERANGE] if it does not fit. [XAttr::Size] answers a probe without producing the value.
+#[derive(Debug)]
+pub enum XAttr {
+    Size(u32),
+    Data(Vec<u8>),
 }
 
-impl IWrite for GetXAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let mut count = 0usize;
-        buffer[..self.arg.as_bytes().len()].copy_from_slice(self.arg.as_bytes());
-        count += self.arg.as_bytes().len();
+impl XAttr {
+    /// Apply the size-probe semantics for a request that asked for `size` bytes.
+    pub(crate) fn fit(&mut self, size: u32) -> Result<(), Errno> {
+        let len = match self {
+            XAttr::Size(len) => *len as usize,
+            XAttr::Data(data) => data.len(),
+        };
 
-        buffer[count..count + self.data.as_bytes().len()].copy_from_slice(self.data.as_bytes());
-        count += self.data.len();
+        if size == 0 {
+            *self = XAttr::Size(len as u32);
+            return Ok(());
+        }
 
-        count
+        match self {
+            _ if len > size as usize => Err(Errno::ERANGE),
+            // Only the length where the value was asked for
+            XAttr::Size(_) => Err(Errno::EIO),
+            XAttr::Data(_) => Ok(()),
+        }
     }
 }
 
-pub struct ListXAttr {
-    data: Vec<u8>,
-}
-
-impl IWrite for ListXAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.data.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.data.as_bytes());
-        count
+impl IWrite for XAttr {
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        match self {
+            XAttr::Size(size) => write_bytes(buffer, fuse_getxattr_out { size: *size, padding: 0 }.as_bytes()),
+            XAttr::Data(data) => write_bytes(buffer, data),
+        }
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+/// Value of an extended attribute. See [XAttr].
+pub type GetXAttr = XAttr;
+
+/// NUL separated list of extended attribute names. See [XAttr].
+pub type ListXAttr = XAttr;
+
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct RemoveXAttr {}
 
 impl IWrite for RemoveXAttr {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Flush {}
 
 impl IWrite for Flush {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Init {
     pub arg: fuse_init_out,
 }
 
 impl IWrite for Init {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct OpenDir {
     pub arg: fuse_open_out,
 }
 
 impl IWrite for OpenDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+#[derive(Debug)]
 pub struct ReadDir {
     /// list of directory entry names
     ///
@@ -467,373 +741,353 @@ pub struct ReadDir {
 }
 
 impl IWrite for ReadDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
+    /// Entries that do not fit the size the kernel asked for are left out. The kernel continues from the offset of
+    /// the last entry written.
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
         let mut count = 0usize;
 
         for entry in self.entries.as_mut_slice() {
-            count += entry.write(&mut buffer[count..]);
+            match entry.write(&mut buffer[count..]) {
+                Ok(len) => count += len,
+                Err(e) if count == 0 => return Err(e),
+                Err(_e) => break,
+            }
         }
 
-        count
+        Ok(count)
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct ReleaseDir {}
 
 impl IWrite for ReleaseDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct FSyncDir {}
 
 impl IWrite for FSyncDir {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct GetLk {
-    arg: fuse_lk_out,
+    pub arg: fuse_lk_out,
 }
 
 impl IWrite for GetLk {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct SetLk {
-    arg: fuse_lk_out,
+    pub arg: fuse_lk_out,
 }
 
 impl IWrite for SetLk {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Access {}
 
 impl IWrite for Access {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Create {
     pub arg: fuse_create_out,
 }
 
 impl IWrite for Create {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Interrupt {}
 
 impl IWrite for Interrupt {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct BMap {
-    arg: fuse_bmap_out,
+    pub arg: fuse_bmap_out,
 }
 
 impl IWrite for BMap {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Destroy {}
 
 impl IWrite for Destroy {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct IoCtl {
-    arg: fuse_ioctl_out,
+    pub arg: fuse_ioctl_out,
 }
 
 impl IWrite for IoCtl {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Poll {
     #[cfg(feature = "abi-7-11")]
-    arg: fuse_poll_out,
+    pub arg: fuse_poll_out,
 }
 
 impl IWrite for Poll {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct NotifyReply {}
 
 impl IWrite for NotifyReply {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct BatchForget {}
 
 impl IWrite for BatchForget {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct FAllocate {}
 
 impl IWrite for FAllocate {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+#[derive(Debug)]
 pub struct ReadDirPlus {
-    entries: Vec<DirectoryEntryPlus>,
+    pub entries: Vec<DirectoryEntryPlus>,
 }
 
 impl IWrite for ReadDirPlus {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
+    /// Same truncation as [ReadDir]
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
         let mut count = 0usize;
 
         for e in self.entries.as_mut_slice() {
-            count += e.write(&mut buffer[count..]);
+            match e.write(&mut buffer[count..]) {
+                Ok(len) => count += len,
+                Err(e) if count == 0 => return Err(e),
+                Err(_e) => break,
+            }
         }
-        count
+
+        Ok(count)
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Rename2 {}
 
 impl IWrite for Rename2 {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Lseek {
     pub arg: fuse_lseek_out,
 }
 
 impl IWrite for Lseek {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct CopyFileRange {
     pub arg: fuse_write_out,
 }
 
 impl IWrite for CopyFileRange {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct SetVolName {}
 
 impl IWrite for SetVolName {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 #[cfg(target_os = "macos")]
 pub struct GetXTimes {
-    arg: fuse_getxtimes_out,
+    pub arg: fuse_getxtimes_out,
 }
 
 #[cfg(target_os = "macos")]
 impl IWrite for GetXTimes {
-    fn write_too(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct Exchange {}
 
 impl IWrite for Exchange {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+/// Success carries no payload
 #[cfg(feature = "abi-7-31")]
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct SetupMapping {}
 
+#[cfg(feature = "abi-7-31")]
 impl IWrite for SetupMapping {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+/// Success carries no payload
 #[cfg(feature = "abi-7-31")]
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct RemoveMapping {}
 
+#[cfg(feature = "abi-7-31")]
 impl IWrite for RemoveMapping {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+/// Success carries no payload
 #[cfg(feature = "abi-7-34")]
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct SyncFs {}
 
+#[cfg(feature = "abi-7-34")]
 impl IWrite for SyncFs {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
+/// Same payload as [Create]: the new entry and the open file handle
 #[cfg(feature = "abi-7-37")]
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
-pub struct TmpFile {}
+pub struct TmpFile {
+    pub arg: fuse_create_out,
+}
 
+#[cfg(feature = "abi-7-37")]
 impl IWrite for TmpFile {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
 #[cfg(feature = "abi-7-39")]
-#[derive(IntoBytes, KnownLayout, Immutable)]
+#[derive(Debug, IntoBytes, KnownLayout, Immutable)]
 #[repr(transparent)]
 pub struct StatX {
     pub arg: fuse_statx_out,
 }
 
+#[cfg(feature = "abi-7-39")]
 impl IWrite for StatX {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        write_bytes(buffer, self.as_bytes())
     }
 }
 
-#[derive(IntoBytes, KnownLayout, Immutable)]
-#[repr(transparent)]
-pub struct CuseInit {}
+/// Answer to `CUSE_INIT`: the limits and device number followed by the device info, `KEY=value` strings each
+/// terminated by a NUL (e.g. `DEVNAME=ttyFOO\0`)
+#[cfg(feature = "abi-7-12")]
+#[derive(Debug)]
+pub struct CuseInit {
+    pub arg: cuse_init_out,
+    pub dev_info: Vec<u8>,
+}
 
+#[cfg(feature = "abi-7-12")]
 impl IWrite for CuseInit {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = self.as_bytes().len();
-        buffer[0..count].copy_from_slice(self.as_bytes());
-        count
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        let count = write_bytes(buffer, self.arg.as_bytes())?;
+        Ok(count + write_bytes(&mut buffer[count..], &self.dev_info)?)
     }
 }
 
 #[repr(u32)]
+#[derive(Debug)]
 pub enum Operation {
     Lookup(Lookup) = 1,
     Forget(Forget) = 2,
@@ -908,13 +1162,19 @@ pub enum Operation {
     Exchange(Exchange) = 63,
 
     #[cfg(feature = "abi-7-12")]
-    #[allow(dead_code)]
     CuseInit(CuseInit) = 4096,
 }
 
+impl Operation {
+    /// Opcode of the request this operation answers
+    pub fn get_opcode(&self) -> u32 {
+        unsafe { *<*const _>::from(self).cast::<u32>() }
+    }
+}
+
 impl IWrite for Operation {
-    fn write(&mut self, buffer: &mut [u8]) -> usize {
-        let count = match self {
+    fn write(&mut self, buffer: &mut [u8]) -> Result<usize, Errno> {
+        match self {
             Operation::Lookup(lookup) => lookup.write(buffer),
             Operation::Forget(forget) => forget.write(buffer),
             Operation::GetAttr(get_attr) => get_attr.write(buffer),
@@ -970,15 +1230,294 @@ impl IWrite for Operation {
             Operation::TmpFile(op) => op.write(buffer),
             #[cfg(feature = "abi-7-39")]
             Operation::StatX(statx) => statx.write(buffer),
-        };
-
-        count
+        }
     }
 }
 
-/*
 #[cfg(test)]
 mod test {
+    use super::*;
+
+    const SIZE_HEADER: usize = size_of::<fuse_out_header>();
+
+    fn entry(ino: u64, name: &str) -> DirectoryEntry {
+        DirectoryEntry {
+            entry: fuse_dirent {
+                ino,
+                off: ino as i64,
+                namelen: 0,
+                typ: 0,
+            },
+            name: OsString::from(name),
+        }
+    }
+
+    #[test]
+    fn xattr_probe() {
+        let mut buffer = vec![0u8; 4096];
+
+        // Probe: the value becomes its length
+        let mut reply = Reply::new(1, 0, Some(Operation::GetXAttr(XAttr::Data(b"value".to_vec()))));
+        let count = reply.write_sized(&mut buffer, Some(0)).expect("write");
+        assert_eq!(count, SIZE_HEADER + size_of::<fuse_getxattr_out>());
+        assert_eq!(&buffer[SIZE_HEADER..SIZE_HEADER + 4], &5u32.to_ne_bytes());
+
+        // Fits
+        let mut reply = Reply::new(1, 0, Some(Operation::ListXAttr(XAttr::Data(b"user.a\0".to_vec()))));
+        let count = reply.write_sized(&mut buffer, Some(64)).expect("write");
+        assert_eq!(count, SIZE_HEADER + 7);
+
+        // Too small
+        let mut reply = Reply::new(1, 0, Some(Operation::GetXAttr(XAttr::Data(b"value".to_vec()))));
+        let count = reply.write_sized(&mut buffer, Some(2)).expect("write");
+        assert_eq!(count, SIZE_HEADER);
+        assert_eq!(reply.header.error, i32::from(Errno::ERANGE));
+    }
+
+    #[test]
+    fn read_dir_truncated() {
+        let mut buffer = vec![0u8; 4096];
+
+        // Each entry is 24 bytes of header plus the name padded to 8
+        let operation = Operation::ReadDir(ReadDir {
+            entries: vec![entry(1, "a"), entry(2, "b"), entry(3, "c")],
+        });
+        let mut reply = Reply::new(1, 0, Some(operation));
+        let count = reply.write_sized(&mut buffer, Some(70)).expect("write");
+        assert_eq!(count, SIZE_HEADER + 64);
+        assert_eq!(&buffer[..4], &(count as u32).to_ne_bytes());
+    }
+
+    #[cfg(feature = "abi-7-23")]
+    #[test]
+    fn older_minor() {
+        use zerocopy::FromZeros;
+
+        let mut buffer = vec![0u8; 4096];
+
+        // The open_out follows the shorter entry
+        let mut arg = fuse_create_out::new_zeroed();
+        arg.open.fh = 7;
+        let mut reply = Reply::new(1, 0, Some(Operation::Create(Create { arg })));
+        let count = reply.write_versioned(&mut buffer, None, 8).expect("write");
+        assert_eq!(count, SIZE_HEADER + FUSE_COMPAT_ENTRY_OUT_SIZE + size_of::<fuse_open_out>());
+        assert_eq!(&buffer[..4], &(count as u32).to_ne_bytes());
+        let offset = SIZE_HEADER + FUSE_COMPAT_ENTRY_OUT_SIZE;
+        assert_eq!(&buffer[offset..offset + 8], &7u64.to_ne_bytes());
+
+        let arg = fuse_attr_out::new_zeroed();
+        let mut reply = Reply::new(1, 0, Some(Operation::GetAttr(GetAttr { arg })));
+        let count = reply.write_versioned(&mut buffer, None, 8).expect("write");
+        assert_eq!(count, SIZE_HEADER + FUSE_COMPAT_ATTR_OUT_SIZE);
+
+        let arg = fuse_init_out::new_zeroed();
+        let mut reply = Reply::new(1, 0, Some(Operation::Init(Init { arg })));
+        let count = reply.write_versioned(&mut buffer, None, 22).expect("write");
+        assert_eq!(count, SIZE_HEADER + FUSE_COMPAT_22_INIT_OUT_SIZE);
+
+        // Current layout from 7.23 on
+        let arg = fuse_init_out::new_zeroed();
+        let mut reply = Reply::new(1, 0, Some(Operation::Init(Init { arg })));
+        let count = reply.write_versioned(&mut buffer, None, 23).expect("write");
+        assert_eq!(count, SIZE_HEADER + size_of::<fuse_init_out>());
+    }
+
+    #[test]
+    fn oversized_read() {
+        let mut buffer = vec![0u8; 4096];
+
+        let mut reply = Reply::new(1, 0, Some(Operation::Read(Read::Data(vec![0u8; 100]))));
+        assert_eq!(reply.write_sized(&mut buffer, Some(10)), Err(Errno::EIO));
+
+        // Larger than the buffer itself
+        let mut reply = Reply::new(1, 0, Some(Operation::Read(Read::Data(vec![0u8; 8192]))));
+        assert_eq!(reply.write_sized(&mut buffer, None), Err(Errno::EIO));
+    }
+
+    #[test]
+    fn read_segments_and_file() {
+        use std::io::Write;
+
+        let mut buffer = vec![0u8; 4096];
+
+        let segments = vec![Bytes::from_static(b"abc"), Bytes::from_static(b"defg")];
+        let mut reply = Reply::new(1, 0, Some(Operation::Read(Read::Segments(segments))));
+        assert_eq!(reply.write_sized(&mut buffer, Some(7)), Ok(SIZE_HEADER + 7));
+        assert_eq!(&buffer[SIZE_HEADER..SIZE_HEADER + 7], b"abcdefg");
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"0123456789").unwrap();
+        let fd = Arc::new(OwnedFd::from(file));
+
+        // Short at the end of the file
+        let mut reply = Reply::new(1, 0, Some(Operation::Read(Read::File { fd, offset: 6, len: 8 })));
+        assert_eq!(reply.write_sized(&mut buffer, Some(8)), Ok(SIZE_HEADER + 4));
+        assert_eq!(&buffer[SIZE_HEADER..SIZE_HEADER + 4], b"6789");
+    }
+
+    #[cfg(feature = "abi-7-40")]
+    #[test]
+    fn open_passthrough() {
+        use zerocopy::FromZeros;
+
+        let mut buffer = vec![0u8; 4096];
+
+        let mut arg = fuse_open_out::new_zeroed();
+        arg.fh = 7;
+        arg.set_backing_id(3);
+        let mut reply = Reply::new(1, 0, Some(Operation::Open(Open { arg })));
+        assert_eq!(reply.write(&mut buffer), Ok(SIZE_HEADER + 16));
+        assert_eq!(&buffer[SIZE_HEADER + 8..SIZE_HEADER + 12], &crate::constants::FOPEN_PASSTHROUGH.to_ne_bytes());
+        assert_eq!(&buffer[SIZE_HEADER + 12..SIZE_HEADER + 16], &3i32.to_ne_bytes());
+    }
+
+    mod round_trip {
+        use proptest::{collection::vec, prelude::*};
+        use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+        use crate::messages::fuse_abi::{fuse_opcode::*, *};
+        use crate::messages::reply::{IWrite, Reply};
+        use crate::messages::strategy::{self, arg, empty, Cases};
+
+        fn data() -> BoxedStrategy<Vec<u8>> {
+            vec(any::<u8>(), 0..64).boxed()
+        }
+
+        /// Directory entries of type `T`, each followed by its name padded to 8 bytes
+        fn dirents<T>(namelen: fn(&mut T) -> &mut u32) -> BoxedStrategy<Vec<u8>>
+        where
+            T: FromBytes + IntoBytes + Immutable + KnownLayout + 'static,
+        {
+            vec((arg::<T>(), vec(any::<u8>(), 1..16)), 0..4)
+                .prop_map(move |entries| {
+                    let mut buffer = Vec::new();
+                    for (bytes, name) in entries {
+                        let mut entry = T::read_from_bytes(&bytes).unwrap();
+                        *namelen(&mut entry) = name.len() as u32;
+                        buffer.extend_from_slice(entry.as_bytes());
+                        buffer.extend_from_slice(&name);
+                        buffer.resize(buffer.len().next_multiple_of(8), 0);
+                    }
+                    buffer
+                })
+                .boxed()
+        }
+
+        /// Opcode and payload of every successful reply under the enabled ABI
+        fn payloads() -> impl Strategy<Value = (u32, Vec<u8>)> {
+            let mut cases: Cases = vec![
+                (FUSE_LOOKUP, arg::<fuse_entry_out>()),
+                (FUSE_GETATTR, arg::<fuse_attr_out>()),
+                (FUSE_SETATTR, arg::<fuse_attr_out>()),
+                (FUSE_READLINK, data()),
+                (FUSE_SYMLINK, arg::<fuse_entry_out>()),
+                (FUSE_MKNOD, arg::<fuse_entry_out>()),
+                (FUSE_MKDIR, arg::<fuse_entry_out>()),
+                (FUSE_UNLINK, empty()),
+                (FUSE_RMDIR, empty()),
+                (FUSE_RENAME, empty()),
+                (FUSE_LINK, arg::<fuse_entry_out>()),
+                (FUSE_OPEN, arg::<fuse_open_out>()),
+                (FUSE_READ, data()),
+                (FUSE_WRITE, arg::<fuse_write_out>()),
+                (FUSE_STATFS, arg::<fuse_statfs_out>()),
+                (FUSE_RELEASE, empty()),
+                (FUSE_FSYNC, empty()),
+                (FUSE_SETXATTR, empty()),
+                (FUSE_GETXATTR, data()),
+                (FUSE_LISTXATTR, data()),
+                (FUSE_REMOVEXATTR, empty()),
+                (FUSE_FLUSH, empty()),
+                (FUSE_INIT, arg::<fuse_init_out>()),
+                (FUSE_OPENDIR, arg::<fuse_open_out>()),
+                (FUSE_READDIR, dirents(|x: &mut fuse_dirent| &mut x.namelen)),
+                (FUSE_RELEASEDIR, empty()),
+                (FUSE_FSYNCDIR, empty()),
+                (FUSE_GETLK, arg::<fuse_lk_out>()),
+                (FUSE_SETLK, empty()),
+                (FUSE_SETLKW, empty()),
+                (FUSE_ACCESS, empty()),
+                (FUSE_CREATE, arg::<fuse_create_out>()),
+                (FUSE_BMAP, arg::<fuse_bmap_out>()),
+                (FUSE_DESTROY, empty()),
+            ];
+
+            #[cfg(feature = "abi-7-11")]
+            cases.push((FUSE_IOCTL, arg::<fuse_ioctl_out>()));
+            #[cfg(feature = "abi-7-11")]
+            cases.push((FUSE_POLL, arg::<fuse_poll_out>()));
+            #[cfg(feature = "abi-7-19")]
+            cases.push((FUSE_FALLOCATE, empty()));
+            #[cfg(feature = "abi-7-21")]
+            cases.push((
+                FUSE_READDIRPLUS,
+                dirents(|x: &mut fuse_direntplus| &mut x.dirent.namelen),
+            ));
+            #[cfg(feature = "abi-7-23")]
+            cases.push((FUSE_RENAME2, empty()));
+            #[cfg(feature = "abi-7-24")]
+            cases.push((FUSE_LSEEK, arg::<fuse_lseek_out>()));
+            #[cfg(feature = "abi-7-28")]
+            cases.push((FUSE_COPY_FILE_RANGE, arg::<fuse_write_out>()));
+            #[cfg(feature = "abi-7-31")]
+            cases.push((FUSE_SETUPMAPPING, empty()));
+            #[cfg(feature = "abi-7-31")]
+            cases.push((FUSE_REMOVEMAPPING, empty()));
+            #[cfg(feature = "abi-7-34")]
+            cases.push((FUSE_SYNCFS, empty()));
+            #[cfg(feature = "abi-7-37")]
+            cases.push((FUSE_TMPFILE, arg::<fuse_create_out>()));
+            #[cfg(feature = "abi-7-39")]
+            cases.push((FUSE_STATX, arg::<fuse_statx_out>()));
+            #[cfg(feature = "abi-7-12")]
+            cases.push((
+                CUSE_INIT,
+                (arg::<cuse_init_out>(), data())
+                    .prop_map(|(arg, dev_info)| [arg, dev_info].concat())
+                    .boxed(),
+            ));
+
+            strategy::payloads(cases)
+        }
+
+        fn header(len: usize, error: i32, unique: u64) -> Vec<u8> {
+            fuse_out_header {
+                len: (size_of::<fuse_out_header>() + len) as u32,
+                error,
+                unique,
+            }
+            .as_bytes()
+            .to_vec()
+        }
+
+        proptest! {
+            #[test]
+            fn decode_write((opcode, payload) in payloads(), unique in any::<u64>()) {
+                let bytes = [header(payload.len(), 0, unique), payload].concat();
+
+                let mut reply = Reply::decode(&bytes, opcode).expect("decode");
+                let mut buffer = vec![0u8; 64 * 1024];
+                let count = reply.write(&mut buffer).expect("write");
+                prop_assert_eq!(&buffer[..count], &bytes[..]);
+            }
+
+            #[test]
+            fn decode_error((opcode, _payload) in payloads(), errno in 1..4096i32, unique in any::<u64>()) {
+                let bytes = header(0, -errno, unique);
+
+                let reply = Reply::decode(&bytes, opcode).expect("decode");
+                prop_assert_eq!(reply.header.error, -errno);
+                prop_assert!(reply.operation.is_none());
+            }
+
+            #[test]
+            fn decode_malformed(bytes in vec(any::<u8>(), 0..128), opcode in any::<u32>()) {
+                let _ = Reply::decode(&bytes, opcode);
+            }
+        }
+    }
+
+    /*
     use std::time::Duration;
 
     use libc::{S_IFDIR, S_IFREG};
@@ -1010,7 +1549,7 @@ mod test {
         let mut r = Reply::new(
             0xdeadbeef,
             0,
-            Some(Operation::Read(super::Read { data: data.to_vec() })),
+            Some(Operation::Read(super::Read::Data(data.to_vec()))),
         );
         r.update_length();
 
@@ -1226,7 +1765,7 @@ mod test {
             0x77, 0x6f, 0x72, 0x6c, 0x64, 0x2e, 0x72, 0x73,
         ];
 
-        let n1 = String::from("hello");
+        let n1 = OsString::from("hello");
 
         let e1 = DirectoryEntry {
             entry: fuse_dirent {
@@ -1238,7 +1777,7 @@ mod test {
             name: n1,
         };
 
-        let n2 = String::from("world.rs");
+        let n2 = OsString::from("world.rs");
         let e2 = DirectoryEntry {
             entry: fuse_dirent {
                 ino: 0xccdd,
@@ -1299,5 +1838,5 @@ mod test {
 
         assert_eq!(expected, buffer);
     }
+    */
 }
-*/
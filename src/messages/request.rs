@@ -1,25 +1,48 @@
+use std::ffi::{OsStr, OsString};
 use std::fmt::{self, Display};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(target_os = "linux")]
+use std::{io, os::fd::BorrowedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use log::error;
-use zerocopy::FromBytes;
+use bytes::Bytes;
+use log::{error, warn};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::error::TrySendError;
 
-use crate::error::Errno;
-use crate::messages::argument::get_vec;
+use crate::error::{Errno, ParseError, ParseErrorKind};
+use crate::messages::argument::{get_arg, get_arg_prefix, get_bytes, get_name, get_vec};
+#[cfg(feature = "abi-7-36")]
+use crate::messages::extension::Extensions;
 use crate::messages::fuse_abi::*;
-use crate::{messages::argument::get_string, ReplyTx};
-use tokio::sync::Mutex;
-
-use super::reply::NotifyReply;
+use crate::messages::reply::{self, Reply, XAttr};
+#[cfg(target_os = "linux")]
+use crate::pipe::Pipe;
+use crate::ReplyTx;
+use tokio_util::sync::CancellationToken;
+use zerocopy::IntoBytes;
 
 pub struct Request {
     pub header: fuse_in_header,
     pub operation: Operation,
     pub reply_to: ReplyTx,
+    /// Cancelled when the kernel interrupts this request (e.g. the calling process received a signal).
+    ///
+    /// The filesystem should stop work on the request and answer it, typically with [Errno::EINTR].
+    pub cancellation_token: CancellationToken,
+    /// Security context and group for the inode a `CREATE`, `MKDIR`, `MKNOD`, `SYMLINK` or `TMPFILE` makes. Empty
+    /// unless `FUSE_SECURITY_CTX` or `FUSE_CREATE_SUPP_GROUP` was agreed.
+    #[cfg(feature = "abi-7-36")]
+    pub extensions: Extensions,
+    /// Answers the request if it is dropped unanswered. Replies sent directly on [Request::reply_to] bypass it.
+    guard: ReplyGuard,
 }
 
 impl Request {
     pub fn from_op(op: Operation, reply_to: &ReplyTx) -> Self {
+        let opcode = op.get_opcode();
+
         Self {
             header: fuse_in_header {
                 uid: 0,
@@ -29,214 +52,356 @@ impl Request {
                 nodeid: 0,
                 unique: 0,
                 padding: 0,
-                opcode: op.get_opcode(),
+                #[cfg(feature = "abi-7-36")]
+                total_extlen: 0,
+                opcode,
             },
             operation: op,
             reply_to: reply_to.clone(),
+            cancellation_token: CancellationToken::new(),
+            #[cfg(feature = "abi-7-36")]
+            extensions: Extensions::default(),
+            guard: ReplyGuard::new(0, opcode, reply_to),
         }
     }
 
-    pub fn parse(buffer: &mut [u8], reply_to: &ReplyTx) -> Result<Self, Errno> {
-        let (header, rest) = match fuse_in_header::mut_from_prefix(buffer) {
-            Err(_e) => return Err(Errno::EIO),
-            Ok((h, r)) => (*h, r),
+    /// Decode a single message exactly as read from the device.
+    ///
+    /// `buffer` must hold exactly the bytes read: `fuse_in_header.len` is checked against its length. Never panics on
+    /// malformed input.
+    ///
+    /// Arguments are expected in the layout of [FUSE_KERNEL_MINOR_VERSION]. Copies `buffer`. See
+    /// [Request::parse_bytes] and [Request::parse_versioned].
+    pub fn parse(buffer: &[u8], reply_to: &ReplyTx) -> Result<Self, ParseError> {
+        Self::parse_bytes(&Bytes::copy_from_slice(buffer), reply_to)
+    }
+
+    /// Same as [Request::parse], but the payload of a `FUSE_WRITE` is a view of `buffer` rather than a copy.
+    pub fn parse_bytes(buffer: &Bytes, reply_to: &ReplyTx) -> Result<Self, ParseError> {
+        Self::parse_versioned(buffer, FUSE_KERNEL_MINOR_VERSION, reply_to)
+    }
+
+    /// Same as [Request::parse_bytes], for a kernel speaking protocol minor `minor`.
+    ///
+    /// Arguments that older kernels send shorter (e.g. `fuse_read_in` before 7.9, see the `FUSE_COMPAT_*` sizes in
+    /// [crate::messages::fuse_abi]) are read in their old size; the fields they lack are zero. Every other layout is
+    /// the one selected by the `abi-7-N` features.
+    pub fn parse_versioned(buffer: &Bytes, minor: u32, reply_to: &ReplyTx) -> Result<Self, ParseError> {
+        Self::decode(
+            buffer,
+            #[cfg(target_os = "linux")]
+            None,
+            minor,
+            reply_to,
+        )
+    }
+
+    /// Decode a `FUSE_WRITE` whose header and arguments are in `buffer` and whose payload was left in a pipe.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn parse_spliced(
+        buffer: &Bytes,
+        payload: SplicedPayload,
+        minor: u32,
+        reply_to: &ReplyTx,
+    ) -> Result<Self, ParseError> {
+        Self::decode(buffer, Some(payload), minor, reply_to)
+    }
+
+    fn decode(
+        buffer: &Bytes,
+        #[cfg(target_os = "linux")] mut payload: Option<SplicedPayload>,
+        minor: u32,
+        reply_to: &ReplyTx,
+    ) -> Result<Self, ParseError> {
+        #[cfg(target_os = "linux")]
+        let read = buffer.len() + payload.as_ref().map_or(0, SplicedPayload::len);
+        #[cfg(not(target_os = "linux"))]
+        let read = buffer.len();
+
+        let (header, rest) = match get_arg::<fuse_in_header>(buffer) {
+            None => {
+                return Err(ParseError {
+                    opcode: 0,
+                    unique: 0,
+                    kind: ParseErrorKind::ShortHeader { read },
+                })
+            }
+            Some((h, r)) => (h, r),
+        };
+
+        let error = |kind| ParseError {
+            opcode: header.opcode,
+            unique: header.unique,
+            kind,
+        };
+        let truncated = |what| error(ParseErrorKind::Truncated { what });
+        let missing_nul = |what| error(ParseErrorKind::MissingNul { what });
+        // Size of an argument that was extended in protocol minor `since`
+        let compat = |since: u32, old: usize, new: usize| if minor < since { old } else { new };
+
+        if header.len as usize != read {
+            return Err(error(ParseErrorKind::LengthMismatch {
+                header: header.len,
+                read,
+            }));
+        }
+
+        // Extensions take up the end of the message
+        #[cfg(feature = "abi-7-36")]
+        let (rest, extensions) = {
+            let len = rest
+                .len()
+                .checked_sub(header.total_extlen as usize * 8)
+                .ok_or_else(|| truncated("extensions"))?;
+            let (rest, extensions) = rest.split_at(len);
+            (rest, Extensions::parse(extensions).ok_or_else(|| truncated("extensions"))?)
         };
 
         let operation = match fuse_opcode::try_from(header.opcode) {
-            Err(_e) => {
-                error!("invalid op code {:?}", _e);
-                return Err(Errno::EIO);
-            }
+            Err(_e) => return Err(error(ParseErrorKind::UnknownOpcode)),
             Ok(opcode) => match opcode {
-                // TODO error handling - these will panic
                 fuse_opcode::FUSE_LOOKUP => {
-                    let (name, _rest) = get_string(rest);
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
                     Operation::Lookup(Lookup { name })
                 }
-                fuse_opcode::FUSE_FORGET => Operation::Forget(Forget {
-                    arg: *fuse_forget_in::ref_from_prefix(buffer).unwrap().0,
-                }),
+                fuse_opcode::FUSE_FORGET => {
+                    let (arg, _rest) = get_arg::<fuse_forget_in>(rest).ok_or_else(|| truncated("fuse_forget_in"))?;
+                    Operation::Forget(Forget { arg })
+                }
                 fuse_opcode::FUSE_GETATTR => Operation::GetAttr(GetAttr {
                     #[cfg(feature = "abi-7-9")]
-                    arg: *fuse_getattr_in::ref_from_prefix(rest).unwrap().0,
-                }),
-                fuse_opcode::FUSE_SETATTR => Operation::SetAttr(SetAttr {
-                    arg: fuse_setattr_in::ref_from_prefix(rest).unwrap().0.clone(),
+                    arg: get_arg_prefix::<fuse_getattr_in>(rest, compat(9, 0, size_of::<fuse_getattr_in>()))
+                        .ok_or_else(|| truncated("fuse_getattr_in"))?
+                        .0,
                 }),
+                fuse_opcode::FUSE_SETATTR => {
+                    let (arg, _rest) = get_arg::<fuse_setattr_in>(rest).ok_or_else(|| truncated("fuse_setattr_in"))?;
+                    Operation::SetAttr(SetAttr { arg })
+                }
                 fuse_opcode::FUSE_READLINK => Operation::ReadLink(ReadLink {}),
                 fuse_opcode::FUSE_SYMLINK => {
-                    let (name, rest) = get_string(rest);
-                    let (target, _rest) = get_string(rest);
+                    let (name, rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    let (target, _rest) = get_name(rest).ok_or_else(|| missing_nul("target"))?;
                     Operation::SymLink(SymLink { name, target })
                 }
                 fuse_opcode::FUSE_MKNOD => {
-                    let (arg, rest) = fuse_mknod_in::mut_from_prefix(rest).unwrap();
-                    let name = get_string(rest).0;
-                    Operation::MkNod(MkNod { arg: *arg, name })
+                    let size = compat(12, FUSE_COMPAT_MKNOD_IN_SIZE, size_of::<fuse_mknod_in>());
+                    let (arg, rest) =
+                        get_arg_prefix::<fuse_mknod_in>(rest, size).ok_or_else(|| truncated("fuse_mknod_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::MkNod(MkNod { arg, name })
                 }
                 fuse_opcode::FUSE_MKDIR => {
-                    let (arg, rest) = fuse_mkdir_in::mut_from_prefix(rest).unwrap();
-                    let (name, _rest) = get_string(rest);
-                    Operation::MkDir(MkDir { arg: *arg, name })
+                    let (arg, rest) = get_arg::<fuse_mkdir_in>(rest).ok_or_else(|| truncated("fuse_mkdir_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::MkDir(MkDir { arg, name })
                 }
                 fuse_opcode::FUSE_UNLINK => {
-                    let (name, _rest) = get_string(rest);
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
                     Operation::Unlink(Unlink { name })
                 }
                 fuse_opcode::FUSE_RMDIR => {
-                    let (name, _rest) = get_string(rest);
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
                     Operation::RmDir(RmDir { name })
                 }
                 fuse_opcode::FUSE_RENAME => {
-                    let (arg, rest) = fuse_rename_in::mut_from_prefix(rest).unwrap();
-                    let (name, rest) = get_string(rest);
-                    let (newname, _rest) = get_string(rest);
-                    Operation::Rename(Rename {
-                        arg: *arg,
-                        name,
-                        newname,
-                    })
+                    let (arg, rest) = get_arg::<fuse_rename_in>(rest).ok_or_else(|| truncated("fuse_rename_in"))?;
+                    let (name, rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    let (newname, _rest) = get_name(rest).ok_or_else(|| missing_nul("newname"))?;
+                    Operation::Rename(Rename { arg, name, newname })
                 }
                 fuse_opcode::FUSE_LINK => {
-                    let (arg, rest) = fuse_link_in::mut_from_prefix(rest).unwrap();
-                    let (name, _rest) = get_string(rest);
-                    Operation::Link(Link { arg: *arg, name })
+                    let (arg, rest) = get_arg::<fuse_link_in>(rest).ok_or_else(|| truncated("fuse_link_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::Link(Link { arg, name })
                 }
                 fuse_opcode::FUSE_OPEN => {
-                    let (arg, _rest) = fuse_open_in::ref_from_prefix(rest).unwrap();
-                    Operation::Open(Open { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_open_in>(rest).ok_or_else(|| truncated("fuse_open_in"))?;
+                    Operation::Open(Open { arg })
                 }
                 fuse_opcode::FUSE_READ => {
-                    let (arg, _rest) = fuse_read_in::ref_from_prefix(rest).unwrap();
-                    Operation::Read(Read { arg: *arg })
+                    let size = compat(9, FUSE_COMPAT_READ_IN_SIZE, size_of::<fuse_read_in>());
+                    let (arg, _rest) =
+                        get_arg_prefix::<fuse_read_in>(rest, size).ok_or_else(|| truncated("fuse_read_in"))?;
+                    Operation::Read(Read { arg })
                 }
                 fuse_opcode::FUSE_WRITE => {
-                    let (arg, rest2) = fuse_write_in::ref_from_prefix(rest).unwrap();
+                    let size = compat(9, FUSE_COMPAT_WRITE_IN_SIZE, size_of::<fuse_write_in>());
+                    let (arg, rest) =
+                        get_arg_prefix::<fuse_write_in>(rest, size).ok_or_else(|| truncated("fuse_write_in"))?;
+
+                    #[cfg(target_os = "linux")]
+                    if let Some(payload) = payload.take() {
+                        if payload.len() != arg.size as usize || !rest.is_empty() {
+                            return Err(truncated("write data"));
+                        }
+                        return Ok(Self::new(
+                            header,
+                            Operation::Write(Write {
+                                arg,
+                                data: Bytes::new(),
+                                pipe: Some(payload),
+                            }),
+                            reply_to,
+                        ));
+                    }
+
+                    let (data, _rest) = get_bytes(rest, arg.size as usize).ok_or_else(|| truncated("write data"))?;
                     Operation::Write(Write {
-                        arg: *arg,
-                        data: Arc::new(Mutex::new(rest2[..arg.size as usize].to_vec())),
+                        arg,
+                        data: buffer.slice_ref(data),
+                        #[cfg(target_os = "linux")]
+                        pipe: None,
                     })
                 }
                 fuse_opcode::FUSE_STATFS => Operation::StatFs(StatFs {}),
                 fuse_opcode::FUSE_RELEASE => {
-                    let (arg, _rest) = fuse_release_in::ref_from_prefix(rest).unwrap();
-                    Operation::Release(Release { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_release_in>(rest).ok_or_else(|| truncated("fuse_release_in"))?;
+                    Operation::Release(Release { arg })
                 }
                 fuse_opcode::FUSE_FSYNC => {
-                    let (arg, _rest) = fuse_fsync_in::ref_from_prefix(rest).unwrap();
-                    Operation::FSync(FSync { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_fsync_in>(rest).ok_or_else(|| truncated("fuse_fsync_in"))?;
+                    Operation::FSync(FSync { arg })
                 }
                 fuse_opcode::FUSE_SETXATTR => {
-                    let (arg, rest) = fuse_setxattr_in::mut_from_prefix(rest).unwrap();
-                    let (name, rest) = get_string(rest);
-                    let value = Arc::new(get_vec(rest, arg.size as usize));
-                    Operation::SetXAttr(SetXAttr { arg: *arg, name, value })
+                    let (arg, rest) = get_arg::<fuse_setxattr_in>(rest).ok_or_else(|| truncated("fuse_setxattr_in"))?;
+                    let (name, rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    let (value, _rest) = get_bytes(rest, arg.size as usize).ok_or_else(|| truncated("xattr value"))?;
+                    Operation::SetXAttr(SetXAttr {
+                        arg,
+                        name,
+                        value: Arc::new(value.to_vec()),
+                    })
                 }
                 fuse_opcode::FUSE_GETXATTR => {
-                    let (arg, rest) = fuse_getxattr_in::mut_from_prefix(rest).unwrap();
-                    let (name, _rest) = get_string(rest);
-                    Operation::GetXAttr(GetXAttr { arg: *arg, name })
+                    let (arg, rest) = get_arg::<fuse_getxattr_in>(rest).ok_or_else(|| truncated("fuse_getxattr_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::GetXAttr(GetXAttr { arg, name })
                 }
                 fuse_opcode::FUSE_LISTXATTR => {
-                    let (arg, _rest) = fuse_getxattr_in::ref_from_prefix(rest).unwrap();
-                    Operation::ListXAttr(ListXAttr { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_getxattr_in>(rest).ok_or_else(|| truncated("fuse_getxattr_in"))?;
+                    Operation::ListXAttr(ListXAttr { arg })
                 }
                 fuse_opcode::FUSE_REMOVEXATTR => {
-                    let (name, _rest) = get_string(rest);
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
                     Operation::RemoveXAttr(RemoveXAttr { name })
                 }
                 fuse_opcode::FUSE_FLUSH => {
-                    let (arg, _rest) = fuse_flush_in::ref_from_prefix(rest).unwrap();
-                    Operation::Flush(Flush { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_flush_in>(rest).ok_or_else(|| truncated("fuse_flush_in"))?;
+                    Operation::Flush(Flush { arg })
                 }
                 fuse_opcode::FUSE_INIT => {
-                    let (arg, _rest) = fuse_init_in::ref_from_prefix(rest).unwrap();
-                    Operation::Init(Init { arg: *arg })
+                    // No minor is agreed yet: kernels before 7.36 send the short form
+                    let size = rest.len().clamp(FUSE_COMPAT_INIT_IN_SIZE, size_of::<fuse_init_in>());
+                    let (arg, _rest) =
+                        get_arg_prefix::<fuse_init_in>(rest, size).ok_or_else(|| truncated("fuse_init_in"))?;
+                    Operation::Init(Init { arg })
                 }
                 fuse_opcode::FUSE_OPENDIR => {
-                    let (arg, _rest) = fuse_open_in::ref_from_prefix(rest).unwrap();
-                    Operation::OpenDir(OpenDir { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_open_in>(rest).ok_or_else(|| truncated("fuse_open_in"))?;
+                    Operation::OpenDir(OpenDir { arg })
                 }
                 fuse_opcode::FUSE_READDIR => {
-                    let (arg, _rest) = fuse_read_in::ref_from_prefix(rest).unwrap();
-                    Operation::ReadDir(ReadDir { arg: *arg })
+                    let size = compat(9, FUSE_COMPAT_READ_IN_SIZE, size_of::<fuse_read_in>());
+                    let (arg, _rest) =
+                        get_arg_prefix::<fuse_read_in>(rest, size).ok_or_else(|| truncated("fuse_read_in"))?;
+                    Operation::ReadDir(ReadDir { arg })
                 }
                 fuse_opcode::FUSE_RELEASEDIR => {
-                    let (arg, _rest) = fuse_release_in::ref_from_prefix(rest).unwrap();
-                    Operation::ReleaseDir(ReleaseDir { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_release_in>(rest).ok_or_else(|| truncated("fuse_release_in"))?;
+                    Operation::ReleaseDir(ReleaseDir { arg })
                 }
                 fuse_opcode::FUSE_FSYNCDIR => {
-                    let (arg, _rest) = fuse_fsync_in::ref_from_prefix(rest).unwrap();
-                    Operation::FSyncDir(FSyncDir { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_fsync_in>(rest).ok_or_else(|| truncated("fuse_fsync_in"))?;
+                    Operation::FSyncDir(FSyncDir { arg })
                 }
                 fuse_opcode::FUSE_GETLK => {
-                    let (arg, _rest) = fuse_lk_in::ref_from_prefix(rest).unwrap();
-                    Operation::GetLk(GetLk { arg: *arg })
+                    let size = compat(9, FUSE_COMPAT_LK_IN_SIZE, size_of::<fuse_lk_in>());
+                    let (arg, _rest) = get_arg_prefix::<fuse_lk_in>(rest, size).ok_or_else(|| truncated("fuse_lk_in"))?;
+                    Operation::GetLk(GetLk { arg })
                 }
                 fuse_opcode::FUSE_SETLK => {
-                    let (arg, _rest) = fuse_lk_in::ref_from_prefix(rest).unwrap();
-                    Operation::SetLk(SetLk { arg: *arg })
+                    let size = compat(9, FUSE_COMPAT_LK_IN_SIZE, size_of::<fuse_lk_in>());
+                    let (arg, _rest) = get_arg_prefix::<fuse_lk_in>(rest, size).ok_or_else(|| truncated("fuse_lk_in"))?;
+                    Operation::SetLk(SetLk { arg })
                 }
                 fuse_opcode::FUSE_SETLKW => {
-                    let (arg, _rest) = fuse_lk_in::ref_from_prefix(rest).unwrap();
-                    Operation::SetLkW(SetLkW { arg: *arg })
+                    let size = compat(9, FUSE_COMPAT_LK_IN_SIZE, size_of::<fuse_lk_in>());
+                    let (arg, _rest) = get_arg_prefix::<fuse_lk_in>(rest, size).ok_or_else(|| truncated("fuse_lk_in"))?;
+                    Operation::SetLkW(SetLkW { arg })
                 }
                 fuse_opcode::FUSE_ACCESS => {
-                    let (arg, _rest) = fuse_access_in::ref_from_prefix(rest).unwrap();
-                    Operation::Access(Access { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_access_in>(rest).ok_or_else(|| truncated("fuse_access_in"))?;
+                    Operation::Access(Access { arg })
                 }
                 fuse_opcode::FUSE_CREATE => {
-                    let (arg, rest) = fuse_create_in::mut_from_prefix(rest).unwrap();
-                    let (name, _rest) = get_string(rest);
-                    Operation::Create(Create { arg: *arg, name })
+                    let size = compat(12, FUSE_COMPAT_CREATE_IN_SIZE, size_of::<fuse_create_in>());
+                    let (arg, rest) =
+                        get_arg_prefix::<fuse_create_in>(rest, size).ok_or_else(|| truncated("fuse_create_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::Create(Create { arg, name })
                 }
                 fuse_opcode::FUSE_INTERRUPT => {
-                    let (arg, _rest) = fuse_interrupt_in::ref_from_prefix(rest).unwrap();
-                    Operation::Interrupt(Interrupt { arg: *arg })
+                    let (arg, _rest) =
+                        get_arg::<fuse_interrupt_in>(rest).ok_or_else(|| truncated("fuse_interrupt_in"))?;
+                    Operation::Interrupt(Interrupt { arg })
                 }
                 fuse_opcode::FUSE_BMAP => {
-                    let (arg, _rest) = fuse_bmap_in::ref_from_prefix(rest).unwrap();
-                    Operation::BMap(BMap { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_bmap_in>(rest).ok_or_else(|| truncated("fuse_bmap_in"))?;
+                    Operation::BMap(BMap { arg })
                 }
                 fuse_opcode::FUSE_DESTROY => Operation::Destroy(Destroy {}),
-                // TODO
                 #[cfg(feature = "abi-7-11")]
                 fuse_opcode::FUSE_IOCTL => {
-                    let (arg, rest) = fuse_ioctl_in::ref_from_prefix(rest).unwrap();
-                    let data = rest.to_vec();
+                    let (arg, rest) = get_arg::<fuse_ioctl_in>(rest).ok_or_else(|| truncated("fuse_ioctl_in"))?;
+                    let (data, _rest) = get_bytes(rest, arg.in_size as usize).ok_or_else(|| truncated("ioctl data"))?;
 
-                    Operation::IoCtl(IoCtl { arg: *arg, data })
+                    Operation::IoCtl(IoCtl {
+                        arg,
+                        data: data.to_vec(),
+                    })
                 }
                 #[cfg(feature = "abi-7-11")]
                 fuse_opcode::FUSE_POLL => {
-                    let (arg, _rest) = fuse_poll_in::ref_from_prefix(rest).unwrap();
-                    Operation::Poll(Poll { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_poll_in>(rest).ok_or_else(|| truncated("fuse_poll_in"))?;
+                    Operation::Poll(Poll { arg })
                 }
                 #[cfg(feature = "abi-7-15")]
-                fuse_opcode::FUSE_NOTIFY_REPLY => Operation::NotifyReply(NotifyReply {}),
+                fuse_opcode::FUSE_NOTIFY_REPLY => {
+                    let (arg, rest) =
+                        get_arg::<fuse_notify_retrieve_in>(rest).ok_or_else(|| truncated("fuse_notify_retrieve_in"))?;
+                    let (data, _rest) = get_bytes(rest, arg.size as usize).ok_or_else(|| truncated("retrieve data"))?;
+                    Operation::NotifyReply(NotifyReply {
+                        arg,
+                        data: data.to_vec(),
+                    })
+                }
                 #[cfg(feature = "abi-7-16")]
                 fuse_opcode::FUSE_BATCH_FORGET => {
-                    let (arg, rest) = fuse_batch_forget_in::ref_from_prefix(rest).unwrap();
-                    let nodes = get_vec::<fuse_forget_one>(rest, arg.count as usize);
-                    Operation::BatchForget(BatchForget { arg: *arg, nodes })
+                    let (arg, rest) =
+                        get_arg::<fuse_batch_forget_in>(rest).ok_or_else(|| truncated("fuse_batch_forget_in"))?;
+                    let (nodes, _rest) =
+                        get_vec::<fuse_forget_one>(rest, arg.count as usize).ok_or_else(|| truncated("forget nodes"))?;
+                    Operation::BatchForget(BatchForget { arg, nodes })
                 }
                 #[cfg(feature = "abi-7-19")]
                 fuse_opcode::FUSE_FALLOCATE => {
-                    let (arg, _rest) = fuse_fallocate_in::ref_from_prefix(rest).unwrap();
-                    Operation::FAllocate(FAllocate { arg: *arg })
+                    let (arg, _rest) =
+                        get_arg::<fuse_fallocate_in>(rest).ok_or_else(|| truncated("fuse_fallocate_in"))?;
+                    Operation::FAllocate(FAllocate { arg })
                 }
                 #[cfg(feature = "abi-7-21")]
                 fuse_opcode::FUSE_READDIRPLUS => {
-                    let (arg, _rest) = fuse_read_in::ref_from_prefix(rest).unwrap();
-                    Operation::ReadDirPlus(ReadDirPlus { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_read_in>(rest).ok_or_else(|| truncated("fuse_read_in"))?;
+                    Operation::ReadDirPlus(ReadDirPlus { arg })
                 }
                 #[cfg(feature = "abi-7-23")]
                 fuse_opcode::FUSE_RENAME2 => {
-                    let (arg, rest) = fuse_rename2_in::mut_from_prefix(rest).unwrap();
-                    let (name, rest) = get_string(rest);
-                    let (newname, _rest) = get_string(rest);
+                    let (arg, rest) = get_arg::<fuse_rename2_in>(rest).ok_or_else(|| truncated("fuse_rename2_in"))?;
+                    let (name, rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    let (newname, _rest) = get_name(rest).ok_or_else(|| missing_nul("newname"))?;
                     Operation::Rename2(Rename2 {
-                        arg: *arg,
+                        arg,
                         name,
                         newname,
                         old_parent: header.nodeid,
@@ -244,25 +409,45 @@ impl Request {
                 }
                 #[cfg(feature = "abi-7-24")]
                 fuse_opcode::FUSE_LSEEK => {
-                    let (arg, _rest) = fuse_lseek_in::ref_from_prefix(rest).unwrap();
-                    Operation::LSeek(LSeek { arg: *arg })
+                    let (arg, _rest) = get_arg::<fuse_lseek_in>(rest).ok_or_else(|| truncated("fuse_lseek_in"))?;
+                    Operation::LSeek(LSeek { arg })
                 }
                 #[cfg(feature = "abi-7-28")]
                 fuse_opcode::FUSE_COPY_FILE_RANGE => {
-                    let (arg, _rest) = fuse_copy_file_range_in::ref_from_prefix(rest).unwrap();
-                    Operation::CopyFileRange(CopyFileRange { arg: *arg })
+                    let (arg, _rest) =
+                        get_arg::<fuse_copy_file_range_in>(rest).ok_or_else(|| truncated("fuse_copy_file_range_in"))?;
+                    Operation::CopyFileRange(CopyFileRange { arg })
                 }
-                // TODO complete mappings
                 #[cfg(feature = "abi-7-31")]
-                fuse_opcode::FUSE_SETUPMAPPING => Operation::SetupMapping(SetupMapping::default()),
+                fuse_opcode::FUSE_SETUPMAPPING => {
+                    let (arg, _rest) =
+                        get_arg::<fuse_setupmapping_in>(rest).ok_or_else(|| truncated("fuse_setupmapping_in"))?;
+                    Operation::SetupMapping(SetupMapping { arg })
+                }
                 #[cfg(feature = "abi-7-31")]
-                fuse_opcode::FUSE_REMOVEMAPPING => Operation::RemoveMapping(RemoveMapping::default()),
+                fuse_opcode::FUSE_REMOVEMAPPING => {
+                    let (arg, rest) =
+                        get_arg::<fuse_removemapping_in>(rest).ok_or_else(|| truncated("fuse_removemapping_in"))?;
+                    let (mappings, _rest) = get_vec::<fuse_removemapping_one>(rest, arg.count as usize)
+                        .ok_or_else(|| truncated("removemapping entries"))?;
+                    Operation::RemoveMapping(RemoveMapping { arg, mappings })
+                }
                 #[cfg(feature = "abi-7-34")]
-                fuse_opcode::FUSE_SYNCFS => Operation::SyncFs(SyncFs::default()),
+                fuse_opcode::FUSE_SYNCFS => {
+                    let (arg, _rest) = get_arg::<fuse_syncfs_in>(rest).ok_or_else(|| truncated("fuse_syncfs_in"))?;
+                    Operation::SyncFs(SyncFs { arg })
+                }
                 #[cfg(feature = "abi-7-37")]
-                fuse_opcode::FUSE_TMPFILE => Operation::TmpFile(TmpFile::default()),
+                fuse_opcode::FUSE_TMPFILE => {
+                    let (arg, rest) = get_arg::<fuse_create_in>(rest).ok_or_else(|| truncated("fuse_create_in"))?;
+                    let (name, _rest) = get_name(rest).ok_or_else(|| missing_nul("name"))?;
+                    Operation::TmpFile(TmpFile { arg, name })
+                }
                 #[cfg(feature = "abi-7-39")]
-                fuse_opcode::FUSE_STATX => Operation::StatX(StatX::default()),
+                fuse_opcode::FUSE_STATX => {
+                    let (arg, _rest) = get_arg::<fuse_statx_in>(rest).ok_or_else(|| truncated("fuse_statx_in"))?;
+                    Operation::StatX(StatX { arg })
+                }
                 #[cfg(target_os = "macos")]
                 fuse_opcode::FUSE_SETVOLNAME => Operation::SetVolName(SetVolName {}),
                 #[cfg(target_os = "macos")]
@@ -270,43 +455,103 @@ impl Request {
                 #[cfg(target_os = "macos")]
                 fuse_opcode::FUSE_EXCHANGE => Operation::Exchange(Exchange {}),
                 fuse_opcode::CUSE_INIT => {
-                    let (arg, _rest) = fuse_init_in::ref_from_prefix(rest).unwrap();
-                    Operation::CuseInit(CuseInit { arg: *arg })
+                    let (arg, _rest) = get_arg::<cuse_init_in>(rest).ok_or_else(|| truncated("cuse_init_in"))?;
+                    Operation::CuseInit(CuseInit { arg })
                 }
             },
         };
 
-        let request = Request {
+        #[allow(unused_mut)]
+        let mut request = Self::new(header, operation, reply_to);
+        #[cfg(feature = "abi-7-36")]
+        {
+            request.extensions = extensions;
+        }
+
+        Ok(request)
+    }
+
+    fn new(header: fuse_in_header, operation: Operation, reply_to: &ReplyTx) -> Self {
+        Request {
             header,
             operation,
             reply_to: reply_to.clone(),
+            cancellation_token: CancellationToken::new(),
+            #[cfg(feature = "abi-7-36")]
+            extensions: Extensions::default(),
+            guard: ReplyGuard::new(header.unique, header.opcode, reply_to),
+        }
+    }
+
+    /// Encode the request as the kernel sends it. The inverse of [Request::parse].
+    ///
+    /// `opcode`, `len` and, from 7.36, `total_extlen` of the header are derived from the operation and extensions;
+    /// the other header fields are kept. The payload of a `FUSE_WRITE` left in a pipe (see [Write::pipe]) is not included.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(size_of::<fuse_in_header>());
+        buffer.extend_from_slice(self.header.as_bytes());
+        self.operation.encode(&mut buffer);
+
+        #[allow(unused_mut)]
+        let mut header = fuse_in_header {
+            opcode: self.operation.get_opcode(),
+            ..self.header
         };
+        #[cfg(feature = "abi-7-36")]
+        {
+            let start = buffer.len();
+            self.extensions.encode(&mut buffer);
+            header.total_extlen = ((buffer.len() - start) / 8) as u16;
+        }
+        header.len = buffer.len() as u32;
+        buffer[..size_of::<fuse_in_header>()].copy_from_slice(header.as_bytes());
 
-        Ok(request)
+        buffer
     }
 
     /// Send an error reply based on the [Error] value
     ///
     /// [Error] is automatically matches to appropriate [Errno]
     pub async fn send_error(&self, error: Errno) -> Result<(), Errno> {
-        let reply = super::reply::Reply {
-            header: fuse_out_header {
-                error: Errno::from(error).into(),
-                len: 0,
-                unique: self.header.unique,
-            },
-            operation: None,
-        };
+        self.send(Reply::new(self.header.unique, error.into(), None)).await
+    }
 
-        if let Err(_e) = self.reply_to.send(reply).await {
-            error!("channel send");
-            return Err(Errno::EIO);
+    /// Send a reply built by hand.
+    ///
+    /// Fails with [Errno::EINVAL] without sending anything if the request takes no reply (see
+    /// [Operation::expects_reply]), if `unique` does not match or if the reply answers a different opcode.
+    pub async fn send(&self, reply: Reply) -> Result<(), Errno> {
+        if !self.operation.expects_reply() {
+            error!(
+                "request {} opcode {} takes no reply",
+                self.header.unique, self.header.opcode
+            );
+            return Err(Errno::EINVAL);
         }
 
-        Ok(())
-    }
+        if reply.header.unique != self.header.unique {
+            error!(
+                "reply unique {} does not match request {}",
+                reply.header.unique, self.header.unique
+            );
+            return Err(Errno::EINVAL);
+        }
+
+        if let Some(opcode) = reply.operation.as_ref().map(reply::Operation::get_opcode) {
+            if opcode != self.header.opcode {
+                error!(
+                    "reply opcode {} does not match request {} opcode {}",
+                    opcode, self.header.unique, self.header.opcode
+                );
+                return Err(Errno::EINVAL);
+            }
+        }
+
+        if self.guard.answered.swap(true, Ordering::AcqRel) {
+            error!("request {} already answered", self.header.unique);
+            return Err(Errno::EINVAL);
+        }
 
-    pub async fn send(&self, reply: super::reply::Reply) -> Result<(), Errno> {
         if let Err(_e) = self.reply_to.send(reply).await {
             error!("channel send");
             return Err(Errno::EIO);
@@ -316,20 +561,244 @@ impl Request {
     }
 
     pub async fn send_ok(&self) -> Result<(), Errno> {
-        let reply = crate::messages::reply::Reply::from(self);
+        self.send(Reply::from(self)).await
+    }
 
-        if let Err(_e) = self.reply_to.send(reply).await {
-            error!("channel send");
-            return Err(Errno::EIO);
+    /// Answer the request with `errno` if it is dropped without a reply. Called by the session when it forwards
+    /// the request to the filesystem.
+    pub(crate) fn arm_reply_guard(&mut self, errno: Errno) {
+        if self.operation.expects_reply() {
+            self.guard.errno = Some(errno);
         }
+    }
 
-        Ok(())
+    /// Answer `LOOKUP`, `MKNOD`, `MKDIR`, `SYMLINK` or `LINK`
+    pub async fn reply_entry(&self, arg: fuse_entry_out) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::Lookup(_) => reply::Operation::Lookup(reply::Lookup { arg }),
+            Operation::MkNod(_) => reply::Operation::MkNod(reply::MkNod { arg }),
+            Operation::MkDir(_) => reply::Operation::MkDir(reply::MkDir { arg }),
+            Operation::SymLink(_) => reply::Operation::SymLink(reply::SymLink { arg }),
+            Operation::Link(_) => reply::Operation::Link(reply::Link { arg }),
+            _ => return Err(self.mismatch("entry")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `GETATTR` or `SETATTR`
+    pub async fn reply_attr(&self, arg: fuse_attr_out) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::GetAttr(_) => reply::Operation::GetAttr(reply::GetAttr { arg }),
+            Operation::SetAttr(_) => reply::Operation::SetAttr(reply::SetAttr { arg }),
+            _ => return Err(self.mismatch("attr")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `READ` with file contents or `READLINK` with the link target
+    pub async fn reply_data(&self, data: Vec<u8>) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::Read(_) => reply::Operation::Read(reply::Read::Data(data)),
+            Operation::ReadLink(_) => reply::Operation::ReadLink(reply::ReadLink {
+                data: OsString::from_vec(data),
+            }),
+            _ => return Err(self.mismatch("data")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `OPEN` or `OPENDIR`
+    pub async fn reply_open(&self, arg: fuse_open_out) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::Open(_) => reply::Operation::Open(reply::Open { arg }),
+            Operation::OpenDir(_) => reply::Operation::OpenDir(reply::OpenDir { arg }),
+            _ => return Err(self.mismatch("open")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `CREATE` or `TMPFILE`
+    pub async fn reply_create(&self, arg: fuse_create_out) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::Create(_) => reply::Operation::Create(reply::Create { arg }),
+            #[cfg(feature = "abi-7-37")]
+            Operation::TmpFile(_) => reply::Operation::TmpFile(reply::TmpFile { arg }),
+            _ => return Err(self.mismatch("create")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `WRITE` or `COPY_FILE_RANGE` with the number of bytes written
+    pub async fn reply_write(&self, size: u32) -> Result<(), Errno> {
+        let arg = fuse_write_out { size, padding: 0 };
+        let operation = match self.operation {
+            Operation::Write(_) => reply::Operation::Write(reply::Write { arg }),
+            #[cfg(feature = "abi-7-28")]
+            Operation::CopyFileRange(_) => reply::Operation::CopyFileRange(reply::CopyFileRange { arg }),
+            _ => return Err(self.mismatch("write")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer `STATFS`
+    pub async fn reply_statfs(&self, st: fuse_kstatfs) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::StatFs(_) => reply::Operation::StatFs(reply::StatFs {
+                arg: fuse_statfs_out { st },
+            }),
+            _ => return Err(self.mismatch("statfs")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    /// Answer a `GETXATTR` or `LISTXATTR` size probe with the length of the value
+    pub async fn reply_xattr_size(&self, size: u32) -> Result<(), Errno> {
+        self.reply_xattr(XAttr::Size(size)).await
+    }
+
+    /// Answer `GETXATTR` with the value or `LISTXATTR` with the NUL separated names
+    pub async fn reply_xattr_data(&self, data: Vec<u8>) -> Result<(), Errno> {
+        self.reply_xattr(XAttr::Data(data)).await
+    }
+
+    /// Answer a request whose successful reply carries no payload, such as `UNLINK`, `RELEASE` or `SETXATTR`
+    pub async fn reply_none(&self) -> Result<(), Errno> {
+        let empty = match self.operation {
+            Operation::Unlink(_)
+            | Operation::RmDir(_)
+            | Operation::Rename(_)
+            | Operation::Release(_)
+            | Operation::FSync(_)
+            | Operation::SetXAttr(_)
+            | Operation::RemoveXAttr(_)
+            | Operation::Flush(_)
+            | Operation::ReleaseDir(_)
+            | Operation::FSyncDir(_)
+            | Operation::SetLk(_)
+            | Operation::SetLkW(_)
+            | Operation::Access(_)
+            | Operation::Destroy(_) => true,
+            #[cfg(feature = "abi-7-19")]
+            Operation::FAllocate(_) => true,
+            #[cfg(feature = "abi-7-23")]
+            Operation::Rename2(_) => true,
+            #[cfg(feature = "abi-7-31")]
+            Operation::SetupMapping(_) | Operation::RemoveMapping(_) => true,
+            #[cfg(feature = "abi-7-34")]
+            Operation::SyncFs(_) => true,
+            #[cfg(target_os = "macos")]
+            Operation::SetVolName(_) | Operation::Exchange(_) => true,
+            _ => false,
+        };
+
+        if !empty {
+            return Err(self.mismatch("empty"));
+        }
+
+        self.send(Reply::from(self)).await
+    }
+
+    async fn reply_xattr(&self, xattr: XAttr) -> Result<(), Errno> {
+        let operation = match self.operation {
+            Operation::GetXAttr(_) => reply::Operation::GetXAttr(xattr),
+            Operation::ListXAttr(_) => reply::Operation::ListXAttr(xattr),
+            _ => return Err(self.mismatch("xattr")),
+        };
+
+        self.reply_with(operation).await
+    }
+
+    async fn reply_with(&self, operation: reply::Operation) -> Result<(), Errno> {
+        self.send(Reply::new(self.header.unique, 0, Some(operation))).await
+    }
+
+    fn mismatch(&self, kind: &str) -> Errno {
+        error!(
+            "{} reply does not answer request {} opcode {}",
+            kind, self.header.unique, self.header.opcode
+        );
+        Errno::EINVAL
+    }
+}
+
+/// Sends an error reply for a request dropped by the filesystem without an answer, which would otherwise leave the
+/// calling process blocked in the kernel
+struct ReplyGuard {
+    unique: u64,
+    opcode: u32,
+    reply_to: ReplyTx,
+    answered: AtomicBool,
+    /// `None` until armed with [Request::arm_reply_guard]
+    errno: Option<Errno>,
+}
+
+impl ReplyGuard {
+    fn new(unique: u64, opcode: u32, reply_to: &ReplyTx) -> Self {
+        Self {
+            unique,
+            opcode,
+            reply_to: reply_to.clone(),
+            answered: AtomicBool::new(false),
+            errno: None,
+        }
+    }
+}
+
+impl Drop for ReplyGuard {
+    fn drop(&mut self) {
+        let Some(errno) = self.errno else {
+            return;
+        };
+
+        if *self.answered.get_mut() {
+            return;
+        }
+
+        warn!(
+            "request {} opcode {} dropped unanswered, replying {}",
+            self.unique, self.opcode, errno
+        );
+
+        let reply = Reply::new(self.unique, errno.into(), None);
+        match self.reply_to.try_send(reply) {
+            Ok(()) => {}
+            // The session drains the channel: wait for room rather than leave the caller blocked in the kernel
+            Err(TrySendError::Full(reply)) => {
+                let reply_to = self.reply_to.clone();
+                let unique = self.unique;
+                match Handle::try_current() {
+                    Ok(handle) => {
+                        handle.spawn(async move {
+                            if reply_to.send(reply).await.is_err() {
+                                error!("request {} left unanswered: session stopped", unique);
+                            }
+                        });
+                    }
+                    Err(_) => {
+                        if reply_to.blocking_send(reply).is_err() {
+                            error!("request {} left unanswered: session stopped", unique);
+                        }
+                    }
+                }
+            }
+            Err(TrySendError::Closed(_)) => {
+                error!("request {} left unanswered: session stopped", self.unique);
+            }
+        }
     }
 }
 
 /// Lookup a directory to get its attributes
+#[derive(Debug)]
 pub struct Lookup {
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Forget about an inode.
@@ -340,93 +809,153 @@ pub struct Lookup {
 /// may ignore forget calls if the inodes do not need a limited lifetime.
 ///
 /// On unmount, it is not guaranteed that all referenced inodes will receive a forget message.
+#[derive(Debug)]
 pub struct Forget {
     pub arg: fuse_forget_in,
 }
 
 /// Get attributes for an inode
+#[derive(Debug)]
 pub struct GetAttr {
     #[cfg(feature = "abi-7-9")]
     pub arg: fuse_getattr_in,
 }
 
 /// Set attributes for an inode
+#[derive(Debug)]
 pub struct SetAttr {
     pub arg: fuse_setattr_in,
 }
 
 /// Read a symbolic link
+#[derive(Debug)]
 pub struct ReadLink {}
 
 /// Create a symbolic link
 #[derive(Debug)]
 pub struct SymLink {
-    pub name: String,
-    pub target: String,
+    pub name: OsString,
+    pub target: OsString,
 }
 
 /// Create a regular file, block device, fifo, or socket node
 ///
 /// See [man](https://man7.org/linux/man-pages/man2/mknod.2.html)
+#[derive(Debug)]
 pub struct MkNod {
     pub arg: fuse_mknod_in,
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Make a directory
+#[derive(Debug)]
 pub struct MkDir {
     pub arg: fuse_mkdir_in,
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Remove a file or directory
+#[derive(Debug)]
 pub struct Unlink {
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Remove a directory
+#[derive(Debug)]
 pub struct RmDir {
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Rename a file or directory
+#[derive(Debug)]
 pub struct Rename {
     pub arg: fuse_rename_in,
-    pub name: String,
-    pub newname: String,
+    pub name: OsString,
+    pub newname: OsString,
 }
 
 /// Create a hard link
+#[derive(Debug)]
 pub struct Link {
     pub arg: fuse_link_in,
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Open a file
 ///
 /// See [open man page](https://man7.org/linux/man-pages/man2/open.2.html)
+#[derive(Debug)]
 pub struct Open {
     pub arg: fuse_open_in,
 }
 
 /// Read bytes from a file
+#[derive(Debug)]
 pub struct Read {
     pub arg: fuse_read_in,
 }
 
 /// Write bytes to a file
+#[derive(Debug)]
 pub struct Write {
     pub arg: fuse_write_in,
-    // TODO make this [Option] so we can take it without copying it
-    pub data: Arc<Mutex<Vec<u8>>>,
+    /// View of the buffer the request was read into. The buffer is recycled once every view is dropped.
+    ///
+    /// Empty if the payload was left in [Write::pipe].
+    pub data: Bytes,
+    /// Payload left in a pipe by the splice receive path. See [crate::builder::Builder::set_splice_read].
+    #[cfg(target_os = "linux")]
+    pub pipe: Option<SplicedPayload>,
+}
+
+/// Payload of a `FUSE_WRITE` that never left the kernel, held in a pipe
+///
+/// Move it into the backing file with [SplicedPayload::splice_into], or copy it out with [SplicedPayload::into_vec].
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct SplicedPayload {
+    pipe: Pipe,
+    len: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl SplicedPayload {
+    pub(crate) fn new(pipe: Pipe, len: usize) -> Self {
+        Self { pipe, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Move the payload into `fd` at `offset`, or at its current position if `offset` is `None`, with `splice(2)`.
+    ///
+    /// Blocks like `write(2)` on `fd` would. Returns the number of bytes moved.
+    pub fn splice_into(self, fd: BorrowedFd<'_>, offset: Option<i64>) -> io::Result<usize> {
+        self.pipe.splice_into(fd, offset, self.len)
+    }
+
+    /// Copy the payload out of the pipe.
+    pub fn into_vec(self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0u8; self.len];
+        let count = self.pipe.drain(&mut data)?;
+        data.truncate(count);
+        Ok(data)
+    }
 }
 
 /// Get filesystem statistics
+#[derive(Debug)]
 pub struct StatFs {}
 
 /// Release an open file
 ///
 /// See [close man page](https://man7.org/linux/man-pages/man2/close.2.html)
+#[derive(Debug)]
 pub struct Release {
     pub arg: fuse_release_in,
 }
@@ -434,50 +963,59 @@ pub struct Release {
 /// Synchronize file contents
 ///
 /// See [fsync man page](https://man7.org/linux/man-pages/man2/fsync.2.html)
+#[derive(Debug)]
 pub struct FSync {
     pub arg: fuse_fsync_in,
 }
 
 /// Set an extended attribute
+#[derive(Debug)]
 pub struct SetXAttr {
     pub arg: fuse_setxattr_in,
-    pub name: String,
+    pub name: OsString,
     pub value: Arc<Vec<u8>>,
 }
 
+#[derive(Debug)]
 pub struct GetXAttr {
     pub arg: fuse_getxattr_in,
-    pub name: String,
+    pub name: OsString,
 }
 
 /// List extended attribute names
+#[derive(Debug)]
 pub struct ListXAttr {
     pub arg: fuse_getxattr_in,
 }
 
 /// Remove an extended attribute
+#[derive(Debug)]
 pub struct RemoveXAttr {
-    pub name: String,
+    pub name: OsString,
 }
 
 /// Flush a file to disk
 ///
 /// See [fuse](https://libfuse.github.io/doxygen/structfuse__operations.html#a6bfecd61ddd58f74820953ee23b19ef3)
+#[derive(Debug)]
 pub struct Flush {
     pub arg: fuse_flush_in,
 }
 
 /// Initialize a filesystem
+#[derive(Debug)]
 pub struct Init {
     pub arg: fuse_init_in,
 }
 
 /// Open a directory
+#[derive(Debug)]
 pub struct OpenDir {
     pub arg: fuse_open_in,
 }
 
 /// Read a directory
+#[derive(Debug)]
 pub struct ReadDir {
     pub arg: fuse_read_in,
 }
@@ -485,30 +1023,36 @@ pub struct ReadDir {
 /// Release an open directory
 ///
 /// For every [OpenDir] call there will be one [ReleaseDir] call
+#[derive(Debug)]
 pub struct ReleaseDir {
     pub arg: fuse_release_in,
 }
 
 /// Synchronize directory contents
+#[derive(Debug)]
 pub struct FSyncDir {
     pub arg: fuse_fsync_in,
 }
 
 /// Test for a POSIX lock
+#[derive(Debug)]
 pub struct GetLk {
     pub arg: fuse_lk_in,
 }
 
 /// Acquire, modify or release a POSIX lock
+#[derive(Debug)]
 pub struct SetLk {
     pub arg: fuse_lk_in,
 }
 
+#[derive(Debug)]
 pub struct SetLkW {
     pub arg: fuse_lk_in,
 }
 
 /// Check file access permissions
+#[derive(Debug)]
 pub struct Access {
     pub arg: fuse_access_in,
 }
@@ -517,7 +1061,7 @@ pub struct Access {
 #[derive(Debug)]
 pub struct Create {
     pub arg: fuse_create_in,
-    pub name: String,
+    pub name: OsString,
 }
 
 /// If a process issuing a FUSE filesystem request is interrupted, the
@@ -552,6 +1096,11 @@ pub struct Create {
 /// should reply to the [Interrupt] request with an [Errno::EAGAIN] error.
 /// In case (1) the [Interrupt] request will be requeued.  In case (2) the
 /// [Interrupt] reply will be ignored.
+///
+/// The session handles [Interrupt] itself and does not forward it: it cancels
+/// [Request::cancellation_token] of the original request, and applies the
+/// [Errno::EAGAIN] rule above when the original request is unknown.
+#[derive(Debug)]
 pub struct Interrupt {
     pub arg: fuse_interrupt_in,
 }
@@ -560,15 +1109,18 @@ pub struct Interrupt {
 ///
 /// **Note**: this makes sense only for block device backed filesystems
 /// mounted with the `blkdev` option.
+#[derive(Debug)]
 pub struct BMap {
     pub arg: fuse_bmap_in,
 }
 
 /// Delete the inode
+#[derive(Debug)]
 pub struct Destroy {}
 
 /// Control the device
 #[cfg(feature = "abi-7-11")]
+#[derive(Debug)]
 pub struct IoCtl {
     pub arg: fuse_ioctl_in,
     pub data: Vec<u8>,
@@ -576,12 +1128,25 @@ pub struct IoCtl {
 
 /// Pole the filesystem for change to the file
 #[cfg(feature = "abi-7-11")]
+#[derive(Debug)]
 pub struct Poll {
     pub arg: fuse_poll_in,
 }
 
+/// The kernel's answer to a retrieve notification sent with [crate::notify::Notifier::retrieve]
+///
+/// `header.unique` carries the `notify_unique` of the retrieve. Handled by the session; never forwarded to the
+/// filesystem and never replied to.
+#[cfg(feature = "abi-7-15")]
+#[derive(Debug)]
+pub struct NotifyReply {
+    pub arg: fuse_notify_retrieve_in,
+    pub data: Vec<u8>,
+}
+
 /// Batch forget
 #[cfg(feature = "abi-7-16")]
+#[derive(Debug)]
 pub struct BatchForget {
     pub arg: fuse_batch_forget_in,
     pub nodes: Vec<fuse_forget_one>,
@@ -591,22 +1156,25 @@ pub struct BatchForget {
 ///
 /// See [fallocate man](https://man7.org/linux/man-pages/man2/fallocate.2.html)
 #[cfg(feature = "abi-7-19")]
+#[derive(Debug)]
 pub struct FAllocate {
     pub arg: fuse_fallocate_in,
 }
 
 /// Read directory
 #[cfg(feature = "abi-7-21")]
+#[derive(Debug)]
 pub struct ReadDirPlus {
     pub arg: fuse_read_in,
 }
 
 /// Rename a file
 #[cfg(feature = "abi-7-23")]
+#[derive(Debug)]
 pub struct Rename2 {
     pub arg: fuse_rename2_in,
-    pub name: String,
-    pub newname: String,
+    pub name: OsString,
+    pub newname: OsString,
     pub old_parent: u64,
 }
 
@@ -614,6 +1182,7 @@ pub struct Rename2 {
 ///
 /// See [lseek man](https://man7.org/linux/man-pages/man2/lseek.2.html)
 #[cfg(feature = "abi-7-24")]
+#[derive(Debug)]
 pub struct LSeek {
     pub arg: fuse_lseek_in,
 }
@@ -621,61 +1190,78 @@ pub struct LSeek {
 /// Copy the specified range from the source inode to the destination inode
 #[cfg(feature = "abi-7-28")]
 #[repr(transparent)]
+#[derive(Debug)]
 pub struct CopyFileRange {
     pub arg: fuse_copy_file_range_in,
 }
 
+/// DAX: map `arg.len` bytes of the file at `arg.foffset` into the DAX window at `arg.moffset`
 #[cfg(feature = "abi-7-31")]
 #[repr(transparent)]
-#[derive(Default)]
+#[derive(Debug)]
 pub struct SetupMapping {
     pub arg: fuse_setupmapping_in,
 }
 
+/// DAX: remove the listed ranges from the DAX window
 #[cfg(feature = "abi-7-31")]
-#[derive(Default)]
+#[derive(Debug)]
 pub struct RemoveMapping {
     pub arg: fuse_removemapping_in,
+    /// `arg.count` entries
     pub mappings: Vec<fuse_removemapping_one>,
 }
 
+/// Flush the whole filesystem to stable storage (`syncfs(2)`)
 #[cfg(feature = "abi-7-34")]
-#[derive(Default)]
+#[repr(transparent)]
+#[derive(Debug)]
 pub struct SyncFs {
-    #[allow(unused)]
-    arg: fuse_syncfs_in,
+    pub arg: fuse_syncfs_in,
 }
 
+/// Create an unnamed file in directory `header.nodeid` (`O_TMPFILE`) and open it
+///
+/// Answered like [Create].
 #[cfg(feature = "abi-7-37")]
-#[derive(Default)]
-#[repr(transparent)]
+#[derive(Debug)]
 pub struct TmpFile {
-    arg: fuse_create_in,
+    pub arg: fuse_create_in,
+    /// Placeholder name chosen by the kernel. Carries no meaning.
+    pub name: OsString,
 }
 
 /// MacOS only: Rename the volume. Set `fuse_init_out.flags` during init to
 /// `FUSE_VOL_RENAME` to enable
 #[cfg(target_os = "macos")]
+#[derive(Debug)]
 pub struct SetVolName {
-    name: String,
+    name: OsString,
 }
 
 /// macOS only: Query extended times (bkuptime and crtime). Set fuse_init_out.flags
 /// during init to FUSE_XTIMES to enable
 #[cfg(target_os = "macos")]
+#[derive(Debug)]
 pub struct GetXTimes {}
 
 // API TODO: Consider rename2(RENAME_EXCHANGE)
 /// macOS only (undocumented)
 #[cfg(target_os = "macos")]
+#[derive(Debug)]
 pub struct Exchange {
     pub arg: fuse_exchange_in,
-    oldname: String,
-    newname: String,
+    oldname: OsString,
+    newname: OsString,
 }
 
+/// Extended attributes of inode `header.nodeid` (`statx(2)`)
+///
+/// `arg.sx_mask` lists the `STATX_*` fields requested, `arg.sx_flags` the `AT_STATX_*` sync flags. `arg.fh` is valid
+/// if `arg.getattr_flags` contains `FUSE_GETATTR_FH`.
+#[cfg(feature = "abi-7-39")]
 #[repr(transparent)]
-#[derive(Default)]
+#[derive(Debug)]
 pub struct StatX {
     pub arg: fuse_statx_in,
 }
@@ -684,10 +1270,11 @@ pub struct StatX {
 #[cfg(feature = "abi-7-12")]
 #[derive(Debug)]
 pub struct CuseInit {
-    pub arg: fuse_init_in,
+    pub arg: cuse_init_in,
 }
 
 #[repr(u32)]
+#[derive(Debug)]
 pub enum Operation {
     Lookup(Lookup) = 1,
     Forget(Forget) = 2,
@@ -733,7 +1320,6 @@ pub enum Operation {
     #[cfg(feature = "abi-7-11")]
     Poll(Poll)    = 40,
     #[cfg(feature = "abi-7-15")]
-    #[allow(dead_code)]
     NotifyReply(NotifyReply) = 41,
     #[cfg(feature = "abi-7-16")]
     BatchForget(BatchForget) = 42,
@@ -773,12 +1359,177 @@ impl Operation {
     pub fn get_opcode(&self) -> u32 {
         unsafe { *<*const _>::from(self).cast::<u32>() }
     }
+
+    /// Number of bytes the kernel has room for in the reply, for requests that carry one
+    pub fn reply_size(&self) -> Option<u32> {
+        match self {
+            Operation::Read(x) => Some(x.arg.size),
+            Operation::ReadDir(x) => Some(x.arg.size),
+            #[cfg(feature = "abi-7-21")]
+            Operation::ReadDirPlus(x) => Some(x.arg.size),
+            Operation::GetXAttr(x) => Some(x.arg.size),
+            Operation::ListXAttr(x) => Some(x.arg.size),
+            _ => None,
+        }
+    }
+
+    /// Append the arguments following the header to `buffer`
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        fn name(buffer: &mut Vec<u8>, name: &OsStr) {
+            buffer.extend_from_slice(name.as_bytes());
+            buffer.push(0);
+        }
+
+        match self {
+            Operation::Lookup(x) => name(buffer, &x.name),
+            Operation::Forget(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-9")]
+            Operation::GetAttr(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(not(feature = "abi-7-9"))]
+            Operation::GetAttr(_) => {}
+            Operation::SetAttr(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::ReadLink(_) => {}
+            Operation::SymLink(x) => {
+                name(buffer, &x.name);
+                name(buffer, &x.target);
+            }
+            Operation::MkNod(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            Operation::MkDir(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            Operation::Unlink(x) => name(buffer, &x.name),
+            Operation::RmDir(x) => name(buffer, &x.name),
+            Operation::Rename(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+                name(buffer, &x.newname);
+            }
+            Operation::Link(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            Operation::Open(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Read(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Write(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                buffer.extend_from_slice(&x.data);
+            }
+            Operation::StatFs(_) => {}
+            Operation::Release(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::FSync(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::SetXAttr(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+                buffer.extend_from_slice(&x.value);
+            }
+            Operation::GetXAttr(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            Operation::ListXAttr(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::RemoveXAttr(x) => name(buffer, &x.name),
+            Operation::Flush(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Init(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::OpenDir(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::ReadDir(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::ReleaseDir(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::FSyncDir(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::GetLk(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::SetLk(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::SetLkW(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Access(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Create(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            Operation::Interrupt(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::BMap(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            Operation::Destroy(_) => {}
+            #[cfg(feature = "abi-7-11")]
+            Operation::IoCtl(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                buffer.extend_from_slice(&x.data);
+            }
+            #[cfg(feature = "abi-7-11")]
+            Operation::Poll(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-15")]
+            Operation::NotifyReply(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                buffer.extend_from_slice(&x.data);
+            }
+            #[cfg(feature = "abi-7-16")]
+            Operation::BatchForget(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                buffer.extend_from_slice(x.nodes.as_bytes());
+            }
+            #[cfg(feature = "abi-7-19")]
+            Operation::FAllocate(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-21")]
+            Operation::ReadDirPlus(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-23")]
+            Operation::Rename2(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+                name(buffer, &x.newname);
+            }
+            #[cfg(feature = "abi-7-24")]
+            Operation::LSeek(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-28")]
+            Operation::CopyFileRange(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-31")]
+            Operation::SetupMapping(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-31")]
+            Operation::RemoveMapping(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                buffer.extend_from_slice(x.mappings.as_bytes());
+            }
+            #[cfg(feature = "abi-7-34")]
+            Operation::SyncFs(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(feature = "abi-7-37")]
+            Operation::TmpFile(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.name);
+            }
+            #[cfg(feature = "abi-7-39")]
+            Operation::StatX(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+            #[cfg(target_os = "macos")]
+            Operation::SetVolName(x) => name(buffer, &x.name),
+            #[cfg(target_os = "macos")]
+            Operation::GetXTimes(_) => {}
+            #[cfg(target_os = "macos")]
+            Operation::Exchange(x) => {
+                buffer.extend_from_slice(x.arg.as_bytes());
+                name(buffer, &x.oldname);
+                name(buffer, &x.newname);
+            }
+            #[cfg(feature = "abi-7-12")]
+            Operation::CuseInit(x) => buffer.extend_from_slice(x.arg.as_bytes()),
+        }
+    }
+
+    /// Whether the kernel waits for an answer. `FORGET` and `BATCH_FORGET` must never be answered; `INTERRUPT` and
+    /// `NOTIFY_REPLY` are handled by the session.
+    pub fn expects_reply(&self) -> bool {
+        match self {
+            Operation::Forget(_) | Operation::Interrupt(_) => false,
+            #[cfg(feature = "abi-7-15")]
+            Operation::NotifyReply(_) => false,
+            #[cfg(feature = "abi-7-16")]
+            Operation::BatchForget(_) => false,
+            _ => true,
+        }
+    }
 }
 
 #[allow(const_item_mutation)]
 #[cfg(test)]
 mod tests {
 
+    use crate::error::{Errno, ParseErrorKind};
     use crate::messages::request::Operation;
 
     use super::Request;
@@ -842,7 +1593,7 @@ mod tests {
     #[test]
     fn init() {
         let (reply_tx, _reply_rx) = crate::create_reply_channel();
-        let request = Request::parse(&mut INIT_REQUEST, &reply_tx).expect("parse");
+        let request = Request::parse(&INIT_REQUEST, &reply_tx).expect("parse");
         assert_eq!(request.header.len, 56);
         assert_eq!(request.header.len, 56);
         assert_eq!(request.header.opcode, 26);
@@ -864,7 +1615,7 @@ mod tests {
     #[test]
     fn mknod() {
         let (reply_tx, _reply_rx) = crate::create_reply_channel();
-        let request = Request::parse(&mut MKNOD_REQUEST, &reply_tx).expect("parse");
+        let request = Request::parse(&MKNOD_REQUEST, &reply_tx).expect("parse");
         #[cfg(not(feature = "abi-7-12"))]
         assert_eq!(req.header.len, 56);
         #[cfg(feature = "abi-7-12")]
@@ -884,6 +1635,503 @@ mod tests {
             _ => panic!("Unexpected request operation"),
         }
     }
+
+    #[test]
+    fn parse_errors() {
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let e = Request::parse(&INIT_REQUEST[..20], &reply_tx).err().expect("short header");
+        assert_eq!(e.kind, ParseErrorKind::ShortHeader { read: 20 });
+
+        let e = Request::parse(&INIT_REQUEST[..48], &reply_tx).err().expect("length mismatch");
+        assert_eq!(e.kind, ParseErrorKind::LengthMismatch { header: 56, read: 48 });
+        assert_eq!(e.unique, 0xdead_beef_baad_f00d);
+
+        // Header claims the truncated length: the init arguments are missing
+        let mut truncated = INIT_REQUEST[..48].to_vec();
+        truncated[..4].copy_from_slice(&48u32.to_ne_bytes());
+        let e = Request::parse(&truncated, &reply_tx).err().expect("truncated");
+        assert_eq!(e.kind, ParseErrorKind::Truncated { what: "fuse_init_in" });
+        assert_eq!(e.errno(), Errno::EIO);
+
+        // Lookup without a NUL after the name
+        let mut lookup = INIT_REQUEST[..40].to_vec();
+        lookup.extend_from_slice(b"foo");
+        lookup[..4].copy_from_slice(&43u32.to_ne_bytes());
+        lookup[4..8].copy_from_slice(&1u32.to_ne_bytes());
+        let e = Request::parse(&lookup, &reply_tx).err().expect("missing NUL");
+        assert_eq!(e.kind, ParseErrorKind::MissingNul { what: "name" });
+
+        let mut unknown = INIT_REQUEST.to_vec();
+        unknown[4..8].copy_from_slice(&9999u32.to_ne_bytes());
+        let e = Request::parse(&unknown, &reply_tx).err().expect("unknown opcode");
+        assert_eq!(e.kind, ParseErrorKind::UnknownOpcode);
+        assert_eq!(e.errno(), Errno::ENOSYS);
+    }
+
+    /// Frame `payload` behind the header of [INIT_REQUEST] with the given opcode
+    fn message(opcode: u32, payload: &[u8]) -> Vec<u8> {
+        let mut data = INIT_REQUEST[..40].to_vec();
+        data.extend_from_slice(payload);
+        let len = data.len() as u32;
+        data[..4].copy_from_slice(&len.to_ne_bytes());
+        data[4..8].copy_from_slice(&opcode.to_ne_bytes());
+        data
+    }
+
+    #[test]
+    fn lookup_latin1_name() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        // "café" in Latin-1: not valid UTF-8
+        let request = Request::parse(&message(1, b"caf\xe9\0"), &reply_tx).expect("parse");
+        match request.operation {
+            Operation::Lookup(x) => assert_eq!(x.name.as_bytes(), b"caf\xe9"),
+            _ => panic!("Unexpected request operation"),
+        }
+    }
+
+    #[cfg(feature = "abi-7-36")]
+    #[test]
+    fn extensions() {
+        use std::ffi::OsString;
+        use zerocopy::{FromZeros, IntoBytes};
+
+        use crate::messages::extension::{Extensions, SecurityContext};
+        use crate::messages::fuse_abi::fuse_mkdir_in;
+
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let extensions = Extensions {
+            security_context: Some(vec![SecurityContext {
+                name: OsString::from("security.selinux"),
+                value: b"system_u:object_r:container_file_t:s0\0".to_vec(),
+            }]),
+            #[cfg(feature = "abi-7-38")]
+            supplementary_groups: Some(vec![100]),
+        };
+        let mut payload = [fuse_mkdir_in::new_zeroed().as_bytes(), b"dir\0"].concat();
+        let start = payload.len();
+        extensions.encode(&mut payload);
+        let mut data = message(9, &payload);
+        let total_extlen = ((payload.len() - start) / 8) as u16;
+        data[36..38].copy_from_slice(&total_extlen.to_ne_bytes());
+
+        let request = Request::parse(&data, &reply_tx).expect("parse");
+        match &request.operation {
+            Operation::MkDir(x) => assert_eq!(x.name, "dir"),
+            _ => panic!("Unexpected request operation"),
+        }
+        assert_eq!(request.extensions, extensions);
+        assert_eq!(request.encode(), data);
+
+        // Extensions longer than the message
+        data[36..38].copy_from_slice(&64u16.to_ne_bytes());
+        let e = Request::parse(&data, &reply_tx).err().expect("overlong extensions");
+        assert_eq!(e.kind, ParseErrorKind::Truncated { what: "extensions" });
+    }
+
+    #[cfg(feature = "abi-7-31")]
+    #[test]
+    fn remove_mapping() {
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let mut payload = 2u32.to_ne_bytes().to_vec();
+        for (moffset, len) in [(0u64, 4096u64), (8192, 2048)] {
+            payload.extend_from_slice(&moffset.to_ne_bytes());
+            payload.extend_from_slice(&len.to_ne_bytes());
+        }
+
+        let request = Request::parse(&message(49, &payload), &reply_tx).expect("parse");
+        match request.operation {
+            Operation::RemoveMapping(x) => {
+                assert_eq!(x.arg.count, 2);
+                assert_eq!(x.mappings.len(), 2);
+                assert_eq!(x.mappings[1].moffset, 8192);
+                assert_eq!(x.mappings[1].len, 2048);
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+
+        // Count claims more entries than were sent
+        payload[..4].copy_from_slice(&3u32.to_ne_bytes());
+        let e = Request::parse(&message(49, &payload), &reply_tx).err().expect("truncated");
+        assert_eq!(e.kind, ParseErrorKind::Truncated { what: "removemapping entries" });
+    }
+
+    #[cfg(feature = "abi-7-39")]
+    #[test]
+    fn statx() {
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&1u32.to_ne_bytes()); // getattr_flags
+        payload.extend_from_slice(&0u32.to_ne_bytes()); // reserved
+        payload.extend_from_slice(&0x1234u64.to_ne_bytes()); // fh
+        payload.extend_from_slice(&0x2000u32.to_ne_bytes()); // sx_flags
+        payload.extend_from_slice(&0x7ffu32.to_ne_bytes()); // sx_mask
+
+        let request = Request::parse(&message(52, &payload), &reply_tx).expect("parse");
+        match request.operation {
+            Operation::StatX(x) => {
+                assert_eq!(x.arg.getattr_flags, 1);
+                assert_eq!(x.arg.fh, 0x1234);
+                assert_eq!(x.arg.sx_flags, 0x2000);
+                assert_eq!(x.arg.sx_mask, 0x7ff);
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+    }
+
+    #[test]
+    fn write_data_is_a_view() {
+        use crate::messages::fuse_abi::fuse_write_in;
+
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let mut payload = vec![0u8; size_of::<fuse_write_in>()];
+        payload[16..20].copy_from_slice(&5u32.to_ne_bytes()); // size
+        payload.extend_from_slice(b"hello");
+
+        let buffer = bytes::Bytes::from(message(16, &payload));
+        let request = Request::parse_bytes(&buffer, &reply_tx).expect("parse");
+        match request.operation {
+            Operation::Write(x) => {
+                assert_eq!(&x.data[..], b"hello");
+                assert_eq!(x.data.as_ptr(), buffer[buffer.len() - 5..].as_ptr());
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+    }
+
+    #[test]
+    fn older_minor() {
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        // Before 7.9 READ carries fh, offset, size and padding only
+        let mut payload = vec![0u8; 24];
+        payload[16..20].copy_from_slice(&4096u32.to_ne_bytes());
+        let buffer = bytes::Bytes::from(message(15, &payload));
+        let request = Request::parse_versioned(&buffer, 8, &reply_tx).expect("parse");
+        match request.operation {
+            Operation::Read(x) => assert_eq!(x.arg.size, 4096),
+            _ => panic!("Unexpected request operation"),
+        }
+        #[cfg(feature = "abi-7-9")]
+        assert!(Request::parse_bytes(&buffer, &reply_tx).is_err());
+
+        // Before 7.12 MKNOD carries mode and rdev only, followed by the name
+        let mut payload = vec![0u8; 8];
+        payload[..4].copy_from_slice(&0o100644u32.to_ne_bytes());
+        payload.extend_from_slice(b"foo\0");
+        let buffer = bytes::Bytes::from(message(8, &payload));
+        let request = Request::parse_versioned(&buffer, 11, &reply_tx).expect("parse");
+        match request.operation {
+            Operation::MkNod(x) => {
+                assert_eq!(x.arg.mode, 0o100644);
+                assert_eq!(x.name, "foo");
+            }
+            _ => panic!("Unexpected request operation"),
+        }
+
+        // Before 7.9 GETATTR has no argument
+        let buffer = bytes::Bytes::from(message(3, &[]));
+        let request = Request::parse_versioned(&buffer, 8, &reply_tx).expect("parse");
+        assert!(matches!(request.operation, Operation::GetAttr(_)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn write_payload_in_pipe() {
+        use crate::messages::fuse_abi::{fuse_in_header, fuse_write_in, FUSE_KERNEL_MINOR_VERSION};
+        use crate::pipe::Pipe;
+        use std::io::{Read, Seek};
+        use std::os::fd::AsFd;
+
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+        let mut arg = vec![0u8; size_of::<fuse_write_in>()];
+        arg[16..20].copy_from_slice(&5u32.to_ne_bytes()); // size
+        let mut head = message(16, &arg);
+        let len = (size_of::<fuse_in_header>() + size_of::<fuse_write_in>() + 5) as u32;
+        head[..4].copy_from_slice(&len.to_ne_bytes());
+
+        let pipe = Pipe::new().unwrap();
+        pipe.push(b"hello").unwrap();
+
+        let payload = super::SplicedPayload::new(pipe, 5);
+        let request = Request::parse_spliced(&bytes::Bytes::from(head), payload, FUSE_KERNEL_MINOR_VERSION, &reply_tx)
+            .expect("parse");
+        let Operation::Write(write) = request.operation else {
+            panic!("Unexpected request operation");
+        };
+        assert!(write.data.is_empty());
+
+        let mut file = tempfile::tempfile().unwrap();
+        assert_eq!(write.pipe.unwrap().splice_into(file.as_fd(), Some(0)).unwrap(), 5);
+
+        let mut contents = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+    }
+
+    #[tokio::test]
+    async fn reply_bound_to_opcode() {
+        use crate::messages::fuse_abi::fuse_read_in;
+        use crate::messages::reply;
+
+        let (reply_tx, mut reply_rx) = crate::create_reply_channel();
+
+        let lookup = Request::parse(&message(1, b"foo\0"), &reply_tx).expect("parse");
+        assert_eq!(lookup.reply_write(4).await, Err(Errno::EINVAL));
+        assert_eq!(lookup.reply_data(vec![1, 2, 3]).await, Err(Errno::EINVAL));
+        assert_eq!(lookup.reply_none().await, Err(Errno::EINVAL));
+        assert!(reply_rx.try_recv().is_err());
+
+        let read = Request::parse(&message(15, &[0u8; size_of::<fuse_read_in>()]), &reply_tx).expect("parse");
+        read.reply_data(vec![1, 2, 3]).await.expect("reply");
+
+        let reply = reply_rx.try_recv().expect("reply");
+        assert_eq!(reply.header.unique, read.header.unique);
+        assert_eq!(reply.header.error, 0);
+        assert!(matches!(reply.operation, Some(reply::Operation::Read(reply::Read::Data(ref data))) if data == &[1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn forget_takes_no_reply() {
+        let (reply_tx, mut reply_rx) = crate::create_reply_channel();
+
+        let forget = Request::parse(&message(2, &1u64.to_ne_bytes()), &reply_tx).expect("parse");
+        assert_eq!(forget.send_error(Errno::ENOSYS).await, Err(Errno::EINVAL));
+        assert_eq!(forget.send_ok().await, Err(Errno::EINVAL));
+        assert!(reply_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn reply_guard() {
+        let (reply_tx, mut reply_rx) = crate::create_reply_channel();
+
+        // Not forwarded by the session: dropped silently
+        drop(Request::parse(&message(1, b"foo\0"), &reply_tx).expect("parse"));
+        assert!(reply_rx.try_recv().is_err());
+
+        let mut request = Request::parse(&message(1, b"foo\0"), &reply_tx).expect("parse");
+        request.arm_reply_guard(Errno::EIO);
+        let unique = request.header.unique;
+        drop(request);
+
+        let reply = reply_rx.try_recv().expect("guard reply");
+        assert_eq!(reply.header.unique, unique);
+        assert_eq!(reply.header.error, Errno::EIO.into());
+
+        let mut request = Request::parse(&message(1, b"foo\0"), &reply_tx).expect("parse");
+        request.arm_reply_guard(Errno::EIO);
+        request.send_error(Errno::ENOENT).await.expect("reply");
+        assert_eq!(request.send_error(Errno::ENOENT).await, Err(Errno::EINVAL));
+        drop(request);
+
+        assert_eq!(reply_rx.try_recv().expect("reply").header.error, Errno::ENOENT.into());
+        assert!(reply_rx.try_recv().is_err());
+
+        let mut forget = Request::parse(&message(2, &1u64.to_ne_bytes()), &reply_tx).expect("parse");
+        forget.arm_reply_guard(Errno::EIO);
+        drop(forget);
+        assert!(reply_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn reply_guard_full_channel() {
+        use crate::messages::reply::Reply;
+
+        let (reply_tx, mut reply_rx) = crate::create_reply_channel();
+
+        for unique in 0..crate::SIZE_CHANNEL as u64 {
+            reply_tx.try_send(Reply::new(unique, 0, None)).expect("room");
+        }
+
+        let mut request = Request::parse(&message(1, b"foo\0"), &reply_tx).expect("parse");
+        request.arm_reply_guard(Errno::EIO);
+        let unique = request.header.unique;
+        drop(request);
+
+        for _ in 0..crate::SIZE_CHANNEL {
+            assert_eq!(reply_rx.recv().await.expect("reply").header.error, 0);
+        }
+
+        let reply = reply_rx.recv().await.expect("guard reply");
+        assert_eq!(reply.header.unique, unique);
+        assert_eq!(reply.header.error, Errno::EIO.into());
+    }
+
+    mod round_trip {
+        use proptest::{collection::vec, prelude::*};
+        use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+        use crate::messages::fuse_abi::{fuse_opcode::*, *};
+        use crate::messages::request::Request;
+        use crate::messages::strategy::{self, arg, empty, Cases};
+
+        /// NUL-terminated name
+        fn name() -> BoxedStrategy<Vec<u8>> {
+            vec(1..=255u8, 0..16)
+                .prop_map(|mut name| {
+                    name.push(0);
+                    name
+                })
+                .boxed()
+        }
+
+        /// `T` followed by as many bytes as the field of `T` set by `size` says
+        fn sized<T>(size: fn(&mut T, u32)) -> BoxedStrategy<Vec<u8>>
+        where
+            T: FromBytes + IntoBytes + Immutable + KnownLayout + 'static,
+        {
+            (arg::<T>(), vec(any::<u8>(), 0..64))
+                .prop_map(move |(bytes, data)| {
+                    let mut arg = T::read_from_bytes(&bytes).unwrap();
+                    size(&mut arg, data.len() as u32);
+                    [arg.as_bytes(), &data].concat()
+                })
+                .boxed()
+        }
+
+        /// `T` followed by `count` entries of `E`, as set by `count`
+        #[cfg(feature = "abi-7-16")]
+        fn counted<T, E>(count: fn(&mut T, u32)) -> BoxedStrategy<Vec<u8>>
+        where
+            T: FromBytes + IntoBytes + Immutable + KnownLayout + 'static,
+        {
+            (0..8usize)
+                .prop_flat_map(|n| (Just(n), arg::<T>(), vec(any::<u8>(), n * size_of::<E>())))
+                .prop_map(move |(n, bytes, entries)| {
+                    let mut arg = T::read_from_bytes(&bytes).unwrap();
+                    count(&mut arg, n as u32);
+                    [arg.as_bytes(), &entries].concat()
+                })
+                .boxed()
+        }
+
+        fn concat(parts: Vec<BoxedStrategy<Vec<u8>>>) -> BoxedStrategy<Vec<u8>> {
+            parts.prop_map(|parts| parts.concat()).boxed()
+        }
+
+        /// Opcode and arguments of every request the kernel sends under the enabled ABI
+        fn payloads() -> impl Strategy<Value = (u32, Vec<u8>)> {
+            let mut cases: Cases = vec![
+                (FUSE_LOOKUP, name()),
+                (FUSE_FORGET, arg::<fuse_forget_in>()),
+                (FUSE_SETATTR, arg::<fuse_setattr_in>()),
+                (FUSE_READLINK, empty()),
+                (FUSE_SYMLINK, concat(vec![name(), name()])),
+                (FUSE_MKNOD, concat(vec![arg::<fuse_mknod_in>(), name()])),
+                (FUSE_MKDIR, concat(vec![arg::<fuse_mkdir_in>(), name()])),
+                (FUSE_UNLINK, name()),
+                (FUSE_RMDIR, name()),
+                (FUSE_RENAME, concat(vec![arg::<fuse_rename_in>(), name(), name()])),
+                (FUSE_LINK, concat(vec![arg::<fuse_link_in>(), name()])),
+                (FUSE_OPEN, arg::<fuse_open_in>()),
+                (FUSE_READ, arg::<fuse_read_in>()),
+                (FUSE_WRITE, sized(|x: &mut fuse_write_in, size| x.size = size)),
+                (FUSE_STATFS, empty()),
+                (FUSE_RELEASE, arg::<fuse_release_in>()),
+                (FUSE_FSYNC, arg::<fuse_fsync_in>()),
+                (
+                    FUSE_SETXATTR,
+                    (arg::<fuse_setxattr_in>(), name(), vec(any::<u8>(), 0..64))
+                        .prop_map(|(arg, name, value)| {
+                            let mut arg = fuse_setxattr_in::read_from_bytes(&arg).unwrap();
+                            arg.size = value.len() as u32;
+                            [arg.as_bytes(), &name, &value].concat()
+                        })
+                        .boxed(),
+                ),
+                (FUSE_GETXATTR, concat(vec![arg::<fuse_getxattr_in>(), name()])),
+                (FUSE_LISTXATTR, arg::<fuse_getxattr_in>()),
+                (FUSE_REMOVEXATTR, name()),
+                (FUSE_FLUSH, arg::<fuse_flush_in>()),
+                (FUSE_INIT, arg::<fuse_init_in>()),
+                (FUSE_OPENDIR, arg::<fuse_open_in>()),
+                (FUSE_READDIR, arg::<fuse_read_in>()),
+                (FUSE_RELEASEDIR, arg::<fuse_release_in>()),
+                (FUSE_FSYNCDIR, arg::<fuse_fsync_in>()),
+                (FUSE_GETLK, arg::<fuse_lk_in>()),
+                (FUSE_SETLK, arg::<fuse_lk_in>()),
+                (FUSE_SETLKW, arg::<fuse_lk_in>()),
+                (FUSE_ACCESS, arg::<fuse_access_in>()),
+                (FUSE_CREATE, concat(vec![arg::<fuse_create_in>(), name()])),
+                (FUSE_INTERRUPT, arg::<fuse_interrupt_in>()),
+                (FUSE_BMAP, arg::<fuse_bmap_in>()),
+                (FUSE_DESTROY, empty()),
+                (CUSE_INIT, arg::<cuse_init_in>()),
+            ];
+
+            #[cfg(feature = "abi-7-9")]
+            cases.push((FUSE_GETATTR, arg::<fuse_getattr_in>()));
+            #[cfg(not(feature = "abi-7-9"))]
+            cases.push((FUSE_GETATTR, empty()));
+            #[cfg(feature = "abi-7-11")]
+            cases.push((FUSE_IOCTL, sized(|x: &mut fuse_ioctl_in, size| x.in_size = size)));
+            #[cfg(feature = "abi-7-11")]
+            cases.push((FUSE_POLL, arg::<fuse_poll_in>()));
+            #[cfg(feature = "abi-7-15")]
+            cases.push((
+                FUSE_NOTIFY_REPLY,
+                sized(|x: &mut fuse_notify_retrieve_in, size| x.size = size),
+            ));
+            #[cfg(feature = "abi-7-16")]
+            cases.push((
+                FUSE_BATCH_FORGET,
+                counted::<fuse_batch_forget_in, fuse_forget_one>(|x, count| x.count = count),
+            ));
+            #[cfg(feature = "abi-7-19")]
+            cases.push((FUSE_FALLOCATE, arg::<fuse_fallocate_in>()));
+            #[cfg(feature = "abi-7-21")]
+            cases.push((FUSE_READDIRPLUS, arg::<fuse_read_in>()));
+            #[cfg(feature = "abi-7-23")]
+            cases.push((FUSE_RENAME2, concat(vec![arg::<fuse_rename2_in>(), name(), name()])));
+            #[cfg(feature = "abi-7-24")]
+            cases.push((FUSE_LSEEK, arg::<fuse_lseek_in>()));
+            #[cfg(feature = "abi-7-28")]
+            cases.push((FUSE_COPY_FILE_RANGE, arg::<fuse_copy_file_range_in>()));
+            #[cfg(feature = "abi-7-31")]
+            cases.push((FUSE_SETUPMAPPING, arg::<fuse_setupmapping_in>()));
+            #[cfg(feature = "abi-7-31")]
+            cases.push((
+                FUSE_REMOVEMAPPING,
+                counted::<fuse_removemapping_in, fuse_removemapping_one>(|x, count| x.count = count),
+            ));
+            #[cfg(feature = "abi-7-34")]
+            cases.push((FUSE_SYNCFS, arg::<fuse_syncfs_in>()));
+            #[cfg(feature = "abi-7-37")]
+            cases.push((FUSE_TMPFILE, concat(vec![arg::<fuse_create_in>(), name()])));
+            #[cfg(feature = "abi-7-39")]
+            cases.push((FUSE_STATX, arg::<fuse_statx_in>()));
+
+            strategy::payloads(cases)
+        }
+
+        proptest! {
+            #[test]
+            fn parse_encode((opcode, payload) in payloads(), header in arg::<fuse_in_header>()) {
+                let (reply_tx, _reply_rx) = crate::create_reply_channel();
+
+                let mut header = fuse_in_header::read_from_bytes(&header).unwrap();
+                header.len = (size_of::<fuse_in_header>() + payload.len()) as u32;
+                header.opcode = opcode;
+                #[cfg(feature = "abi-7-36")]
+                {
+                    header.total_extlen = 0;
+                }
+                let bytes = [header.as_bytes(), &payload].concat();
+
+                let request = Request::parse(&bytes, &reply_tx).expect("parse");
+                prop_assert_eq!(request.encode(), bytes);
+            }
+        }
+    }
 }
 
 /// ABI version
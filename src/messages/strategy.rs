@@ -0,0 +1,26 @@
+//! Proptest strategies shared by the round trip tests of [super::request] and [super::reply].
+
+use proptest::{collection::vec, prelude::*, strategy::Union};
+
+use crate::messages::fuse_abi::fuse_opcode;
+
+/// Payload strategy for each opcode
+pub(crate) type Cases = Vec<(fuse_opcode, BoxedStrategy<Vec<u8>>)>;
+
+/// Arbitrary bytes of an ABI struct
+pub(crate) fn arg<T>() -> BoxedStrategy<Vec<u8>> {
+    vec(any::<u8>(), size_of::<T>()).boxed()
+}
+
+/// No payload at all
+pub(crate) fn empty() -> BoxedStrategy<Vec<u8>> {
+    Just(Vec::new()).boxed()
+}
+
+/// Raw opcode and payload drawn from any of `cases`
+pub(crate) fn payloads(cases: Cases) -> impl Strategy<Value = (u32, Vec<u8>)> {
+    Union::new(cases.into_iter().map(|(opcode, payload)| {
+        let opcode = opcode as u32;
+        payload.prop_map(move |payload| (opcode, payload))
+    }))
+}
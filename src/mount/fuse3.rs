@@ -16,7 +16,7 @@ use tokio::fs::File;
 fn ensure_last_os_error() -> io::Error {
     let err = io::Error::last_os_error();
     match err.raw_os_error() {
-        Some(0) => io::Error::new(io::ErrorKind::Other, "Unspecified Error"),
+        Some(0) => io::Error::other("Unspecified Error"),
         _ => err,
     }
 }
@@ -61,3 +61,5 @@ impl Drop for Mount {
     }
 }
 unsafe impl Send for Mount {}
+// The session is only touched when the mount is dropped
+unsafe impl Sync for Mount {}
@@ -0,0 +1,176 @@
+//! Unsolicited notifications from the filesystem to the kernel.
+//!
+//! Notifications are sent straight to the kernel as a [fuse_out_header] with `unique` set to `0` and `error` set
+//! to the [fuse_notify_code], followed by the notification payload. They allow a filesystem whose backing store
+//! changes behind the kernel's back to invalidate (or populate) the kernel's caches.
+//!
+//! [Notifier::retrieve] is the only notification the kernel answers. The answer arrives as a `FUSE_NOTIFY_REPLY`
+//! request which the session routes back to the pending call rather than to the filesystem.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::{trace, warn};
+use tokio::sync::oneshot;
+use zerocopy::IntoBytes;
+
+use crate::{error::Errno, messages::fuse_abi::*, transport::Transport};
+
+/// Data returned by the kernel in response to [Notifier::retrieve]
+#[derive(Debug)]
+pub struct Retrieved {
+    /// Offset of the first byte of [Retrieved::data] within the file
+    pub offset: u64,
+    /// Cached contents. May be shorter than requested (or empty) if the kernel did not have the pages cached.
+    pub data: Vec<u8>,
+}
+
+/// Cloneable handle used to send notifications to the kernel
+///
+/// Obtained from [crate::session::Session::notifier].
+#[derive(Clone)]
+pub struct Notifier {
+    transport: Arc<dyn Transport>,
+    /// Outstanding retrieve notifications keyed by `notify_unique`
+    retrieves: Arc<Mutex<HashMap<u64, oneshot::Sender<Retrieved>>>>,
+    next_unique: Arc<AtomicU64>,
+}
+
+impl Notifier {
+    pub(crate) fn new(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            retrieves: Arc::new(Mutex::new(HashMap::new())),
+            next_unique: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Invalidate cached attributes and data of inode `ino`.
+    ///
+    /// Data is invalidated from `offset` for `len` bytes. A negative `offset` invalidates only the attributes. A
+    /// `len` of zero or less invalidates to the end of the file.
+    #[cfg(feature = "abi-7-12")]
+    pub async fn inval_inode(&self, ino: u64, offset: i64, len: i64) -> Result<(), Errno> {
+        let arg = fuse_notify_inval_inode_out { ino, off: offset, len };
+
+        self.send(fuse_notify_code::FUSE_NOTIFY_INVAL_INODE, &[arg.as_bytes()])
+            .await
+    }
+
+    /// Invalidate the directory entry `name` within directory `parent`, and the parent's attributes.
+    #[cfg(feature = "abi-7-12")]
+    pub async fn inval_entry(&self, parent: u64, name: &OsStr) -> Result<(), Errno> {
+        let arg = fuse_notify_inval_entry_out {
+            parent,
+            namelen: name.len() as u32,
+            padding: 0,
+        };
+
+        self.send(
+            fuse_notify_code::FUSE_NOTIFY_INVAL_ENTRY,
+            &[arg.as_bytes(), name.as_bytes(), &[0]],
+        )
+        .await
+    }
+
+    /// Tell the kernel that the entry `name` in directory `parent` referring to inode `child` was deleted.
+    ///
+    /// Unlike [Notifier::inval_entry] this also detaches mounted directories and drops the dentry of a file that is
+    /// still open.
+    #[cfg(feature = "abi-7-18")]
+    pub async fn delete(&self, parent: u64, child: u64, name: &OsStr) -> Result<(), Errno> {
+        let arg = fuse_notify_delete_out {
+            parent,
+            child,
+            namelen: name.len() as u32,
+            padding: 0,
+        };
+
+        self.send(
+            fuse_notify_code::FUSE_NOTIFY_DELETE,
+            &[arg.as_bytes(), name.as_bytes(), &[0]],
+        )
+        .await
+    }
+
+    /// Push `data` into the kernel's page cache for inode `ino` starting at `offset`.
+    #[cfg(feature = "abi-7-15")]
+    pub async fn store(&self, ino: u64, offset: u64, data: &[u8]) -> Result<(), Errno> {
+        let arg = fuse_notify_store_out {
+            nodeid: ino,
+            offset,
+            size: data.len() as u32,
+            padding: 0,
+        };
+
+        self.send(fuse_notify_code::FUSE_NOTIFY_STORE, &[arg.as_bytes(), data])
+            .await
+    }
+
+    /// Ask the kernel for up to `size` bytes of cached data of inode `ino` starting at `offset`.
+    ///
+    /// Resolves once the kernel answers with a `FUSE_NOTIFY_REPLY`.
+    #[cfg(feature = "abi-7-15")]
+    pub async fn retrieve(&self, ino: u64, offset: u64, size: u32) -> Result<Retrieved, Errno> {
+        let notify_unique = self.next_unique.fetch_add(1, Ordering::Relaxed);
+        let arg = fuse_notify_retrieve_out {
+            notify_unique,
+            nodeid: ino,
+            offset,
+            size,
+            padding: 0,
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.retrieves.lock().unwrap().insert(notify_unique, tx);
+
+        if let Err(e) = self
+            .send(fuse_notify_code::FUSE_NOTIFY_RETRIEVE, &[arg.as_bytes()])
+            .await
+        {
+            self.retrieves.lock().unwrap().remove(&notify_unique);
+            return Err(e);
+        }
+
+        rx.await.map_err(|_| Errno::EIO)
+    }
+
+    /// Route the kernel's answer to a [Notifier::retrieve] back to the caller.
+    #[cfg(feature = "abi-7-15")]
+    pub(crate) fn on_notify_reply(&self, notify_unique: u64, retrieved: Retrieved) {
+        match self.retrieves.lock().unwrap().remove(&notify_unique) {
+            Some(tx) => {
+                let _ = tx.send(retrieved);
+            }
+            None => warn!("notify reply for unknown retrieve {}", notify_unique),
+        }
+    }
+
+    async fn send(&self, code: fuse_notify_code, payload: &[&[u8]]) -> Result<(), Errno> {
+        trace!("notify {:?}", code);
+
+        let len = size_of::<fuse_out_header>() + payload.iter().map(|p| p.len()).sum::<usize>();
+        let header = fuse_out_header {
+            len: len as u32,
+            error: code as i32,
+            unique: 0,
+        };
+
+        let mut message = Vec::with_capacity(len);
+        message.extend_from_slice(header.as_bytes());
+        for part in payload {
+            message.extend_from_slice(part);
+        }
+
+        self.transport.send(&message).await?;
+
+        Ok(())
+    }
+}
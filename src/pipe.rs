@@ -0,0 +1,147 @@
+//! Non-blocking pipe used to move data between files and the FUSE device with `splice(2)`.
+//!
+//! Only available on Linux. A message must reach the device in a single `splice`, so the pipe is grown with
+//! `F_SETPIPE_SZ` to hold the largest message it carries.
+
+use std::{
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+};
+
+use nix::{
+    fcntl::{fcntl, splice, FcntlArg, OFlag, SpliceFFlags},
+    unistd::{pipe2, read, write},
+};
+
+#[derive(Debug)]
+pub(crate) struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+    /// Bytes the pipe holds before writes would block
+    capacity: usize,
+}
+
+impl Pipe {
+    pub(crate) fn new() -> io::Result<Self> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC | OFlag::O_NONBLOCK)?;
+        let capacity = fcntl(read.as_raw_fd(), FcntlArg::F_GETPIPE_SZ)? as usize;
+
+        Ok(Self { read, write, capacity })
+    }
+
+    /// Grow the pipe to hold at least `size` bytes. Fails if `size` exceeds `/proc/sys/fs/pipe-max-size`.
+    pub(crate) fn reserve(&mut self, size: usize) -> io::Result<()> {
+        if size > self.capacity {
+            self.capacity = fcntl(self.read.as_raw_fd(), FcntlArg::F_SETPIPE_SZ(size as i32))? as usize;
+        }
+
+        Ok(())
+    }
+
+    /// Append `bytes`. The pipe must have room for them.
+    pub(crate) fn push(&self, bytes: &[u8]) -> io::Result<()> {
+        match write(&self.write, bytes)? {
+            n if n == bytes.len() => Ok(()),
+            _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    /// Move up to `len` bytes of `fd` starting at `offset` into the pipe. Returns fewer at the end of the file.
+    pub(crate) fn splice_from(&self, fd: BorrowedFd<'_>, mut offset: i64, len: usize) -> io::Result<usize> {
+        let mut count = 0;
+
+        while count < len {
+            match splice(
+                fd.as_raw_fd(),
+                Some(&mut offset),
+                self.write.as_raw_fd(),
+                None,
+                len - count,
+                SpliceFFlags::SPLICE_F_MOVE | SpliceFFlags::SPLICE_F_NONBLOCK,
+            ) {
+                Ok(0) => break,
+                Ok(n) => count += n,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Move `len` bytes out of the pipe into `fd` at `offset`, or at its current position if `offset` is `None`.
+    pub(crate) fn splice_into(&self, fd: BorrowedFd<'_>, mut offset: Option<i64>, len: usize) -> io::Result<usize> {
+        let mut count = 0;
+
+        while count < len {
+            match splice(
+                self.read.as_raw_fd(),
+                None,
+                fd.as_raw_fd(),
+                offset.as_mut(),
+                len - count,
+                SpliceFFlags::SPLICE_F_MOVE,
+            ) {
+                Ok(0) => break,
+                Ok(n) => count += n,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Read everything the pipe holds into `buffer`.
+    pub(crate) fn drain(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut count = 0;
+
+        while count < buffer.len() {
+            match read(self.read.as_raw_fd(), &mut buffer[count..]) {
+                Ok(0) | Err(nix::errno::Errno::EAGAIN) => break,
+                Ok(n) => count += n,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Fill `buffer` from the pipe. Fails with [io::ErrorKind::UnexpectedEof] if the pipe holds less.
+    pub(crate) fn drain_exact(&self, buffer: &mut [u8]) -> io::Result<()> {
+        match self.drain(buffer)? {
+            count if count == buffer.len() => Ok(()),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    pub(crate) fn reader(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+
+    pub(crate) fn writer(&self) -> BorrowedFd<'_> {
+        self.write.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn splice_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"0123456789").unwrap();
+
+        let mut pipe = Pipe::new().unwrap();
+        pipe.reserve(128 * 1024).unwrap();
+        pipe.push(b"head").unwrap();
+        assert_eq!(pipe.splice_from(file.as_fd(), 4, 16).unwrap(), 6);
+
+        let mut buffer = [0u8; 32];
+        let count = pipe.drain(&mut buffer).unwrap();
+        assert_eq!(&buffer[..count], b"head456789");
+    }
+}
@@ -1,20 +1,62 @@
 use libc::{EAGAIN, EINTR, ENODEV, ENOENT};
-use std::io::Write;
-use tokio::select;
-use tokio::{fs::File, io::AsyncReadExt};
+use tokio::{select, sync::watch};
 use tokio_util::sync::CancellationToken;
 
-use crate::error::Errno;
+use std::{collections::HashMap, io::IoSlice, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+
+use crate::buffer::BufferPool;
+#[cfg(target_os = "linux")]
+use crate::device::Device;
+use crate::error::{Errno, ParseError, ParseErrorKind};
+use crate::in_flight::{Completion, InFlight, INTERRUPT_REQUEUE_DELAY};
+use crate::init::{negotiate, ConnectionInfo, KernelConfig, Negotiation};
+#[cfg(feature = "abi-7-12")]
+use crate::init::{negotiate_cuse, CuseConfig};
+#[cfg(feature = "abi-7-12")]
+use crate::messages::fuse_abi::cuse_init_in;
+use crate::messages::{reply, request::Operation};
+#[cfg(feature = "abi-7-15")]
+use crate::notify::Retrieved;
+#[cfg(target_os = "linux")]
+use crate::pipe::Pipe;
 use crate::{
     messages::{
+        fuse_abi::{fuse_init_in, fuse_opcode, fuse_out_header, FUSE_KERNEL_MINOR_VERSION},
         reply::{IWrite, Reply},
         request::Request,
     },
     mount::Mount,
+    notify::Notifier,
+    strace,
+    transport::Transport,
     ReplyRx, ReplyTx, RequestTx,
 };
 
-use log::{error, info, trace, warn};
+use log::{debug, error, info, trace, warn};
+use zerocopy::IntoBytes;
+
+/// `writev` takes at most `IOV_MAX` (1024) buffers, one of which holds the header
+const MAX_SEGMENTS: usize = 1023;
+
+/// Smallest `FUSE_WRITE` payload left in the pipe when splicing requests. Smaller ones are copied out.
+#[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+const SPLICE_THRESHOLD: usize = 4096;
+
+/// Receive the next request into `buffer`, or splice it from `device` into `pipe` if there is one.
+#[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+async fn receive(
+    transport: &dyn Transport,
+    device: Option<&Device>,
+    buffer: &mut [u8],
+    pipe: Option<&Pipe>,
+) -> std::io::Result<usize> {
+    match (device, pipe) {
+        (Some(device), Some(pipe)) => device.splice_to(pipe.writer(), buffer.len()).await,
+        _ => transport.receive(buffer).await,
+    }
+}
 
 /// Represents a single session between the kernel and a filesystem.
 ///
@@ -24,6 +66,11 @@ use log::{error, info, trace, warn};
 pub struct Session {
     pub(crate) cancellation_token: CancellationToken,
     pub(crate) outbound_fs_request_tx: RequestTx,
+    pub(crate) notifier: Notifier,
+    pub(crate) connection_info: watch::Receiver<Option<ConnectionInfo>>,
+    /// The first worker's device. `None` when the session runs on another [Transport].
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub(crate) device: Option<Arc<Device>>,
 }
 
 impl Session {
@@ -38,24 +85,107 @@ impl Session {
     pub fn get_outbound_fs_request_tx(&self) -> &RequestTx {
         &self.outbound_fs_request_tx
     }
+
+    /// Handle for sending cache invalidation and other notifications to the kernel
+    pub fn notifier(&self) -> Notifier {
+        self.notifier.clone()
+    }
+
+    /// Outcome of the INIT handshake, or `None` if the kernel has not sent `FUSE_INIT` yet
+    pub fn connection_info(&self) -> Option<ConnectionInfo> {
+        self.connection_info.borrow().clone()
+    }
+
+    /// Wait for the INIT handshake to complete.
+    ///
+    /// Fails with [Errno::ENODEV] if the session stops before the kernel sends `FUSE_INIT`.
+    pub async fn initialized(&self) -> Result<ConnectionInfo, Errno> {
+        let mut rx = self.connection_info.clone();
+        let info = rx
+            .wait_for(|info| info.is_some())
+            .await
+            .map_err(|_e| Errno::ENODEV)?
+            .clone();
+
+        Ok(info.unwrap())
+    }
+
+    /// Register `fd` as a backing file and return its id for [fuse_open_out::set_backing_id].
+    ///
+    /// The kernel then reads and writes the backing file directly for files opened with that id, without sending
+    /// `READ` or `WRITE`. Needs `FUSE_PASSTHROUGH` (see [crate::builder::Builder::set_passthrough]) and, on most
+    /// kernels, `CAP_SYS_ADMIN`. Fails with [Errno::ENOTTY] when the session does not run on the FUSE device.
+    ///
+    /// The registration holds a reference to the file until [Session::close_backing], so `fd` may be closed once
+    /// the open has been answered.
+    ///
+    /// [fuse_open_out::set_backing_id]: crate::messages::fuse_abi::fuse_open_out::set_backing_id
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub fn open_backing(&self, fd: impl std::os::fd::AsFd) -> Result<i32, Errno> {
+        let device = self.device.as_ref().ok_or(Errno::ENOTTY)?;
+
+        Ok(device.backing_open(fd.as_fd())?)
+    }
+
+    /// Drop a backing file registered with [Session::open_backing]. Files already open on it keep using it.
+    #[cfg(all(target_os = "linux", feature = "abi-7-40"))]
+    pub fn close_backing(&self, backing_id: i32) -> Result<(), Errno> {
+        let device = self.device.as_ref().ok_or(Errno::ENOTTY)?;
+
+        Ok(device.backing_close(backing_id)?)
+    }
 }
 
 /// Internal "actor" that represents a long-running process ferrying kernel requests to the filesystem and
 /// replies from the filesystem to the kernel.
+///
+/// A session runs one [Inner] per worker. Each worker reads from its own (cloned) device descriptor and answers the
+/// requests it read on that same descriptor. Workers of a session opened on another [Transport] share it.
 pub(crate) struct Inner {
-    pub(crate) _mount: Mount,
-    pub(crate) writer: std::fs::File,
+    /// Shared by all workers. Dropping the last reference unmounts the filesystem.
+    pub(crate) _mount: Option<Arc<Mount>>,
+    /// Carries both requests and replies
+    pub(crate) transport: Arc<dyn Transport>,
+    /// Same as [Inner::transport] when that is the device. Splicing is only possible then.
+    #[cfg(target_os = "linux")]
+    pub(crate) device: Option<Arc<Device>>,
+    /// Shared by all workers. Receives the answers to retrieve notifications.
+    pub(crate) notifier: Notifier,
+    /// What to ask for in the INIT handshake
+    pub(crate) kernel_config: KernelConfig,
+    /// Device to register when the kernel sends `CUSE_INIT`. Set for CUSE sessions only.
+    #[cfg(feature = "abi-7-12")]
+    pub(crate) cuse: Option<CuseConfig>,
+    /// Shared by all workers. Published once the INIT handshake completes.
+    pub(crate) connection_info: Arc<watch::Sender<Option<ConnectionInfo>>>,
+    /// Shared by all workers. Requests forwarded to the filesystem and not yet answered.
+    pub(crate) in_flight: Arc<InFlight>,
+    /// Sent for requests the filesystem drops without answering
+    pub(crate) unanswered_errno: Errno,
+    /// Deadlines keyed by opcode
+    pub(crate) deadlines: HashMap<u32, Duration>,
+    /// Sent for requests whose deadline passed
+    pub(crate) deadline_errno: Errno,
+    /// Shared by all workers. Buffers requests are read into.
+    pub(crate) pool: BufferPool,
+    /// Buffer the next request is read into
+    pub(crate) read_buffer: Vec<u8>,
+    /// Replies are serialized here
     pub(crate) buffer: Vec<u8>,
+    /// Carries `READ` replies spliced from a file. Created on first use.
+    #[cfg(target_os = "linux")]
+    pub(crate) pipe: Option<Pipe>,
+    /// Splice requests into [Inner::receive_pipe] once `FUSE_SPLICE_READ` is agreed
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    pub(crate) splice_read: bool,
+    /// Requests are spliced into this pipe rather than read when set
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    pub(crate) receive_pipe: Option<Pipe>,
     /// Channel on which we will send requests
     pub(crate) outbound_fs_request_tx: RequestTx,
     pub(crate) inbound_fs_reply_tx: ReplyTx,
     pub(crate) inbound_fs_reply_rx: ReplyRx,
     pub(crate) cancellation_token: CancellationToken,
-    /// Duplicate of the file descriptor used by Mount
-    ///
-    /// # Note
-    /// * [Option]al so we can implement a [Drop] that [std::mem]::forgets the file rather than double-closing
-    pub(crate) file: Option<File>,
 }
 
 impl Inner {
@@ -67,21 +197,31 @@ impl Inner {
         info!("started");
 
         while !self.cancellation_token.is_cancelled() || self.is_busy() {
+            #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+            self.prepare_splice_read();
+
+            #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+            let receive = receive(
+                self.transport.as_ref(),
+                self.device.as_deref(),
+                &mut self.read_buffer,
+                self.receive_pipe.as_ref(),
+            );
+            #[cfg(not(all(target_os = "linux", feature = "abi-7-14")))]
+            let receive = self.transport.receive(&mut self.read_buffer);
+
             select! {
                 _ = self.cancellation_token.cancelled(), if !self.cancellation_token.is_cancelled() => {
                 }
                 reply = self.inbound_fs_reply_rx.recv() => {
                    self.on_fs_reply(reply).await?;
                 }
-                read_result = self.file.as_mut().unwrap().read(&mut self.buffer), if !self.cancellation_token.is_cancelled() => {
+                read_result = receive, if !self.cancellation_token.is_cancelled() => {
                    self.on_read(&read_result).await?;
                 }
             }
         }
 
-        let file = self.file.take().unwrap();
-        std::mem::forget(file);
-
         info!("done");
 
         Ok(())
@@ -112,22 +252,178 @@ impl Inner {
                         self.cancellation_token.cancel();
                         return Ok(());
                     }
-                    // Unhandled
-                    _ => todo!(), // _ => return Err(e.into()),
+                    // Unhandled, e.g. EIO for a buffer the kernel finds too small. Stop the session rather than
+                    // leave the other workers serving a broken connection.
+                    _ => {
+                        error!("receive failed: {}", e);
+                        self.cancellation_token.cancel();
+                        return Err(std::io::Error::from_raw_os_error(e.raw_os_error().unwrap_or(libc::EIO)).into());
+                    }
+                }
+            }
+            Ok(bytes) => {
+                #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+                if self.receive_pipe.is_some() {
+                    return self.on_spliced(*bytes).await;
+                }
+
+                let buffer = std::mem::replace(&mut self.read_buffer, self.pool.acquire());
+                let message = self.pool.freeze(buffer, *bytes);
+
+                match Request::parse_versioned(&message, self.minor(), &self.inbound_fs_reply_tx) {
+                    Ok(request) => self.on_request(request).await?,
+                    Err(e) => self.on_parse_error(e).await?,
                 }
             }
-            Ok(_bytes) => {
-                let request = Request::parse(&mut self.buffer, &self.inbound_fs_reply_tx)?;
-                if let Err(_e) = self.outbound_fs_request_tx.send(request).await {
-                    error!("channel send");
-                    return Err(Errno::EIO);
+        }
+
+        Ok(())
+    }
+
+    /// Handle the requests the session answers itself and forward the rest to the filesystem.
+    pub(crate) async fn on_request(&mut self, mut request: Request) -> Result<(), Errno> {
+        debug!(target: strace::TARGET, "{}", strace::request(&request));
+
+        #[cfg(feature = "abi-7-15")]
+        if let Operation::NotifyReply(notify_reply) = &mut request.operation {
+            let retrieved = Retrieved {
+                offset: notify_reply.arg.offset,
+                data: std::mem::take(&mut notify_reply.data),
+            };
+            self.notifier.on_notify_reply(request.header.unique, retrieved);
+            return Ok(());
+        }
+
+        if let Operation::Init(init) = &request.operation {
+            let arg = init.arg;
+            return self.on_init(request.header.unique, &arg).await;
+        }
+
+        #[cfg(feature = "abi-7-12")]
+        if let Operation::CuseInit(init) = &request.operation {
+            let arg = init.arg;
+            return self.on_cuse_init(request.header.unique, &arg).await;
+        }
+
+        match &request.operation {
+            Operation::Interrupt(interrupt) => {
+                self.on_interrupt(request.header.unique, interrupt.arg.unique);
+                return Ok(());
+            }
+            // Never answered
+            Operation::Forget(_) => {}
+            #[cfg(feature = "abi-7-16")]
+            Operation::BatchForget(_) => {}
+            _ => {
+                request.cancellation_token = self
+                    .in_flight
+                    .register(request.header.unique, request.operation.reply_size());
+                request.arm_reply_guard(self.unanswered_errno);
+
+                if let Some(deadline) = self.deadlines.get(&request.header.opcode) {
+                    self.watch_deadline(request.header.unique, *deadline);
                 }
             }
         }
 
+        if let Err(_e) = self.outbound_fs_request_tx.send(request).await {
+            error!("channel send");
+            return Err(Errno::EIO);
+        }
+
         Ok(())
     }
 
+    /// Start splicing requests once `FUSE_SPLICE_READ` is agreed. Falls back to reading if the pipe can't be set up.
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    fn prepare_splice_read(&mut self) {
+        if !self.splice_read || self.receive_pipe.is_some() || self.device.is_none() {
+            return;
+        }
+
+        let agreed = self
+            .connection_info
+            .borrow()
+            .as_ref()
+            .is_some_and(|info| info.flags.contains(crate::init::InitFlags::SPLICE_READ));
+        if !agreed {
+            return;
+        }
+
+        // The pipe must hold the largest request. Above `/proc/sys/fs/pipe-max-size` that takes CAP_SYS_RESOURCE.
+        let size = self.read_buffer.len();
+        let pipe = Pipe::new().and_then(|mut pipe| pipe.reserve(size).map(|_| pipe));
+        match pipe {
+            Ok(pipe) => self.receive_pipe = Some(pipe),
+            Err(e) => {
+                warn!(
+                    "splice read disabled: cannot grow a pipe to {} bytes: {}. Lower max_write or raise pipe-max-size.",
+                    size, e
+                );
+                self.splice_read = false;
+            }
+        }
+    }
+
+    /// Decode a request of `bytes` bytes spliced into [Inner::receive_pipe].
+    ///
+    /// The payload of a large `FUSE_WRITE` stays in the pipe and is handed to the filesystem with the request; the
+    /// next request is spliced into a new pipe. Everything else is copied out into a pooled buffer. Splicing is only
+    /// agreed from 7.14 on, so the `fuse_write_in` has its current size.
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    async fn on_spliced(&mut self, bytes: usize) -> Result<(), Errno> {
+        use crate::messages::{
+            fuse_abi::{fuse_in_header, fuse_write_in},
+            request::SplicedPayload,
+        };
+        use zerocopy::FromBytes;
+
+        const SIZE_HEAD: usize = size_of::<fuse_in_header>() + size_of::<fuse_write_in>();
+
+        let pipe = self.receive_pipe.take().unwrap();
+        let mut buffer = std::mem::replace(&mut self.read_buffer, self.pool.acquire());
+
+        // Without its header the rest of the message can't be told apart from the next one
+        let head = size_of::<fuse_in_header>().min(bytes);
+        if let Err(e) = pipe.drain_exact(&mut buffer[..head]) {
+            error!("splice read failed on the header: {}", e);
+            self.cancellation_token.cancel();
+            return Err(e.into());
+        }
+        let header = fuse_in_header::read_from_prefix(&buffer[..head]).ok().map(|(header, _)| header);
+        let opcode = header.map_or(0, |header| header.opcode);
+
+        let result = if opcode == fuse_opcode::FUSE_WRITE as u32 && bytes >= SIZE_HEAD + SPLICE_THRESHOLD {
+            pipe.drain_exact(&mut buffer[head..SIZE_HEAD]).map(|_| {
+                let message = self.pool.freeze(buffer, SIZE_HEAD);
+                let payload = SplicedPayload::new(pipe, bytes - SIZE_HEAD);
+                Request::parse_spliced(&message, payload, self.minor(), &self.inbound_fs_reply_tx)
+            })
+        } else {
+            pipe.drain_exact(&mut buffer[head..bytes]).map(|_| {
+                self.receive_pipe = Some(pipe);
+                let message = self.pool.freeze(buffer, bytes);
+                Request::parse_versioned(&message, self.minor(), &self.inbound_fs_reply_tx)
+            })
+        };
+
+        match result {
+            Ok(Ok(request)) => self.on_request(request).await,
+            Ok(Err(e)) => self.on_parse_error(e).await,
+            // The rest of the request is lost with the pipe. Answer it and read from the device from now on.
+            Err(e) => {
+                error!("splice read failed: {}", e);
+                self.splice_read = false;
+                let e = ParseError {
+                    opcode,
+                    unique: header.map_or(0, |header| header.unique),
+                    kind: ParseErrorKind::Truncated { what: "spliced message" },
+                };
+                self.on_parse_error(e).await
+            }
+        }
+    }
+
     pub(crate) fn is_busy(&self) -> bool {
         !self.inbound_fs_reply_rx.is_closed() || !self.inbound_fs_reply_rx.is_empty()
     }
@@ -143,24 +439,350 @@ impl Inner {
             return Err(Errno::EIO);
         }
 
-        let mut reply = reply.unwrap();
+        let reply = reply.unwrap();
+
+        match self.in_flight.complete(reply.header.unique) {
+            Completion::Reply(reply_size) => self.write_reply(reply, reply_size).await,
+            Completion::Late => {
+                trace!("dropping late reply {}", reply.header.unique);
+                Ok(())
+            }
+        }
+    }
+
+    /// Answer a request that could not be decoded with [ParseError::errno] rather than tearing down the session.
+    pub(crate) async fn on_parse_error(&mut self, e: ParseError) -> Result<(), Errno> {
+        error!("unparseable request: {}", e);
+
+        if let ParseErrorKind::ShortHeader { .. } = e.kind {
+            return Ok(());
+        }
+
+        match fuse_opcode::try_from(e.opcode) {
+            // Never answered
+            Ok(fuse_opcode::FUSE_FORGET) | Ok(fuse_opcode::FUSE_INTERRUPT) => Ok(()),
+            #[cfg(feature = "abi-7-15")]
+            Ok(fuse_opcode::FUSE_NOTIFY_REPLY) => Ok(()),
+            #[cfg(feature = "abi-7-16")]
+            Ok(fuse_opcode::FUSE_BATCH_FORGET) => Ok(()),
+            _ => self.write_reply(Reply::new(e.unique, e.errno().into(), None), None).await,
+        }
+    }
+
+    /// Cancel the request targeted by the `FUSE_INTERRUPT` with unique `unique`.
+    ///
+    /// If the target is unknown, answer the interrupt with `EAGAIN` after [INTERRUPT_REQUEUE_DELAY] unless the target
+    /// shows up in the meantime. The kernel requeues the interrupt if the target is still outstanding and ignores the
+    /// reply otherwise.
+    pub(crate) fn on_interrupt(&mut self, unique: u64, target: u64) {
+        trace!("on_interrupt {}", target);
+
+        let Some(pending) = self.in_flight.interrupt(target) else {
+            return;
+        };
+
+        let transport = self.transport.clone();
+        let in_flight = self.in_flight.clone();
+
+        tokio::spawn(async move {
+            select! {
+                _ = pending.cancelled() => {}
+                _ = tokio::time::sleep(INTERRUPT_REQUEUE_DELAY) => {
+                    if !in_flight.expire(target, &pending) {
+                        return;
+                    }
+
+                    let header = fuse_out_header {
+                        len: size_of::<fuse_out_header>() as u32,
+                        error: Errno::EAGAIN.into(),
+                        unique,
+                    };
 
-        let count = reply.write(&mut self.buffer);
+                    // ENOENT when the target was answered in the meantime
+                    if let Err(e) = transport.send(header.as_bytes()).await {
+                        trace!("interrupt {} requeue: {:?}", target, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Answer request `unique` with [Inner::deadline_errno] and cancel it unless the filesystem answers it within
+    /// `deadline`.
+    pub(crate) fn watch_deadline(&mut self, unique: u64, deadline: Duration) {
+        let Some(done) = self.in_flight.deadline(unique) else {
+            return;
+        };
+
+        let transport = self.transport.clone();
+        let in_flight = self.in_flight.clone();
+        let errno = self.deadline_errno;
+
+        tokio::spawn(async move {
+            select! {
+                _ = done.cancelled() => {}
+                _ = tokio::time::sleep(deadline) => {
+                    if !in_flight.time_out(unique) {
+                        return;
+                    }
+
+                    warn!("request {} timed out after {:?}", unique, deadline);
+
+                    let header = fuse_out_header {
+                        len: size_of::<fuse_out_header>() as u32,
+                        error: errno.into(),
+                        unique,
+                    };
+
+                    if let Err(e) = transport.send(header.as_bytes()).await {
+                        error!("timeout reply {}: {:?}", unique, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Protocol minor agreed in the INIT handshake, ours until then. Requests are parsed and replies written in its
+    /// layout.
+    fn minor(&self) -> u32 {
+        self.connection_info
+            .borrow()
+            .as_ref()
+            .map_or(FUSE_KERNEL_MINOR_VERSION, |info| info.minor)
+    }
+
+    /// Answer the kernel's `FUSE_INIT` from [KernelConfig] and publish the [ConnectionInfo].
+    pub(crate) async fn on_init(&mut self, unique: u64, arg: &fuse_init_in) -> Result<(), Errno> {
+        trace!("on_init");
+
+        let reply = match negotiate(&self.kernel_config, arg) {
+            Err(e) => Reply::new(unique, e.into(), None),
+            Ok(Negotiation::Retry(out)) => {
+                Reply::new(unique, 0, Some(reply::Operation::Init(reply::Init { arg: out })))
+            }
+            Ok(Negotiation::Done(out, info)) => {
+                self.connection_info.send_replace(Some(info));
+                Reply::new(unique, 0, Some(reply::Operation::Init(reply::Init { arg: out })))
+            }
+        };
+
+        self.write_reply(reply, None).await
+    }
+
+    /// Answer the kernel's `CUSE_INIT` from [CuseConfig] and publish the [ConnectionInfo].
+    ///
+    /// Fails the handshake with [Errno::ENOSYS] unless the session was opened with a
+    /// [crate::builder::CuseBuilder].
+    #[cfg(feature = "abi-7-12")]
+    pub(crate) async fn on_cuse_init(&mut self, unique: u64, arg: &cuse_init_in) -> Result<(), Errno> {
+        trace!("on_cuse_init");
+
+        let Some(config) = &self.cuse else {
+            error!("CUSE_INIT on a filesystem session");
+            return self.write_error(unique, Errno::ENOSYS).await;
+        };
+
+        let reply = match negotiate_cuse(config, &self.kernel_config, arg) {
+            Err(e) => Reply::new(unique, e.into(), None),
+            Ok((out, dev_info, info)) => {
+                self.connection_info.send_replace(Some(info));
+                let operation = reply::Operation::CuseInit(reply::CuseInit { arg: out, dev_info });
+                Reply::new(unique, 0, Some(operation))
+            }
+        };
+
+        self.write_reply(reply, None).await
+    }
+
+    /// Serialize `reply` within the `reply_size` the kernel asked for and send it to the kernel.
+    ///
+    /// A reply that does not fit is replaced by an error reply rather than sent truncated.
+    pub(crate) async fn write_reply(&mut self, mut reply: Reply, reply_size: Option<u32>) -> Result<(), Errno> {
+        debug!(target: strace::TARGET, "{}", strace::reply(&reply));
+
+        if reply.header.error == 0 {
+            match &reply.operation {
+                Some(reply::Operation::Read(reply::Read::Segments(segments))) if segments.len() <= MAX_SEGMENTS => {
+                    return self.write_segments(reply.header.unique, segments, reply_size).await;
+                }
+                #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+                Some(reply::Operation::Read(reply::Read::File { fd, offset, len })) if self.splice_write() => {
+                    use std::os::fd::AsFd;
+
+                    if self
+                        .splice_file(reply.header.unique, fd.as_fd(), *offset, *len, reply_size)
+                        .await?
+                    {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(reply::Operation::Read(read)) = &mut reply.operation {
+            if let Err(e) = self.read_file(read).await {
+                error!("reply {} cannot read file: {}", reply.header.unique, e);
+                reply = Reply::new(reply.header.unique, e.into(), None);
+            }
+        }
 
-        match self.writer.write(&self.buffer[..count]) {
+        let minor = self.minor();
+        let count = match reply.write_versioned(&mut self.buffer, reply_size, minor) {
+            Ok(count) => count,
             Err(e) => {
-                return Err(e.into());
+                error!("reply {} does not fit: {}", reply.header.unique, e);
+                Reply::new(reply.header.unique, e.into(), None).write(&mut self.buffer)?
             }
-            Ok(r) => r,
         };
 
+        if let Err(e) = self.transport.send(&self.buffer[..count]).await {
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Replace a [reply::Read::File] with the data read from the file on the blocking pool.
+    ///
+    /// Left as it is if it cannot fit in the reply buffer, so that serializing it fails as any oversized reply does.
+    async fn read_file(&self, read: &mut reply::Read) -> Result<(), Errno> {
+        use std::os::fd::AsFd;
+
+        let reply::Read::File { fd, offset, len } = read else {
+            return Ok(());
+        };
+
+        if *len as usize > self.buffer.len() {
+            return Ok(());
+        }
+
+        let (fd, offset, len) = (fd.clone(), *offset, *len as usize);
+        let data = tokio::task::spawn_blocking(move || {
+            let mut data = vec![0u8; len];
+            let count = reply::read_file(fd.as_fd(), offset, &mut data)?;
+            data.truncate(count);
+            Ok::<_, Errno>(data)
+        })
+        .await
+        .map_err(|_e| Errno::EIO)??;
+
+        *read = reply::Read::Data(data);
+
+        Ok(())
+    }
+
+    /// Write a `READ` reply straight from its `segments` with a single `writev`.
+    async fn write_segments(&mut self, unique: u64, segments: &[Bytes], reply_size: Option<u32>) -> Result<(), Errno> {
+        let len = segments.iter().map(Bytes::len).sum::<usize>();
+
+        if reply_size.is_some_and(|size| len > size as usize) {
+            error!("reply {} does not fit: {}", unique, Errno::EIO);
+            return self.write_error(unique, Errno::EIO).await;
+        }
+
+        let header = fuse_out_header {
+            len: (size_of::<fuse_out_header>() + len) as u32,
+            error: 0,
+            unique,
+        };
+
+        let mut buffers = Vec::with_capacity(segments.len() + 1);
+        buffers.push(IoSlice::new(header.as_bytes()));
+        buffers.extend(segments.iter().map(|segment| IoSlice::new(segment)));
+
+        self.transport.send_vectored(&buffers).await?;
+
         Ok(())
     }
-}
 
-impl Drop for Inner {
-    fn drop(&mut self) {
-        let file = self.file.take().unwrap();
-        std::mem::forget(file);
+    /// Whether `FUSE_SPLICE_WRITE` was agreed in the INIT handshake and replies go to the device
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    fn splice_write(&self) -> bool {
+        self.device.is_some()
+            && self
+                .connection_info
+                .borrow()
+                .as_ref()
+                .is_some_and(|info| info.flags.contains(crate::init::InitFlags::SPLICE_WRITE))
+    }
+
+    /// Splice a `READ` reply of up to `len` bytes of `fd` from `offset` into the device.
+    ///
+    /// Returns `false` without writing anything if the reply has to be copied instead, e.g. because `fd` cannot be
+    /// spliced or the message does not fit in a pipe.
+    #[cfg(all(target_os = "linux", feature = "abi-7-14"))]
+    async fn splice_file(
+        &mut self,
+        unique: u64,
+        fd: std::os::fd::BorrowedFd<'_>,
+        offset: i64,
+        len: u32,
+        reply_size: Option<u32>,
+    ) -> Result<bool, Errno> {
+        let Some(device) = &self.device else {
+            return Ok(false);
+        };
+
+        if reply_size.is_some_and(|size| len > size) {
+            return Ok(false);
+        }
+
+        let total = size_of::<fuse_out_header>() + len as usize;
+        let header = fuse_out_header {
+            len: total as u32,
+            error: 0,
+            unique,
+        };
+
+        let mut pipe = match self.pipe.take() {
+            Some(pipe) => pipe,
+            None => Pipe::new()?,
+        };
+
+        // The pipe is dropped if anything fails part way; it may hold a partial message
+        let moved = match pipe
+            .reserve(total)
+            .and_then(|_| pipe.push(header.as_bytes()))
+            .and_then(|_| pipe.splice_from(fd, offset, len as usize))
+        {
+            Ok(moved) => moved,
+            Err(e) => {
+                trace!("reply {} not spliced: {}", unique, e);
+                return Ok(false);
+            }
+        };
+
+        if moved < len as usize {
+            // Short read: the header overstates the length. Copy the message out and fix it up.
+            let count = pipe.drain(&mut self.buffer)?;
+            let header = fuse_out_header {
+                len: count as u32,
+                error: 0,
+                unique,
+            };
+            self.buffer[..size_of::<fuse_out_header>()].copy_from_slice(header.as_bytes());
+            device.write(&self.buffer[..count]).await?;
+        } else {
+            device.splice_from(pipe.reader(), total).await?;
+        }
+
+        self.pipe = Some(pipe);
+
+        Ok(true)
+    }
+
+    /// Answer request `unique` with `errno`.
+    async fn write_error(&mut self, unique: u64, errno: Errno) -> Result<(), Errno> {
+        let header = fuse_out_header {
+            len: size_of::<fuse_out_header>() as u32,
+            error: errno.into(),
+            unique,
+        };
+        debug!(target: strace::TARGET, "{}", strace::reply(&Reply { header, operation: None }));
+
+        self.transport.send(header.as_bytes()).await?;
+
+        Ok(())
     }
 }
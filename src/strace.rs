@@ -0,0 +1,1106 @@
+//! Human-readable decoding of requests and replies, in the spirit of `strace`.
+//!
+//! [request] and [reply] wrap a message for [Display]: the `unique` id, the opcode name, the header of a request and
+//! the decoded arguments, with flags and modes spelled out symbolically. Flags are named after the kernel's `fuse.h`
+//! whether or not the ABI feature that introduced them is enabled; unknown bits are printed in hex.
+//!
+//! The session logs every request it receives and every reply it sends at `debug` level to the [TARGET] log target,
+//! e.g. with `RUST_LOG=fusion::strace=debug`:
+//!
+//! ```text
+//! unique=4 LOOKUP nodeid=1 uid=1000 gid=1000 pid=4242 name="hello"
+//! unique=4 <- LOOKUP nodeid=2 generation=0 entry_valid=1.000000000 attr_valid=1.000000000 attr={ino=2 mode=S_IFREG|0644 ...}
+//! unique=5 OPEN nodeid=2 uid=1000 gid=1000 pid=4242 flags=O_RDONLY|O_LARGEFILE
+//! unique=5 <- ENOENT
+//! ```
+//!
+//! To send the output elsewhere, format into any writer: `writeln!(out, "{}", strace::request(&request))`.
+
+use std::{
+    ffi::OsStr,
+    fmt::{self, Display, Formatter},
+    os::unix::ffi::OsStrExt,
+};
+
+#[cfg(feature = "abi-7-36")]
+use crate::messages::extension::Extensions;
+use crate::{
+    init::InitFlags,
+    messages::{
+        fuse_abi::*,
+        reply::{self, Reply},
+        request::{Operation, Request},
+    },
+};
+
+/// Log target of the session's request and reply log
+pub const TARGET: &str = "fusion::strace";
+
+/// Bytes of a name, value or payload shown before eliding the rest
+const MAX_BYTES: usize = 32;
+
+/// Decode `request` for display
+pub fn request(request: &Request) -> RequestTrace<'_> {
+    RequestTrace(request)
+}
+
+/// Decode `reply` for display. Error replies and replies without payload do not name the opcode.
+pub fn reply(reply: &Reply) -> ReplyTrace<'_> {
+    ReplyTrace(reply)
+}
+
+/// See [request]
+pub struct RequestTrace<'a>(&'a Request);
+
+/// See [reply]
+pub struct ReplyTrace<'a>(&'a Reply);
+
+impl Display for RequestTrace<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let header = &self.0.header;
+        write!(
+            f,
+            "unique={} {} nodeid={} uid={} gid={} pid={}",
+            header.unique,
+            Opcode(header.opcode),
+            header.nodeid,
+            header.uid,
+            header.gid,
+            header.pid
+        )?;
+
+        match &self.0.operation {
+            Operation::Lookup(x) => write!(f, " name={}", Name(&x.name)),
+            Operation::Forget(x) => write!(f, " nlookup={}", x.arg.nlookup),
+            #[cfg(feature = "abi-7-9")]
+            Operation::GetAttr(x) => write!(
+                f,
+                " getattr_flags={} fh={}",
+                Bits(x.arg.getattr_flags.into(), GETATTR_FLAGS),
+                x.arg.fh
+            ),
+            #[cfg(not(feature = "abi-7-9"))]
+            Operation::GetAttr(_) => Ok(()),
+            Operation::SetAttr(x) => setattr_in(f, &x.arg),
+            Operation::ReadLink(_) => Ok(()),
+            Operation::SymLink(x) => write!(f, " name={} target={}", Name(&x.name), Name(&x.target)),
+            Operation::MkNod(x) => {
+                write!(
+                    f,
+                    " name={} mode={} rdev={}",
+                    Name(&x.name),
+                    Mode(x.arg.mode),
+                    x.arg.rdev
+                )?;
+                #[cfg(feature = "abi-7-12")]
+                write!(f, " umask={:04o}", x.arg.umask)?;
+                Ok(())
+            }
+            Operation::MkDir(x) => {
+                write!(f, " name={} mode={}", Name(&x.name), Mode(x.arg.mode))?;
+                #[cfg(feature = "abi-7-12")]
+                write!(f, " umask={:04o}", x.arg.umask)?;
+                Ok(())
+            }
+            Operation::Unlink(x) => write!(f, " name={}", Name(&x.name)),
+            Operation::RmDir(x) => write!(f, " name={}", Name(&x.name)),
+            Operation::Rename(x) => write!(
+                f,
+                " name={} newdir={} newname={}",
+                Name(&x.name),
+                x.arg.newdir,
+                Name(&x.newname)
+            ),
+            Operation::Link(x) => write!(f, " oldnodeid={} name={}", x.arg.oldnodeid, Name(&x.name)),
+            Operation::Open(x) => write!(f, " flags={}", OpenFlags(x.arg.flags)),
+            Operation::Read(x) => {
+                write!(f, " fh={} offset={} size={}", x.arg.fh, x.arg.offset, x.arg.size)?;
+                #[cfg(feature = "abi-7-9")]
+                write!(
+                    f,
+                    " read_flags={} lock_owner={:#x} flags={}",
+                    Bits(x.arg.read_flags.into(), READ_FLAGS),
+                    x.arg.lock_owner,
+                    OpenFlags(x.arg.flags)
+                )?;
+                Ok(())
+            }
+            Operation::Write(x) => {
+                write!(
+                    f,
+                    " fh={} offset={} size={} write_flags={}",
+                    x.arg.fh,
+                    x.arg.offset,
+                    x.arg.size,
+                    Bits(x.arg.write_flags.into(), WRITE_FLAGS)
+                )?;
+                #[cfg(feature = "abi-7-9")]
+                write!(
+                    f,
+                    " lock_owner={:#x} flags={}",
+                    x.arg.lock_owner,
+                    OpenFlags(x.arg.flags)
+                )?;
+                write!(f, " data={}", Data(&x.data))
+            }
+            Operation::StatFs(_) => Ok(()),
+            Operation::Release(x) => release_in(f, &x.arg),
+            Operation::FSync(x) => fsync_in(f, &x.arg),
+            Operation::SetXAttr(x) => write!(
+                f,
+                " name={} size={} flags={} value={}",
+                Name(&x.name),
+                x.arg.size,
+                Bits(x.arg.flags as u64, XATTR_FLAGS),
+                Data(&x.value)
+            ),
+            Operation::GetXAttr(x) => write!(f, " name={} size={}", Name(&x.name), x.arg.size),
+            Operation::ListXAttr(x) => write!(f, " size={}", x.arg.size),
+            Operation::RemoveXAttr(x) => write!(f, " name={}", Name(&x.name)),
+            Operation::Flush(x) => write!(f, " fh={} lock_owner={:#x}", x.arg.fh, x.arg.lock_owner),
+            Operation::Init(x) => write!(
+                f,
+                " major={} minor={} max_readahead={} flags={}",
+                x.arg.major,
+                x.arg.minor,
+                x.arg.max_readahead,
+                InitFlags::from_init_in(&x.arg)
+            ),
+            Operation::OpenDir(x) => write!(f, " flags={}", OpenFlags(x.arg.flags)),
+            Operation::ReadDir(x) => write!(f, " fh={} offset={} size={}", x.arg.fh, x.arg.offset, x.arg.size),
+            Operation::ReleaseDir(x) => release_in(f, &x.arg),
+            Operation::FSyncDir(x) => fsync_in(f, &x.arg),
+            Operation::GetLk(x) => lk_in(f, &x.arg),
+            Operation::SetLk(x) => lk_in(f, &x.arg),
+            Operation::SetLkW(x) => lk_in(f, &x.arg),
+            Operation::Access(x) => write!(f, " mask={}", AccessMask(x.arg.mask)),
+            Operation::Create(x) => {
+                write!(
+                    f,
+                    " name={} flags={} mode={}",
+                    Name(&x.name),
+                    OpenFlags(x.arg.flags),
+                    Mode(x.arg.mode)
+                )?;
+                #[cfg(feature = "abi-7-12")]
+                write!(f, " umask={:04o}", x.arg.umask)?;
+                Ok(())
+            }
+            Operation::Interrupt(x) => write!(f, " interrupted={}", x.arg.unique),
+            Operation::BMap(x) => write!(f, " block={} blocksize={}", x.arg.block, x.arg.blocksize),
+            Operation::Destroy(_) => Ok(()),
+            #[cfg(feature = "abi-7-11")]
+            Operation::IoCtl(x) => write!(
+                f,
+                " fh={} flags={} cmd={:#x} arg={:#x} in_size={} out_size={} data={}",
+                x.arg.fh,
+                Bits(x.arg.flags.into(), IOCTL_FLAGS),
+                x.arg.cmd,
+                x.arg.arg,
+                x.arg.in_size,
+                x.arg.out_size,
+                Data(&x.data)
+            ),
+            #[cfg(feature = "abi-7-11")]
+            Operation::Poll(x) => {
+                write!(
+                    f,
+                    " fh={} kh={} flags={}",
+                    x.arg.fh,
+                    x.arg.kh,
+                    Bits(x.arg.flags.into(), POLL_FLAGS)
+                )?;
+                #[cfg(feature = "abi-7-21")]
+                write!(f, " events={:#x}", x.arg.events)?;
+                Ok(())
+            }
+            #[cfg(feature = "abi-7-15")]
+            Operation::NotifyReply(x) => write!(f, " offset={} size={}", x.arg.offset, x.arg.size),
+            #[cfg(feature = "abi-7-16")]
+            Operation::BatchForget(x) => {
+                write!(f, " count={} nodes=[", x.arg.count)?;
+                for (index, node) in x.nodes.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}{{nodeid={} nlookup={}}}", separator, node.nodeid, node.nlookup)?;
+                }
+                write!(f, "]")
+            }
+            #[cfg(feature = "abi-7-19")]
+            Operation::FAllocate(x) => write!(
+                f,
+                " fh={} offset={} length={} mode={}",
+                x.arg.fh,
+                x.arg.offset,
+                x.arg.length,
+                Bits(x.arg.mode as u64, FALLOCATE_FLAGS)
+            ),
+            #[cfg(feature = "abi-7-21")]
+            Operation::ReadDirPlus(x) => write!(f, " fh={} offset={} size={}", x.arg.fh, x.arg.offset, x.arg.size),
+            #[cfg(feature = "abi-7-23")]
+            Operation::Rename2(x) => write!(
+                f,
+                " name={} newdir={} newname={} flags={}",
+                Name(&x.name),
+                x.arg.newdir,
+                Name(&x.newname),
+                Bits(x.arg.flags.into(), RENAME_FLAGS)
+            ),
+            #[cfg(feature = "abi-7-24")]
+            Operation::LSeek(x) => write!(
+                f,
+                " fh={} offset={} whence={}",
+                x.arg.fh,
+                x.arg.offset,
+                Whence(x.arg.whence)
+            ),
+            #[cfg(feature = "abi-7-28")]
+            Operation::CopyFileRange(x) => write!(
+                f,
+                " fh_in={} off_in={} nodeid_out={} fh_out={} off_out={} len={} flags={:#x}",
+                x.arg.fh_in, x.arg.off_in, x.arg.nodeid_out, x.arg.fh_out, x.arg.off_out, x.arg.len, x.arg.flags
+            ),
+            #[cfg(feature = "abi-7-31")]
+            Operation::SetupMapping(x) => write!(
+                f,
+                " fh={} foffset={} len={} flags={:#x} moffset={}",
+                x.arg.fh, x.arg.foffset, x.arg.len, x.arg.flags, x.arg.moffset
+            ),
+            #[cfg(feature = "abi-7-31")]
+            Operation::RemoveMapping(x) => {
+                write!(f, " count={} mappings=[", x.arg.count)?;
+                for (index, mapping) in x.mappings.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}{{moffset={} len={}}}", separator, mapping.moffset, mapping.len)?;
+                }
+                write!(f, "]")
+            }
+            #[cfg(feature = "abi-7-34")]
+            Operation::SyncFs(_) => Ok(()),
+            #[cfg(feature = "abi-7-37")]
+            Operation::TmpFile(x) => write!(
+                f,
+                " name={} flags={} mode={}",
+                Name(&x.name),
+                OpenFlags(x.arg.flags),
+                Mode(x.arg.mode)
+            ),
+            #[cfg(feature = "abi-7-39")]
+            Operation::StatX(x) => write!(
+                f,
+                " getattr_flags={} fh={} sx_flags={:#x} sx_mask={:#x}",
+                Bits(x.arg.getattr_flags.into(), GETATTR_FLAGS),
+                x.arg.fh,
+                x.arg.sx_flags,
+                x.arg.sx_mask
+            ),
+            #[cfg(target_os = "macos")]
+            Operation::SetVolName(_) | Operation::GetXTimes(_) | Operation::Exchange(_) => Ok(()),
+            #[cfg(feature = "abi-7-12")]
+            Operation::CuseInit(x) => write!(
+                f,
+                " major={} minor={} flags={:#x}",
+                x.arg.major, x.arg.minor, x.arg.flags
+            ),
+        }?;
+
+        #[cfg(feature = "abi-7-36")]
+        extensions(f, &self.0.extensions)?;
+
+        Ok(())
+    }
+}
+
+impl Display for ReplyTrace<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let header = &self.0.header;
+        write!(f, "unique={} <- ", header.unique)?;
+
+        if header.error != 0 {
+            return write!(f, "{}", Error(header.error));
+        }
+
+        let Some(operation) = &self.0.operation else {
+            return write!(f, "ok");
+        };
+
+        write!(f, "{}", Opcode(operation.get_opcode()))?;
+
+        match operation {
+            reply::Operation::Lookup(reply::Lookup { arg })
+            | reply::Operation::SymLink(reply::SymLink { arg })
+            | reply::Operation::MkNod(reply::MkNod { arg })
+            | reply::Operation::MkDir(reply::MkDir { arg })
+            | reply::Operation::Link(reply::Link { arg }) => entry_out(f, arg),
+            reply::Operation::GetAttr(reply::GetAttr { arg }) | reply::Operation::SetAttr(reply::SetAttr { arg }) => {
+                write!(
+                    f,
+                    " attr_valid={} attr=",
+                    Time(arg.attr_valid as i64, arg.attr_valid_nsec)
+                )?;
+                attr(f, &arg.attr)
+            }
+            reply::Operation::ReadLink(x) => write!(f, " target={}", Name(&x.data)),
+            reply::Operation::Open(reply::Open { arg }) | reply::Operation::OpenDir(reply::OpenDir { arg }) => {
+                open_out(f, arg)
+            }
+            reply::Operation::Read(reply::Read::Data(data)) => write!(f, " data={}", Data(data)),
+            reply::Operation::Read(reply::Read::Segments(segments)) => write!(
+                f,
+                " size={} segments={}",
+                segments.iter().map(|x| x.len()).sum::<usize>(),
+                segments.len()
+            ),
+            reply::Operation::Read(reply::Read::File { offset, len, .. }) => {
+                write!(f, " file offset={} len={}", offset, len)
+            }
+            reply::Operation::Write(reply::Write { arg }) => write!(f, " size={}", arg.size),
+            reply::Operation::StatFs(x) => {
+                let st = &x.arg.st;
+                write!(
+                    f,
+                    " blocks={} bfree={} bavail={} files={} ffree={} bsize={} namelen={} frsize={}",
+                    st.blocks, st.bfree, st.bavail, st.files, st.ffree, st.bsize, st.namelen, st.frsize
+                )
+            }
+            reply::Operation::GetXAttr(xattr) | reply::Operation::ListXAttr(xattr) => match xattr {
+                reply::XAttr::Size(size) => write!(f, " size={}", size),
+                reply::XAttr::Data(data) => write!(f, " value={}", Data(data)),
+            },
+            reply::Operation::Init(x) => init_out(f, &x.arg),
+            reply::Operation::ReadDir(x) => {
+                write!(f, " entries=[")?;
+                for (index, entry) in x.entries.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}", separator)?;
+                    dirent(f, &entry.entry, &entry.name)?;
+                }
+                write!(f, "]")
+            }
+            reply::Operation::GetLk(reply::GetLk { arg }) | reply::Operation::SetLk(reply::SetLk { arg }) => {
+                write!(f, " lk=")?;
+                file_lock(f, &arg.lk)
+            }
+            reply::Operation::Create(reply::Create { arg }) => {
+                entry_out(f, &arg.entry)?;
+                open_out(f, &arg.open)
+            }
+            reply::Operation::BMap(x) => write!(f, " block={}", x.arg.block),
+            #[cfg(feature = "abi-7-11")]
+            reply::Operation::IoCtl(x) => write!(
+                f,
+                " result={} flags={} in_iovs={} out_iovs={}",
+                x.arg.result,
+                Bits(x.arg.flags.into(), IOCTL_FLAGS),
+                x.arg.in_iovs,
+                x.arg.out_iovs
+            ),
+            #[cfg(feature = "abi-7-11")]
+            reply::Operation::Poll(x) => write!(f, " revents={:#x}", x.arg.revents),
+            #[cfg(feature = "abi-7-21")]
+            reply::Operation::ReadDirPlus(x) => {
+                write!(f, " entries=[")?;
+                for (index, entry) in x.entries.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{}", separator)?;
+                    dirent(f, &entry.entry.dirent, &entry.name)?;
+                    write!(f, " entry={{")?;
+                    entry_out(f, &entry.entry.entry_out)?;
+                    write!(f, " }}")?;
+                }
+                write!(f, "]")
+            }
+            #[cfg(feature = "abi-7-24")]
+            reply::Operation::Lseek(x) => write!(f, " offset={}", x.arg.offset),
+            #[cfg(feature = "abi-7-28")]
+            reply::Operation::CopyFileRange(x) => write!(f, " size={}", x.arg.size),
+            #[cfg(feature = "abi-7-37")]
+            reply::Operation::TmpFile(reply::TmpFile { arg }) => {
+                entry_out(f, &arg.entry)?;
+                open_out(f, &arg.open)
+            }
+            #[cfg(feature = "abi-7-39")]
+            reply::Operation::StatX(x) => {
+                let stat = &x.arg.stat;
+                write!(
+                    f,
+                    " attr_valid={} mask={:#x} ino={} mode={} nlink={} uid={} gid={} size={} blocks={}",
+                    Time(x.arg.attr_valid as i64, x.arg.attr_valid_nsec),
+                    stat.mask,
+                    stat.ino,
+                    Mode(stat.mode.into()),
+                    stat.nlink,
+                    stat.uid,
+                    stat.gid,
+                    stat.size,
+                    stat.blocks
+                )
+            }
+            #[cfg(feature = "abi-7-12")]
+            reply::Operation::CuseInit(x) => write!(
+                f,
+                " major={} minor={} flags={:#x} max_read={} max_write={} dev={}:{} info={}",
+                x.arg.major,
+                x.arg.minor,
+                x.arg.flags,
+                x.arg.max_read,
+                x.arg.max_write,
+                x.arg.dev_major,
+                x.arg.dev_minor,
+                Data(&x.dev_info)
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn attr(f: &mut Formatter<'_>, attr: &fuse_attr) -> fmt::Result {
+    write!(
+        f,
+        "{{ino={} mode={} nlink={} uid={} gid={} size={} blocks={} rdev={} atime={} mtime={} ctime={}}}",
+        attr.ino,
+        Mode(attr.mode),
+        attr.nlink,
+        attr.uid,
+        attr.gid,
+        attr.size,
+        attr.blocks,
+        attr.rdev,
+        Time(attr.atime, attr.atimensec),
+        Time(attr.mtime, attr.mtimensec),
+        Time(attr.ctime, attr.ctimensec)
+    )
+}
+
+fn entry_out(f: &mut Formatter<'_>, entry: &fuse_entry_out) -> fmt::Result {
+    write!(
+        f,
+        " nodeid={} generation={} entry_valid={} attr_valid={} attr=",
+        entry.nodeid,
+        entry.generation,
+        Time(entry.entry_valid as i64, entry.entry_valid_nsec),
+        Time(entry.attr_valid as i64, entry.attr_valid_nsec)
+    )?;
+    attr(f, &entry.attr)
+}
+
+fn open_out(f: &mut Formatter<'_>, open: &fuse_open_out) -> fmt::Result {
+    write!(
+        f,
+        " fh={} open_flags={}",
+        open.fh,
+        Bits(open.open_flags.into(), FOPEN_FLAGS)
+    )?;
+    #[cfg(feature = "abi-7-40")]
+    if open.open_flags & crate::constants::FOPEN_PASSTHROUGH != 0 {
+        write!(f, " backing_id={}", open.backing_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "abi-7-36")]
+fn extensions(f: &mut Formatter<'_>, extensions: &Extensions) -> fmt::Result {
+    if let Some(contexts) = &extensions.security_context {
+        write!(f, " secctx=[")?;
+        for (index, context) in contexts.iter().enumerate() {
+            let separator = if index == 0 { "" } else { ", " };
+            write!(f, "{}{}={}", separator, Name(&context.name), Data(&context.value))?;
+        }
+        write!(f, "]")?;
+    }
+    #[cfg(feature = "abi-7-38")]
+    if let Some(groups) = &extensions.supplementary_groups {
+        write!(f, " groups={:?}", groups)?;
+    }
+    Ok(())
+}
+
+fn dirent(f: &mut Formatter<'_>, dirent: &fuse_dirent, name: &OsStr) -> fmt::Result {
+    write!(
+        f,
+        "{{ino={} off={} type={} name={}}}",
+        dirent.ino,
+        dirent.off,
+        FileType(dirent.typ << 12),
+        Name(name)
+    )
+}
+
+fn file_lock(f: &mut Formatter<'_>, lock: &fuse_file_lock) -> fmt::Result {
+    let typ = match lock.typ {
+        libc::F_RDLCK => "F_RDLCK",
+        libc::F_WRLCK => "F_WRLCK",
+        libc::F_UNLCK => "F_UNLCK",
+        _ => "?",
+    };
+    write!(
+        f,
+        "{{type={} start={} end={} pid={}}}",
+        typ, lock.start, lock.end, lock.pid
+    )
+}
+
+fn lk_in(f: &mut Formatter<'_>, lk: &fuse_lk_in) -> fmt::Result {
+    write!(f, " fh={} owner={:#x} lk=", lk.fh, lk.owner)?;
+    file_lock(f, &lk.lk)?;
+    #[cfg(feature = "abi-7-9")]
+    write!(f, " lk_flags={}", Bits(lk.lk_flags.into(), LK_FLAGS))?;
+    Ok(())
+}
+
+fn release_in(f: &mut Formatter<'_>, arg: &fuse_release_in) -> fmt::Result {
+    write!(
+        f,
+        " fh={} flags={} release_flags={} lock_owner={:#x}",
+        arg.fh,
+        OpenFlags(arg.flags),
+        Bits(arg.release_flags.into(), RELEASE_FLAGS),
+        arg.lock_owner
+    )
+}
+
+fn fsync_in(f: &mut Formatter<'_>, arg: &fuse_fsync_in) -> fmt::Result {
+    write!(
+        f,
+        " fh={} fsync_flags={}",
+        arg.fh,
+        Bits(arg.fsync_flags.into(), FSYNC_FLAGS)
+    )
+}
+
+fn setattr_in(f: &mut Formatter<'_>, arg: &fuse_setattr_in) -> fmt::Result {
+    write!(f, " valid={}", Bits(arg.valid.into(), FATTR_FLAGS))?;
+
+    // Only the fields marked valid carry meaning
+    let valid = |bit: u32| arg.valid & bit != 0;
+    if valid(1 << 0) {
+        write!(f, " mode={}", Mode(arg.mode))?;
+    }
+    if valid(1 << 1) {
+        write!(f, " uid={}", arg.uid)?;
+    }
+    if valid(1 << 2) {
+        write!(f, " gid={}", arg.gid)?;
+    }
+    if valid(1 << 3) {
+        write!(f, " size={}", arg.size)?;
+    }
+    if valid(1 << 4) {
+        write!(f, " atime={}", Time(arg.atime, arg.atimensec))?;
+    }
+    if valid(1 << 5) {
+        write!(f, " mtime={}", Time(arg.mtime, arg.mtimensec))?;
+    }
+    if valid(1 << 6) {
+        write!(f, " fh={}", arg.fh)?;
+    }
+    #[cfg(feature = "abi-7-23")]
+    if valid(1 << 10) {
+        write!(f, " ctime={}", Time(arg.ctime, arg.ctimensec))?;
+    }
+
+    Ok(())
+}
+
+fn init_out(f: &mut Formatter<'_>, arg: &fuse_init_out) -> fmt::Result {
+    write!(
+        f,
+        " major={} minor={} max_readahead={} flags={}",
+        arg.major,
+        arg.minor,
+        arg.max_readahead,
+        InitFlags::from_init_out(arg)
+    )?;
+    #[cfg(feature = "abi-7-13")]
+    write!(
+        f,
+        " max_background={} congestion_threshold={}",
+        arg.max_background, arg.congestion_threshold
+    )?;
+    write!(f, " max_write={}", arg.max_write)?;
+    #[cfg(feature = "abi-7-23")]
+    write!(f, " time_gran={}", arg.time_gran)?;
+    #[cfg(feature = "abi-7-28")]
+    write!(f, " max_pages={}", arg.max_pages)?;
+    Ok(())
+}
+
+/// Opcode name without the `FUSE_` prefix
+struct Opcode(u32);
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match fuse_opcode::try_from(self.0) {
+            Ok(opcode) => {
+                let name = format!("{:?}", opcode);
+                write!(f, "{}", name.strip_prefix("FUSE_").unwrap_or(&name))
+            }
+            Err(_e) => write!(f, "OPCODE_{}", self.0),
+        }
+    }
+}
+
+/// Name, quoted and escaped. Need not be UTF-8.
+struct Name<'a, T: ?Sized>(&'a T);
+
+impl<T: AsRef<OsStr> + ?Sized> Display for Name<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Data(self.0.as_ref().as_bytes()))
+    }
+}
+
+/// Bytes quoted and escaped like a C string, elided after [MAX_BYTES]
+struct Data<'a>(&'a [u8]);
+
+impl Display for Data<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "\"")?;
+        for byte in self.0.iter().take(MAX_BYTES) {
+            write!(f, "{}", byte.escape_ascii())?;
+        }
+        write!(f, "\"")?;
+        if self.0.len() > MAX_BYTES {
+            write!(f, "... ({} bytes)", self.0.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds and nanoseconds
+struct Time(i64, u32);
+
+impl Display for Time {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:09}", self.0, self.1)
+    }
+}
+
+/// Negative errno of a reply header, by name where known
+struct Error(i32);
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let errno = -self.0;
+        match ERRNO_NAMES.iter().find(|(value, _)| *value == errno) {
+            Some((_, name)) => write!(f, "{}", name),
+            None => write!(f, "errno {}", errno),
+        }
+    }
+}
+
+/// Names of the bits set in a value, joined with `|`. Remaining bits are printed in hex.
+struct Bits(u64, &'static [(u64, &'static str)]);
+
+impl Display for Bits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0;
+        let mut separator = "";
+
+        for (bits, name) in self.1 {
+            if *bits != 0 && rest & bits == *bits {
+                write!(f, "{}{}", separator, name)?;
+                rest &= !bits;
+                separator = "|";
+            }
+        }
+
+        match (rest, separator) {
+            (0, "") => write!(f, "0"),
+            (0, _) => Ok(()),
+            (rest, separator) => write!(f, "{}{:#x}", separator, rest),
+        }
+    }
+}
+
+/// File type bits of a mode
+struct FileType(u32);
+
+impl Display for FileType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self.0 & libc::S_IFMT {
+            libc::S_IFREG => "S_IFREG",
+            libc::S_IFDIR => "S_IFDIR",
+            libc::S_IFLNK => "S_IFLNK",
+            libc::S_IFCHR => "S_IFCHR",
+            libc::S_IFBLK => "S_IFBLK",
+            libc::S_IFIFO => "S_IFIFO",
+            libc::S_IFSOCK => "S_IFSOCK",
+            0 => "0",
+            _ => "?",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// File mode: type and permission bits, e.g. `S_IFREG|0644`
+pub struct Mode(pub u32);
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.0 & libc::S_IFMT != 0 {
+            write!(f, "{}|", FileType(self.0))?;
+        }
+        write!(f, "{:04o}", self.0 & 0o7777)
+    }
+}
+
+/// `open(2)` flags, e.g. `O_WRONLY|O_CREAT|O_TRUNC`
+pub struct OpenFlags(pub i32);
+
+impl Display for OpenFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let access = match self.0 & libc::O_ACCMODE {
+            libc::O_RDONLY => "O_RDONLY",
+            libc::O_WRONLY => "O_WRONLY",
+            libc::O_RDWR => "O_RDWR",
+            _ => "O_ACCMODE",
+        };
+        write!(f, "{}", access)?;
+
+        let rest = (self.0 & !libc::O_ACCMODE) as u32 as u64;
+        if rest != 0 {
+            write!(f, "|{}", Bits(rest, OPEN_FLAGS))?;
+        }
+        Ok(())
+    }
+}
+
+/// `access(2)` mask, e.g. `R_OK|W_OK`
+pub struct AccessMask(pub i32);
+
+impl Display for AccessMask {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            libc::F_OK => write!(f, "F_OK"),
+            mask => write!(f, "{}", Bits(mask as u64, ACCESS_FLAGS)),
+        }
+    }
+}
+
+/// `lseek(2)` whence
+#[cfg(feature = "abi-7-24")]
+struct Whence(u32);
+
+#[cfg(feature = "abi-7-24")]
+impl Display for Whence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 as i32 {
+            libc::SEEK_SET => write!(f, "SEEK_SET"),
+            libc::SEEK_CUR => write!(f, "SEEK_CUR"),
+            libc::SEEK_END => write!(f, "SEEK_END"),
+            #[cfg(target_os = "linux")]
+            libc::SEEK_DATA => write!(f, "SEEK_DATA"),
+            #[cfg(target_os = "linux")]
+            libc::SEEK_HOLE => write!(f, "SEEK_HOLE"),
+            whence => write!(f, "{}", whence),
+        }
+    }
+}
+
+/// By their `fuse.h` names without the `FUSE_` prefix, e.g. `ASYNC_READ|BIG_WRITES`
+impl Display for InitFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Bits(self.bits(), INIT_FLAGS))
+    }
+}
+
+/// Valid fields of a `SETATTR`
+pub struct SetAttrValid(pub u32);
+
+impl Display for SetAttrValid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Bits(self.0.into(), FATTR_FLAGS))
+    }
+}
+
+/// Multi-bit flags come before the flags they contain
+const OPEN_FLAGS: &[(u64, &str)] = &[
+    (libc::O_CREAT as u64, "O_CREAT"),
+    (libc::O_EXCL as u64, "O_EXCL"),
+    (libc::O_NOCTTY as u64, "O_NOCTTY"),
+    (libc::O_TRUNC as u64, "O_TRUNC"),
+    (libc::O_APPEND as u64, "O_APPEND"),
+    (libc::O_NONBLOCK as u64, "O_NONBLOCK"),
+    (libc::O_SYNC as u64, "O_SYNC"),
+    (libc::O_DSYNC as u64, "O_DSYNC"),
+    (libc::O_ASYNC as u64, "O_ASYNC"),
+    #[cfg(target_os = "linux")]
+    (libc::O_DIRECT as u64, "O_DIRECT"),
+    #[cfg(target_os = "linux")]
+    (libc::O_TMPFILE as u64, "O_TMPFILE"),
+    (libc::O_DIRECTORY as u64, "O_DIRECTORY"),
+    (libc::O_NOFOLLOW as u64, "O_NOFOLLOW"),
+    #[cfg(target_os = "linux")]
+    (libc::O_NOATIME as u64, "O_NOATIME"),
+    (libc::O_CLOEXEC as u64, "O_CLOEXEC"),
+    #[cfg(target_os = "linux")]
+    (libc::O_PATH as u64, "O_PATH"),
+    // O_LARGEFILE as the kernel passes it on 64-bit platforms, where libc defines it as 0
+    #[cfg(target_os = "linux")]
+    (0o100000, "O_LARGEFILE"),
+];
+
+const ACCESS_FLAGS: &[(u64, &str)] = &[
+    (libc::R_OK as u64, "R_OK"),
+    (libc::W_OK as u64, "W_OK"),
+    (libc::X_OK as u64, "X_OK"),
+];
+
+#[cfg(not(target_os = "macos"))]
+const INIT_FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "ASYNC_READ"),
+    (1 << 1, "POSIX_LOCKS"),
+    (1 << 2, "FILE_OPS"),
+    (1 << 3, "ATOMIC_O_TRUNC"),
+    (1 << 4, "EXPORT_SUPPORT"),
+    (1 << 5, "BIG_WRITES"),
+    (1 << 6, "DONT_MASK"),
+    (1 << 7, "SPLICE_WRITE"),
+    (1 << 8, "SPLICE_MOVE"),
+    (1 << 9, "SPLICE_READ"),
+    (1 << 10, "FLOCK_LOCKS"),
+    (1 << 11, "HAS_IOCTL_DIR"),
+    (1 << 12, "AUTO_INVAL_DATA"),
+    (1 << 13, "DO_READDIRPLUS"),
+    (1 << 14, "READDIRPLUS_AUTO"),
+    (1 << 15, "ASYNC_DIO"),
+    (1 << 16, "WRITEBACK_CACHE"),
+    (1 << 17, "NO_OPEN_SUPPORT"),
+    (1 << 18, "PARALLEL_DIROPS"),
+    (1 << 19, "HANDLE_KILLPRIV"),
+    (1 << 20, "POSIX_ACL"),
+    (1 << 21, "ABORT_ERROR"),
+    (1 << 22, "MAX_PAGES"),
+    (1 << 23, "CACHE_SYMLINKS"),
+    (1 << 24, "NO_OPENDIR_SUPPORT"),
+    (1 << 25, "EXPLICIT_INVAL_DATA"),
+    (1 << 26, "MAP_ALIGNMENT"),
+    (1 << 27, "SUBMOUNTS"),
+    (1 << 28, "HANDLE_KILLPRIV_V2"),
+    (1 << 29, "SETXATTR_EXT"),
+    (1 << 30, "INIT_EXT"),
+    (1 << 31, "INIT_RESERVED"),
+    (1 << 32, "SECURITY_CTX"),
+    (1 << 33, "HAS_INODE_DAX"),
+    (1 << 34, "CREATE_SUPP_GROUP"),
+    (1 << 35, "HAS_EXPIRE_ONLY"),
+    (1 << 36, "DIRECT_IO_ALLOW_MMAP"),
+    (1 << 37, "PASSTHROUGH"),
+    (1 << 38, "NO_EXPORT_SUPPORT"),
+    (1 << 39, "HAS_RESEND"),
+];
+
+#[cfg(target_os = "macos")]
+const INIT_FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "ASYNC_READ"),
+    (1 << 1, "POSIX_LOCKS"),
+    (1 << 2, "FILE_OPS"),
+    (1 << 3, "ATOMIC_O_TRUNC"),
+    (1 << 4, "EXPORT_SUPPORT"),
+    (1 << 5, "BIG_WRITES"),
+    (1 << 6, "DONT_MASK"),
+    (1 << 10, "FLOCK_LOCKS"),
+    (1 << 11, "HAS_IOCTL_DIR"),
+    (1 << 12, "AUTO_INVAL_DATA"),
+    (1 << 13, "DO_READDIRPLUS"),
+    (1 << 14, "READDIRPLUS_AUTO"),
+    (1 << 15, "ASYNC_DIO"),
+    (1 << 16, "WRITEBACK_CACHE"),
+    (1 << 17, "NO_OPEN_SUPPORT"),
+    (1 << 18, "PARALLEL_DIROPS"),
+    (1 << 19, "HANDLE_KILLPRIV"),
+    (1 << 20, "POSIX_ACL"),
+    (1 << 21, "ABORT_ERROR"),
+    (1 << 22, "MAX_PAGES"),
+    (1 << 23, "CACHE_SYMLINKS"),
+    (1 << 24, "NO_OPENDIR_SUPPORT"),
+    (1 << 25, "EXPLICIT_INVAL_DATA"),
+    (1 << 27, "ALLOCATE"),
+    (1 << 28, "EXCHANGE_DATA"),
+    (1 << 29, "CASE_INSENSITIVE"),
+    (1 << 30, "VOL_RENAME"),
+    (1 << 31, "XTIMES"),
+];
+
+const FATTR_FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "MODE"),
+    (1 << 1, "UID"),
+    (1 << 2, "GID"),
+    (1 << 3, "SIZE"),
+    (1 << 4, "ATIME"),
+    (1 << 5, "MTIME"),
+    (1 << 6, "FH"),
+    (1 << 7, "ATIME_NOW"),
+    (1 << 8, "MTIME_NOW"),
+    (1 << 9, "LOCKOWNER"),
+    (1 << 10, "CTIME"),
+    (1 << 11, "KILL_SUIDGID"),
+    #[cfg(target_os = "macos")]
+    (1 << 28, "CRTIME"),
+    #[cfg(target_os = "macos")]
+    (1 << 29, "CHGTIME"),
+    #[cfg(target_os = "macos")]
+    (1 << 30, "BKUPTIME"),
+    #[cfg(target_os = "macos")]
+    (1 << 31, "FLAGS"),
+];
+
+const FOPEN_FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "DIRECT_IO"),
+    (1 << 1, "KEEP_CACHE"),
+    (1 << 2, "NONSEEKABLE"),
+    (1 << 3, "CACHE_DIR"),
+    (1 << 4, "STREAM"),
+    (1 << 5, "NOFLUSH"),
+    (1 << 6, "PARALLEL_DIRECT_WRITES"),
+    (1 << 7, "PASSTHROUGH"),
+    #[cfg(target_os = "macos")]
+    (1 << 30, "PURGE_ATTR"),
+    #[cfg(target_os = "macos")]
+    (1 << 31, "PURGE_UBC"),
+];
+
+#[cfg(feature = "abi-7-9")]
+const GETATTR_FLAGS: &[(u64, &str)] = &[(1 << 0, "FH")];
+
+#[cfg(feature = "abi-7-9")]
+const READ_FLAGS: &[(u64, &str)] = &[(1 << 1, "LOCKOWNER")];
+
+const WRITE_FLAGS: &[(u64, &str)] = &[(1 << 0, "CACHE"), (1 << 1, "LOCKOWNER"), (1 << 2, "KILL_SUIDGID")];
+
+const RELEASE_FLAGS: &[(u64, &str)] = &[(1 << 0, "FLUSH"), (1 << 1, "FLOCK_UNLOCK")];
+
+const FSYNC_FLAGS: &[(u64, &str)] = &[(1 << 0, "FDATASYNC")];
+
+#[cfg(feature = "abi-7-9")]
+const LK_FLAGS: &[(u64, &str)] = &[(1 << 0, "FLOCK")];
+
+const XATTR_FLAGS: &[(u64, &str)] = &[(1 << 0, "XATTR_CREATE"), (1 << 1, "XATTR_REPLACE")];
+
+#[cfg(feature = "abi-7-11")]
+const IOCTL_FLAGS: &[(u64, &str)] = &[
+    (1 << 0, "COMPAT"),
+    (1 << 1, "UNRESTRICTED"),
+    (1 << 2, "RETRY"),
+    (1 << 3, "32BIT"),
+    (1 << 4, "DIR"),
+    (1 << 5, "COMPAT_X32"),
+];
+
+#[cfg(feature = "abi-7-11")]
+const POLL_FLAGS: &[(u64, &str)] = &[(1 << 0, "SCHEDULE_NOTIFY")];
+
+#[cfg(feature = "abi-7-19")]
+const FALLOCATE_FLAGS: &[(u64, &str)] = &[
+    (0x01, "KEEP_SIZE"),
+    (0x02, "PUNCH_HOLE"),
+    (0x08, "COLLAPSE_RANGE"),
+    (0x10, "ZERO_RANGE"),
+    (0x20, "INSERT_RANGE"),
+    (0x40, "UNSHARE_RANGE"),
+];
+
+#[cfg(feature = "abi-7-23")]
+const RENAME_FLAGS: &[(u64, &str)] = &[(1 << 0, "NOREPLACE"), (1 << 1, "EXCHANGE"), (1 << 2, "WHITEOUT")];
+
+const ERRNO_NAMES: &[(i32, &str)] = &[
+    (libc::EPERM, "EPERM"),
+    (libc::ENOENT, "ENOENT"),
+    (libc::ESRCH, "ESRCH"),
+    (libc::EINTR, "EINTR"),
+    (libc::EIO, "EIO"),
+    (libc::ENXIO, "ENXIO"),
+    (libc::E2BIG, "E2BIG"),
+    (libc::EBADF, "EBADF"),
+    (libc::EAGAIN, "EAGAIN"),
+    (libc::ENOMEM, "ENOMEM"),
+    (libc::EACCES, "EACCES"),
+    (libc::EFAULT, "EFAULT"),
+    (libc::EBUSY, "EBUSY"),
+    (libc::EEXIST, "EEXIST"),
+    (libc::EXDEV, "EXDEV"),
+    (libc::ENODEV, "ENODEV"),
+    (libc::ENOTDIR, "ENOTDIR"),
+    (libc::EISDIR, "EISDIR"),
+    (libc::EINVAL, "EINVAL"),
+    (libc::ENFILE, "ENFILE"),
+    (libc::EMFILE, "EMFILE"),
+    (libc::ENOTTY, "ENOTTY"),
+    (libc::EFBIG, "EFBIG"),
+    (libc::ENOSPC, "ENOSPC"),
+    (libc::ESPIPE, "ESPIPE"),
+    (libc::EROFS, "EROFS"),
+    (libc::EMLINK, "EMLINK"),
+    (libc::EPIPE, "EPIPE"),
+    (libc::ERANGE, "ERANGE"),
+    (libc::EDEADLK, "EDEADLK"),
+    (libc::ENAMETOOLONG, "ENAMETOOLONG"),
+    (libc::ENOLCK, "ENOLCK"),
+    (libc::ENOSYS, "ENOSYS"),
+    (libc::ENOTEMPTY, "ENOTEMPTY"),
+    (libc::ELOOP, "ELOOP"),
+    #[cfg(target_os = "linux")]
+    (libc::ENODATA, "ENODATA"),
+    #[cfg(not(target_os = "linux"))]
+    (libc::ENOATTR, "ENOATTR"),
+    (libc::EOVERFLOW, "EOVERFLOW"),
+    (libc::ENOTSUP, "ENOTSUP"),
+    (libc::ETIMEDOUT, "ETIMEDOUT"),
+    (libc::ESTALE, "ESTALE"),
+    (libc::EDQUOT, "EDQUOT"),
+    (libc::ECANCELED, "ECANCELED"),
+];
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::{FromZeros, IntoBytes};
+
+    use super::*;
+
+    #[test]
+    fn open() {
+        let mut header = fuse_in_header::new_zeroed();
+        header.len = (size_of::<fuse_in_header>() + size_of::<fuse_open_in>()) as u32;
+        header.opcode = fuse_opcode::FUSE_OPEN as u32;
+        header.unique = 5;
+        header.nodeid = 2;
+        header.uid = 1000;
+        header.gid = 1000;
+        header.pid = 42;
+        let mut arg = fuse_open_in::new_zeroed();
+        arg.flags = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+
+        let (reply_tx, _reply_rx) = crate::create_reply_channel();
+        let open = Request::parse(&[header.as_bytes(), arg.as_bytes()].concat(), &reply_tx).unwrap();
+
+        assert_eq!(
+            request(&open).to_string(),
+            "unique=5 OPEN nodeid=2 uid=1000 gid=1000 pid=42 flags=O_WRONLY|O_CREAT|O_TRUNC"
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let error = Reply::new(5, -libc::ENOENT, None);
+        assert_eq!(reply(&error).to_string(), "unique=5 <- ENOENT");
+
+        let mut arg = fuse_entry_out::new_zeroed();
+        arg.nodeid = 2;
+        arg.attr.mode = libc::S_IFREG | 0o644;
+        let lookup = Reply::new(4, 0, Some(reply::Operation::Lookup(reply::Lookup { arg })));
+        let line = reply(&lookup).to_string();
+        assert!(line.starts_with("unique=4 <- LOOKUP nodeid=2 "), "{}", line);
+        assert!(line.contains(" mode=S_IFREG|0644 "), "{}", line);
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(InitFlags::empty().to_string(), "0");
+        assert_eq!(
+            InitFlags::from_bits_retain(0b11 | 1 << 37 | 1 << 40).to_string(),
+            "ASYNC_READ|POSIX_LOCKS|PASSTHROUGH|0x10000000000"
+        );
+        assert_eq!(SetAttrValid(1 << 3 | 1 << 6).to_string(), "SIZE|FH");
+        assert_eq!(AccessMask(libc::R_OK | libc::X_OK).to_string(), "R_OK|X_OK");
+        assert_eq!(Mode(0o755).to_string(), "0755");
+    }
+}
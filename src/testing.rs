@@ -0,0 +1,658 @@
+//! Drive a filesystem through a session without mounting it.
+//!
+//! [FakeKernel] plays the part of the kernel at the far end of a [SocketTransport]. Each call encodes the request the
+//! kernel would send, pushes it through the session to the filesystem and decodes the reply. Replies that break the
+//! protocol fail the test with a panic:
+//!
+//! - the reply length must match the header and the size of the reply for the request
+//! - replies must answer the request just sent, and `FORGET` must not be answered at all
+//! - errors must be negative errno values
+//! - an inode may not be forgotten more often than it was looked up
+//!
+//! Only built with the `testing` feature.
+//!
+//! ```ignore
+//! let (request_tx, mut request_rx) = fusion::create_request_channel();
+//! tokio::spawn(async move { /* the filesystem answers requests from request_rx */ });
+//!
+//! let mut builder = Builder::new();
+//! builder.set_outbound_fs_request_tx(&request_tx);
+//! let mut kernel = FakeKernel::start(&mut builder).await?;
+//! let entry = kernel.lookup(FUSE_ROOT_ID, OsStr::new("hello")).await?;
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    sync::Arc,
+};
+
+use zerocopy::{FromBytes, FromZeros, IntoBytes, TryFromBytes};
+
+use crate::{
+    builder::Builder,
+    error::Errno,
+    init::SIZE_HEADER_ROOM,
+    messages::fuse_abi::*,
+    session::Session,
+    supported_init_flags,
+    transport::{SocketTransport, Transport},
+};
+
+/// `max_readahead` offered in `FUSE_INIT`
+const MAX_READAHEAD: u32 = 128 * 1024;
+
+/// Largest errno the kernel accepts in a reply
+const MAX_ERRNO: i32 = 4095;
+
+/// Entry decoded from a `READDIR` reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub ino: u64,
+    /// Offset of the next entry
+    pub offset: i64,
+    /// File type as in `st_mode`, e.g. [libc::S_IFREG]
+    pub kind: u32,
+    pub name: OsString,
+}
+
+/// Stand-in for the kernel side of a session
+///
+/// Requests are sent one at a time; each call waits for the filesystem's reply. Errors the filesystem answers with are
+/// returned as [Errno]. Protocol violations panic.
+pub struct FakeKernel {
+    transport: SocketTransport,
+    session: Session,
+    /// Agreed in the INIT handshake
+    max_write: u32,
+    next_unique: u64,
+    /// Replies are received here
+    buffer: Vec<u8>,
+    /// Outstanding lookups keyed by inode
+    lookups: HashMap<u64, u64>,
+    /// `unique` of every `FORGET` sent. None of them may be answered.
+    forgets: HashSet<u64>,
+}
+
+impl FakeKernel {
+    /// Open a session from `builder` over a socket pair and complete the INIT handshake.
+    ///
+    /// The filesystem must already be answering requests from the channel set with
+    /// [Builder::set_outbound_fs_request_tx]. The kernel offers [supported_init_flags]. Messages are limited in size
+    /// by the socket (see [SocketTransport]), so keep [crate::init::KernelConfig::max_write] small.
+    pub async fn start(builder: &mut Builder) -> Result<Self, Errno> {
+        let (transport, peer) = SocketTransport::pair()?;
+        let session = builder.open_with_transport(Arc::new(peer)).await?;
+
+        let mut kernel = Self {
+            transport,
+            session,
+            max_write: 0,
+            next_unique: 1,
+            buffer: vec![0u8; SIZE_HEADER_ROOM],
+            lookups: HashMap::new(),
+            forgets: HashSet::new(),
+        };
+
+        let mut arg = fuse_init_in::new_zeroed();
+        arg.major = FUSE_KERNEL_VERSION;
+        arg.minor = FUSE_KERNEL_MINOR_VERSION;
+        arg.max_readahead = MAX_READAHEAD;
+        (arg.flags, _) = supported_init_flags().split();
+        #[cfg(feature = "abi-7-36")]
+        {
+            (_, arg.flags2) = supported_init_flags().split();
+        }
+
+        let reply = kernel.call(fuse_opcode::FUSE_INIT, 0, &[arg.as_bytes()]).await?;
+        let out = fixed::<fuse_init_out>("INIT", &reply);
+
+        kernel.max_write = out.max_write;
+        kernel.buffer = vec![0u8; out.max_write as usize + SIZE_HEADER_ROOM];
+
+        Ok(kernel)
+    }
+
+    /// The session the filesystem is served by
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Lookups of `ino` not yet forgotten
+    pub fn lookup_count(&self, ino: u64) -> u64 {
+        self.lookups.get(&ino).copied().unwrap_or(0)
+    }
+
+    /// Look up `name` in directory `parent`. Counts a lookup of the returned inode.
+    pub async fn lookup(&mut self, parent: u64, name: &OsStr) -> Result<fuse_entry_out, Errno> {
+        let reply = self
+            .call(fuse_opcode::FUSE_LOOKUP, parent, &[name.as_bytes(), b"\0"])
+            .await?;
+        let entry = fixed::<fuse_entry_out>("LOOKUP", &reply);
+
+        // A zero nodeid caches a negative lookup and is not counted
+        if entry.nodeid != 0 {
+            *self.lookups.entry(entry.nodeid).or_default() += 1;
+        }
+
+        Ok(entry)
+    }
+
+    /// Drop `nlookup` lookups of `ino`. The filesystem does not answer.
+    pub async fn forget(&mut self, ino: u64, nlookup: u64) -> Result<(), Errno> {
+        let count = self.lookup_count(ino);
+        assert!(
+            nlookup <= count,
+            "lookup count: forgetting {} lookups of inode {} which has {}",
+            nlookup,
+            ino,
+            count
+        );
+
+        match count - nlookup {
+            0 => self.lookups.remove(&ino),
+            left => self.lookups.insert(ino, left),
+        };
+
+        let arg = fuse_forget_in { nlookup };
+        let unique = self.send(fuse_opcode::FUSE_FORGET, ino, &[arg.as_bytes()]).await?;
+        self.forgets.insert(unique);
+
+        Ok(())
+    }
+
+    /// Attributes of `ino`
+    pub async fn getattr(&mut self, ino: u64) -> Result<fuse_attr_out, Errno> {
+        #[cfg(feature = "abi-7-9")]
+        let reply = self
+            .call(
+                fuse_opcode::FUSE_GETATTR,
+                ino,
+                &[fuse_getattr_in::new_zeroed().as_bytes()],
+            )
+            .await?;
+        #[cfg(not(feature = "abi-7-9"))]
+        let reply = self.call(fuse_opcode::FUSE_GETATTR, ino, &[]).await?;
+
+        Ok(fixed("GETATTR", &reply))
+    }
+
+    /// Change the attributes of `ino` selected by `arg.valid`.
+    pub async fn setattr(&mut self, ino: u64, arg: &fuse_setattr_in) -> Result<fuse_attr_out, Errno> {
+        let reply = self.call(fuse_opcode::FUSE_SETATTR, ino, &[arg.as_bytes()]).await?;
+
+        Ok(fixed("SETATTR", &reply))
+    }
+
+    /// Open `ino` with `open(2)` `flags`.
+    pub async fn open(&mut self, ino: u64, flags: i32) -> Result<fuse_open_out, Errno> {
+        let mut arg = fuse_open_in::new_zeroed();
+        arg.flags = flags;
+
+        let reply = self.call(fuse_opcode::FUSE_OPEN, ino, &[arg.as_bytes()]).await?;
+
+        Ok(fixed("OPEN", &reply))
+    }
+
+    /// Open directory `ino`.
+    pub async fn opendir(&mut self, ino: u64, flags: i32) -> Result<fuse_open_out, Errno> {
+        let mut arg = fuse_open_in::new_zeroed();
+        arg.flags = flags;
+
+        let reply = self.call(fuse_opcode::FUSE_OPENDIR, ino, &[arg.as_bytes()]).await?;
+
+        Ok(fixed("OPENDIR", &reply))
+    }
+
+    /// Read up to `size` bytes at `offset`. `size` may not exceed the agreed `max_write`.
+    pub async fn read(&mut self, ino: u64, fh: u64, offset: i64, size: u32) -> Result<Vec<u8>, Errno> {
+        self.check_size(size);
+
+        let mut arg = fuse_read_in::new_zeroed();
+        arg.fh = fh;
+        arg.offset = offset;
+        arg.size = size;
+
+        let reply = self.call(fuse_opcode::FUSE_READ, ino, &[arg.as_bytes()]).await?;
+        assert!(
+            reply.len() <= size as usize,
+            "protocol violation: READ reply of {} bytes for {} requested",
+            reply.len(),
+            size
+        );
+
+        Ok(reply)
+    }
+
+    /// Write `data` at `offset` and return the number of bytes written.
+    pub async fn write(&mut self, ino: u64, fh: u64, offset: i64, data: &[u8]) -> Result<u32, Errno> {
+        self.check_size(data.len() as u32);
+
+        let mut arg = fuse_write_in::new_zeroed();
+        arg.fh = fh;
+        arg.offset = offset;
+        arg.size = data.len() as u32;
+
+        let reply = self.call(fuse_opcode::FUSE_WRITE, ino, &[arg.as_bytes(), data]).await?;
+        let out = fixed::<fuse_write_out>("WRITE", &reply);
+        assert!(
+            out.size as usize <= data.len(),
+            "protocol violation: WRITE of {} bytes reports {} written",
+            data.len(),
+            out.size
+        );
+
+        Ok(out.size)
+    }
+
+    /// Read the entries of directory `ino` from `offset` that fit in `size` bytes.
+    pub async fn readdir(&mut self, ino: u64, fh: u64, offset: i64, size: u32) -> Result<Vec<DirEntry>, Errno> {
+        self.check_size(size);
+
+        let mut arg = fuse_read_in::new_zeroed();
+        arg.fh = fh;
+        arg.offset = offset;
+        arg.size = size;
+
+        let reply = self.call(fuse_opcode::FUSE_READDIR, ino, &[arg.as_bytes()]).await?;
+        assert!(
+            reply.len() <= size as usize,
+            "protocol violation: READDIR reply of {} bytes for {} requested",
+            reply.len(),
+            size
+        );
+
+        Ok(dir_entries(&reply))
+    }
+
+    /// Move `name` in `parent` to `newname` in `newparent`.
+    pub async fn rename(&mut self, parent: u64, name: &OsStr, newparent: u64, newname: &OsStr) -> Result<(), Errno> {
+        let mut arg = fuse_rename_in::new_zeroed();
+        arg.newdir = newparent;
+
+        let reply = self
+            .call(
+                fuse_opcode::FUSE_RENAME,
+                parent,
+                &[arg.as_bytes(), name.as_bytes(), b"\0", newname.as_bytes(), b"\0"],
+            )
+            .await?;
+        empty("RENAME", &reply);
+
+        Ok(())
+    }
+
+    /// Release the file handle `fh` of `ino`.
+    pub async fn release(&mut self, ino: u64, fh: u64) -> Result<(), Errno> {
+        let mut arg = fuse_release_in::new_zeroed();
+        arg.fh = fh;
+
+        let reply = self.call(fuse_opcode::FUSE_RELEASE, ino, &[arg.as_bytes()]).await?;
+        empty("RELEASE", &reply);
+
+        Ok(())
+    }
+
+    /// Release the directory handle `fh` of `ino`.
+    pub async fn releasedir(&mut self, ino: u64, fh: u64) -> Result<(), Errno> {
+        let mut arg = fuse_release_in::new_zeroed();
+        arg.fh = fh;
+
+        let reply = self.call(fuse_opcode::FUSE_RELEASEDIR, ino, &[arg.as_bytes()]).await?;
+        empty("RELEASEDIR", &reply);
+
+        Ok(())
+    }
+
+    fn check_size(&self, size: u32) {
+        assert!(
+            size <= self.max_write,
+            "request of {} bytes exceeds max_write {}",
+            size,
+            self.max_write
+        );
+    }
+
+    /// Send a request built from `args` and return its `unique`.
+    async fn send(&mut self, opcode: fuse_opcode, nodeid: u64, args: &[&[u8]]) -> Result<u64, Errno> {
+        let unique = self.next_unique;
+        self.next_unique += 1;
+
+        let len = size_of::<fuse_in_header>() + args.iter().map(|arg| arg.len()).sum::<usize>();
+        let header = fuse_in_header {
+            len: len as u32,
+            opcode: opcode as u32,
+            unique,
+            nodeid,
+            uid: 0,
+            gid: 0,
+            pid: 0,
+            padding: 0,
+            #[cfg(feature = "abi-7-36")]
+            total_extlen: 0,
+        };
+
+        let mut message = Vec::with_capacity(len);
+        message.extend_from_slice(header.as_bytes());
+        for arg in args {
+            message.extend_from_slice(arg);
+        }
+
+        self.transport.send(&message).await?;
+
+        Ok(unique)
+    }
+
+    /// Send a request and wait for its reply. Returns the reply payload or the errno it carries.
+    async fn call(&mut self, opcode: fuse_opcode, nodeid: u64, args: &[&[u8]]) -> Result<Vec<u8>, Errno> {
+        let unique = self.send(opcode, nodeid, args).await?;
+
+        loop {
+            let count = self.transport.receive(&mut self.buffer).await?;
+            let Ok((header, payload)) = fuse_out_header::try_read_from_prefix(&self.buffer[..count]) else {
+                panic!("protocol violation: reply of {} bytes has no header", count);
+            };
+
+            assert_eq!(
+                header.len as usize, count,
+                "protocol violation: reply {} claims {} bytes but has {}",
+                header.unique, header.len, count
+            );
+
+            // Notifications
+            if header.unique == 0 {
+                continue;
+            }
+
+            assert!(
+                !self.forgets.contains(&header.unique),
+                "protocol violation: FORGET {} was answered",
+                header.unique
+            );
+            assert_eq!(
+                header.unique, unique,
+                "protocol violation: reply to {} while waiting for {}",
+                header.unique, unique
+            );
+            assert!(
+                (-MAX_ERRNO..=0).contains(&header.error),
+                "protocol violation: reply {} carries error {}",
+                unique,
+                header.error
+            );
+
+            if header.error != 0 {
+                assert!(
+                    payload.is_empty(),
+                    "protocol violation: error reply {} carries {} bytes",
+                    unique,
+                    payload.len()
+                );
+                return Err(Errno::from_i32(-header.error));
+            }
+
+            return Ok(payload.to_vec());
+        }
+    }
+}
+
+/// Decode a reply that must be exactly one `T`.
+fn fixed<T: FromBytes>(what: &str, reply: &[u8]) -> T {
+    match T::read_from_bytes(reply) {
+        Ok(out) => out,
+        Err(_e) => panic!(
+            "protocol violation: {} reply of {} bytes, expected {}",
+            what,
+            reply.len(),
+            size_of::<T>()
+        ),
+    }
+}
+
+/// Check a reply that carries no payload.
+fn empty(what: &str, reply: &[u8]) {
+    assert!(
+        reply.is_empty(),
+        "protocol violation: {} reply carries {} bytes",
+        what,
+        reply.len()
+    );
+}
+
+/// Decode the `fuse_dirent` records of a `READDIR` reply. Each is padded to 8 bytes.
+fn dir_entries(mut reply: &[u8]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+
+    while !reply.is_empty() {
+        let Ok((dirent, rest)) = fuse_dirent::read_from_prefix(reply) else {
+            panic!("protocol violation: READDIR reply ends within an entry");
+        };
+
+        let namelen = dirent.namelen as usize;
+        let padded = (namelen + 7) & !7;
+        assert!(
+            namelen > 0 && padded <= rest.len(),
+            "protocol violation: READDIR entry name of {} bytes in {} remaining",
+            namelen,
+            rest.len()
+        );
+
+        entries.push(DirEntry {
+            ino: dirent.ino,
+            offset: dirent.off,
+            kind: dirent.typ << 12,
+            name: OsString::from_vec(rest[..namelen].to_vec()),
+        });
+        reply = &rest[padded..];
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        init::KernelConfig,
+        messages::{reply, request::Operation},
+    };
+
+    const HELLO: u64 = 2;
+
+    /// Serve a root directory holding the file `hello`, which can be written, truncated and renamed
+    async fn hello() -> FakeKernel {
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        tokio::spawn(async move {
+            let mut name = OsString::from("hello");
+            let mut data = b"hello world".to_vec();
+
+            while let Some(request) = request_rx.recv().await {
+                let mut entry = fuse_entry_out::new_zeroed();
+                entry.nodeid = HELLO;
+                entry.attr.ino = HELLO;
+                entry.attr.mode = libc::S_IFREG | 0o644;
+                entry.attr.size = data.len() as u64;
+
+                let _ = match &request.operation {
+                    Operation::Lookup(x) if x.name == name => request.reply_entry(entry).await,
+                    Operation::Open(_) | Operation::OpenDir(_) => request.reply_open(fuse_open_out::new_zeroed()).await,
+                    Operation::Read(x) => {
+                        let data = data.iter().skip(x.arg.offset as usize);
+                        request
+                            .reply_data(data.take(x.arg.size as usize).copied().collect())
+                            .await
+                    }
+                    Operation::Write(x) => {
+                        let offset = x.arg.offset as usize;
+                        let end = offset + x.data.len();
+                        if data.len() < end {
+                            data.resize(end, 0);
+                        }
+                        data[offset..end].copy_from_slice(&x.data);
+                        request.reply_write(x.data.len() as u32).await
+                    }
+                    Operation::SetAttr(x) if request.header.nodeid == HELLO => {
+                        if x.arg.valid & crate::constants::FATTR_SIZE != 0 {
+                            data.resize(x.arg.size as usize, 0);
+                        }
+                        let mut out = fuse_attr_out::new_zeroed();
+                        out.attr = entry.attr;
+                        out.attr.size = data.len() as u64;
+                        request.reply_attr(out).await
+                    }
+                    Operation::Rename(x) if x.name == name && x.arg.newdir == FUSE_ROOT_ID => {
+                        name = x.newname.clone();
+                        request.send_ok().await
+                    }
+                    Operation::ReadDir(x) => {
+                        let mut dirent = fuse_dirent::new_zeroed();
+                        dirent.ino = HELLO;
+                        dirent.off = 1;
+                        dirent.set_type(libc::S_IFREG);
+                        let entries = match x.arg.offset {
+                            0 => vec![reply::DirectoryEntry {
+                                entry: dirent,
+                                name: name.clone(),
+                            }],
+                            _ => Vec::new(),
+                        };
+                        let operation = reply::Operation::ReadDir(reply::ReadDir { entries });
+                        request
+                            .send(reply::Reply::new(request.header.unique, 0, Some(operation)))
+                            .await
+                    }
+                    Operation::Release(_) | Operation::ReleaseDir(_) => request.send_ok().await,
+                    Operation::Forget(_) => Ok(()),
+                    _ => request.send_error(Errno::ENOENT).await,
+                };
+            }
+        });
+
+        let mut builder = Builder::new();
+        builder
+            .set_outbound_fs_request_tx(&request_tx)
+            .set_kernel_config(KernelConfig {
+                max_write: 4096,
+                ..Default::default()
+            });
+
+        FakeKernel::start(&mut builder).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn drive_filesystem() {
+        let mut kernel = hello().await;
+
+        assert_eq!(
+            kernel.lookup(FUSE_ROOT_ID, OsStr::new("nope")).await.unwrap_err(),
+            Errno::ENOENT
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                kernel.lookup(FUSE_ROOT_ID, OsStr::new("hello")).await.unwrap().nodeid,
+                HELLO
+            );
+        }
+        assert_eq!(kernel.lookup_count(HELLO), 2);
+
+        let fh = kernel.open(HELLO, libc::O_RDONLY).await.unwrap().fh;
+        assert_eq!(kernel.read(HELLO, fh, 6, 100).await.unwrap(), b"world");
+        kernel.release(HELLO, fh).await.unwrap();
+
+        let fh = kernel.opendir(FUSE_ROOT_ID, 0).await.unwrap().fh;
+        let entries = kernel.readdir(FUSE_ROOT_ID, fh, 0, 4096).await.unwrap();
+        assert_eq!(
+            entries,
+            vec![DirEntry {
+                ino: HELLO,
+                offset: 1,
+                kind: libc::S_IFREG,
+                name: "hello".into(),
+            }]
+        );
+        assert!(kernel.readdir(FUSE_ROOT_ID, fh, 1, 4096).await.unwrap().is_empty());
+        kernel.releasedir(FUSE_ROOT_ID, fh).await.unwrap();
+
+        kernel.forget(HELLO, 2).await.unwrap();
+        assert_eq!(kernel.lookup_count(HELLO), 0);
+        // Any reply to the FORGET would show up here
+        kernel.getattr(HELLO).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn modify_filesystem() {
+        let mut kernel = hello().await;
+
+        let fh = kernel.open(HELLO, libc::O_RDWR).await.unwrap().fh;
+        assert_eq!(kernel.write(HELLO, fh, 0, b"HELLO").await.unwrap(), 5);
+        assert_eq!(kernel.write(HELLO, fh, 11, b"!").await.unwrap(), 1);
+        assert_eq!(kernel.read(HELLO, fh, 0, 100).await.unwrap(), b"HELLO world!");
+
+        let mut arg = fuse_setattr_in::new_zeroed();
+        arg.valid = crate::constants::FATTR_SIZE;
+        arg.size = 5;
+        assert_eq!(kernel.setattr(HELLO, &arg).await.unwrap().attr.size, 5);
+        assert_eq!(kernel.read(HELLO, fh, 0, 100).await.unwrap(), b"HELLO");
+        kernel.release(HELLO, fh).await.unwrap();
+
+        kernel
+            .rename(FUSE_ROOT_ID, OsStr::new("hello"), FUSE_ROOT_ID, OsStr::new("world"))
+            .await
+            .unwrap();
+        assert_eq!(
+            kernel
+                .rename(FUSE_ROOT_ID, OsStr::new("hello"), FUSE_ROOT_ID, OsStr::new("again"))
+                .await
+                .unwrap_err(),
+            Errno::ENOENT
+        );
+        assert_eq!(
+            kernel.lookup(FUSE_ROOT_ID, OsStr::new("hello")).await.unwrap_err(),
+            Errno::ENOENT
+        );
+        let entry = kernel.lookup(FUSE_ROOT_ID, OsStr::new("world")).await.unwrap();
+        assert_eq!(entry.attr.size, 5);
+
+        let fh = kernel.opendir(FUSE_ROOT_ID, 0).await.unwrap().fh;
+        let entries = kernel.readdir(FUSE_ROOT_ID, fh, 0, 4096).await.unwrap();
+        assert_eq!(entries[0].name, "world");
+        kernel.releasedir(FUSE_ROOT_ID, fh).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "WRITE of 2 bytes reports 3 written")]
+    async fn write_overreported() {
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        tokio::spawn(async move {
+            while let Some(request) = request_rx.recv().await {
+                let _ = match &request.operation {
+                    Operation::Write(x) => request.reply_write(x.data.len() as u32 + 1).await,
+                    _ => request.send_error(Errno::ENOSYS).await,
+                };
+            }
+        });
+
+        let mut builder = Builder::new();
+        builder
+            .set_outbound_fs_request_tx(&request_tx)
+            .set_kernel_config(KernelConfig {
+                max_write: 4096,
+                ..Default::default()
+            });
+        let mut kernel = FakeKernel::start(&mut builder).await.unwrap();
+
+        kernel.write(HELLO, 0, 0, b"hi").await.unwrap();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "lookup count")]
+    async fn forget_unbalanced() {
+        let mut kernel = hello().await;
+
+        kernel.lookup(FUSE_ROOT_ID, OsStr::new("hello")).await.unwrap();
+        kernel.forget(HELLO, 2).await.unwrap();
+    }
+}
@@ -0,0 +1,591 @@
+//! Record raw FUSE traffic to a file and replay it against a filesystem.
+//!
+//! A [Recorder] handed to [crate::builder::Builder::set_recorder] appends every message the session receives from or
+//! sends to the kernel to a trace file. A [Replayer] feeds the recorded requests to any filesystem's [RequestTx] and
+//! compares its replies with the recorded ones, which turns a captured incident into a regression test.
+//!
+//! # Format
+//!
+//! A trace starts with a file header followed by any number of frames. Integers in the file and frame headers are
+//! little-endian; the messages are stored exactly as they crossed the device.
+//!
+//! | Offset | Size | File header                               |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 8    | magic, `b"FUSETRCE"`                      |
+//! | 8      | 4    | format version, [VERSION]                 |
+//! | 12     | 4    | reserved, zero                            |
+//!
+//! | Offset | Size | Frame                                     |
+//! |--------|------|-------------------------------------------|
+//! | 0      | 8    | timestamp, nanoseconds since the epoch    |
+//! | 8      | 4    | [Direction]                               |
+//! | 12     | 4    | length of the message                     |
+//! | 16     | len  | message: `fuse_in_header` or `fuse_out_header` and arguments |
+//!
+//! Notifications are recorded as replies with `unique` 0.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, IoSlice, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use log::{error, trace};
+use tokio::sync::{mpsc, oneshot};
+use zerocopy::{
+    byteorder::little_endian::{U32, U64},
+    FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout, TryFromBytes,
+};
+
+use crate::{
+    error::Errno,
+    messages::{
+        fuse_abi::{fuse_out_header, FUSE_KERNEL_MINOR_VERSION},
+        reply::{IWrite, Reply},
+        request::{Operation, Request},
+    },
+    transport::Transport,
+    RequestTx,
+};
+
+/// Current version of the trace format
+pub const VERSION: u32 = 1;
+
+const MAGIC: [u8; 8] = *b"FUSETRCE";
+
+/// How long [Replayer] waits for each reply unless told otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[repr(C)]
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+struct FileHeader {
+    magic: [u8; 8],
+    version: U32,
+    reserved: U32,
+}
+
+#[repr(C)]
+#[derive(IntoBytes, FromBytes, KnownLayout, Immutable)]
+struct FrameHeader {
+    timestamp: U64,
+    direction: U32,
+    len: U32,
+}
+
+/// Which way a frame crossed the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Direction {
+    /// Kernel to filesystem
+    Request = 0,
+    /// Filesystem to kernel, including notifications
+    Reply = 1,
+}
+
+/// A single recorded message
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Appends frames to a trace file
+///
+/// Frames are handed to a writer thread so that recording never blocks the session. Failures to write are logged and
+/// otherwise ignored: recording never stops the session. Clones append to the same file.
+#[derive(Clone)]
+pub struct Recorder {
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+/// Work for the writer thread of a [Recorder]
+enum Command {
+    Frame(Vec<u8>),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+impl Recorder {
+    /// Create (or truncate) the trace file at `path`, write its header and start the writer thread.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let header = FileHeader {
+            magic: MAGIC,
+            version: VERSION.into(),
+            reserved: 0.into(),
+        };
+        file.write_all(header.as_bytes())?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::Builder::new()
+            .name("fusion-trace".into())
+            .spawn(move || write_frames(file, rx))?;
+
+        Ok(Self { tx })
+    }
+
+    /// Append `data` as a single frame stamped with the current time.
+    pub fn record(&self, direction: Direction, data: &[u8]) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let header = FrameHeader {
+            timestamp: (timestamp.as_nanos() as u64).into(),
+            direction: (direction as u32).into(),
+            len: (data.len() as u32).into(),
+        };
+
+        let frame = [header.as_bytes(), data].concat();
+        if self.tx.send(Command::Frame(frame)).is_err() {
+            error!("trace writer stopped");
+        }
+    }
+
+    /// Wait until the frames recorded so far are written to the file.
+    pub async fn flush(&self) -> io::Result<()> {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.send(Command::Flush(tx)).is_err() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        rx.await.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?
+    }
+}
+
+/// Body of the writer thread. Stops once every [Recorder] handle is dropped.
+fn write_frames(mut file: BufWriter<File>, mut rx: mpsc::UnboundedReceiver<Command>) {
+    while let Some(command) = rx.blocking_recv() {
+        match command {
+            Command::Frame(frame) => {
+                if let Err(e) = file.write_all(&frame) {
+                    error!("trace write failed: {}", e);
+                }
+            }
+            Command::Flush(done) => {
+                let _ = done.send(file.flush());
+            }
+        }
+    }
+
+    if let Err(e) = file.flush() {
+        error!("trace write failed: {}", e);
+    }
+}
+
+/// Reads the frames of a trace file in order
+pub struct TraceReader<R> {
+    reader: R,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Check the file header at the start of `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = FileHeader::new_zeroed();
+        reader.read_exact(header.as_mut_bytes())?;
+
+        if header.magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a FUSE trace"));
+        }
+        if header.version.get() != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported trace version {}", header.version),
+            ));
+        }
+
+        Ok(Self { reader })
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = FrameHeader::new_zeroed();
+
+        // End of file between frames is the end of the trace; anywhere else it is truncated
+        let count = read_full(&mut self.reader, header.as_mut_bytes())?;
+        if count == 0 {
+            return Ok(None);
+        }
+        if count < size_of::<FrameHeader>() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let direction = match header.direction.get() {
+            0 => Direction::Request,
+            1 => Direction::Reply,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown direction {}", other),
+                ))
+            }
+        };
+
+        let mut data = vec![0u8; header.len.get() as usize];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(Frame {
+            timestamp: UNIX_EPOCH + Duration::from_nanos(header.timestamp.get()),
+            direction,
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Read until `buffer` is full or the end of `reader`, returning the number of bytes read
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut count = 0;
+
+    while count < buffer.len() {
+        match reader.read(&mut buffer[count..]) {
+            Ok(0) => break,
+            Ok(n) => count += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(count)
+}
+
+/// Records everything passing through `inner`
+pub(crate) struct RecordingTransport {
+    pub(crate) inner: Arc<dyn Transport>,
+    pub(crate) recorder: Arc<Recorder>,
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+    async fn receive(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.receive(buffer).await?;
+        self.recorder.record(Direction::Request, &buffer[..count]);
+        Ok(count)
+    }
+
+    async fn send(&self, buffer: &[u8]) -> io::Result<usize> {
+        self.recorder.record(Direction::Reply, buffer);
+        self.inner.send(buffer).await
+    }
+
+    async fn send_vectored(&self, buffers: &[IoSlice<'_>]) -> io::Result<usize> {
+        let message = buffers
+            .iter()
+            .flat_map(|buffer| buffer.iter().copied())
+            .collect::<Vec<u8>>();
+        self.recorder.record(Direction::Reply, &message);
+        self.inner.send_vectored(buffers).await
+    }
+}
+
+/// A replayed request whose reply differs from the recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub unique: u64,
+    pub opcode: u32,
+    /// The recorded reply, `None` if the trace holds none
+    pub expected: Option<Vec<u8>>,
+    /// The filesystem's reply, `None` if it did not answer in time
+    pub actual: Option<Vec<u8>>,
+}
+
+/// Outcome of [Replayer::replay]
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Requests sent to the filesystem
+    pub replayed: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Sends recorded requests to a filesystem and compares its replies with the recorded ones
+///
+/// Requests are sent one at a time in recorded order, each after the previous one was answered. Those the session
+/// answers itself (`INIT`, `INTERRUPT`, `NOTIFY_REPLY`) are skipped; `FORGET` and `BATCH_FORGET` are sent without
+/// waiting for a reply. Replies are serialized as the session would and compared byte for byte.
+pub struct Replayer {
+    request_tx: RequestTx,
+    /// How long to wait for each reply
+    timeout: Duration,
+    /// Reply for requests the filesystem drops without answering
+    unanswered_errno: Errno,
+}
+
+impl Replayer {
+    pub fn new(request_tx: &RequestTx) -> Self {
+        Self {
+            request_tx: request_tx.clone(),
+            timeout: DEFAULT_TIMEOUT,
+            unanswered_errno: Errno::EIO,
+        }
+    }
+
+    /// How long to wait for each reply before recording it as missing. Defaults to 5 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reply for requests the filesystem drops without answering, as set with
+    /// [crate::builder::Builder::set_unanswered_errno]. Defaults to [Errno::EIO].
+    pub fn set_unanswered_errno(&mut self, errno: Errno) -> &mut Self {
+        self.unanswered_errno = errno;
+        self
+    }
+
+    /// Replay the requests in `frames` and report the replies that differ.
+    ///
+    /// Requests are parsed and replies written in the protocol minor agreed by the recorded `FUSE_INIT`, if any.
+    ///
+    /// Fails with [Errno::EIO] if a recorded request cannot be parsed or the filesystem stops receiving requests.
+    pub async fn replay(&self, frames: impl IntoIterator<Item = Frame>) -> Result<ReplayReport, Errno> {
+        let (requests, mut expected) = split(frames);
+        let (reply_tx, mut reply_rx) = crate::create_reply_channel();
+        let mut report = ReplayReport::default();
+        let mut buffer = vec![0u8; crate::SIZE_BUFFER];
+        let mut minor = FUSE_KERNEL_MINOR_VERSION;
+
+        for data in requests {
+            let mut request = Request::parse_versioned(&data.into(), minor, &reply_tx).map_err(|e| {
+                error!("recorded request does not parse: {}", e);
+                Errno::EIO
+            })?;
+
+            let unique = request.header.unique;
+            let opcode = request.header.opcode;
+
+            match request.operation {
+                Operation::Init(init) => {
+                    minor = init.arg.minor.min(FUSE_KERNEL_MINOR_VERSION);
+                    continue;
+                }
+                Operation::Interrupt(_) => continue,
+                #[cfg(feature = "abi-7-15")]
+                Operation::NotifyReply(_) => continue,
+                _ => {}
+            }
+
+            let expects_reply = request.operation.expects_reply();
+            let reply_size = request.operation.reply_size();
+            if expects_reply {
+                request.arm_reply_guard(self.unanswered_errno);
+            }
+
+            if let Err(_e) = self.request_tx.send(request).await {
+                error!("channel send");
+                return Err(Errno::EIO);
+            }
+            report.replayed += 1;
+
+            if !expects_reply {
+                continue;
+            }
+
+            let actual = match self.receive(&mut reply_rx, unique).await {
+                Some(mut reply) => Some(match reply.write_versioned(&mut buffer, reply_size, minor) {
+                    Ok(count) => buffer[..count].to_vec(),
+                    Err(e) => {
+                        let count = Reply::new(unique, e.into(), None).write(&mut buffer)?;
+                        buffer[..count].to_vec()
+                    }
+                }),
+                None => None,
+            };
+
+            let expected = expected.remove(&unique);
+            if actual != expected {
+                report.mismatches.push(Mismatch {
+                    unique,
+                    opcode,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Wait for the reply to `unique`. Late replies to requests that already timed out are dropped.
+    async fn receive(&self, reply_rx: &mut crate::ReplyRx, unique: u64) -> Option<Reply> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+
+        loop {
+            match tokio::time::timeout_at(deadline, reply_rx.recv()).await {
+                Ok(Some(reply)) if reply.header.unique == unique => return Some(reply),
+                Ok(Some(reply)) => trace!("dropping late reply {}", reply.header.unique),
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Separate recorded requests from recorded replies, keyed by `unique`. Notifications are dropped.
+fn split(frames: impl IntoIterator<Item = Frame>) -> (Vec<Vec<u8>>, HashMap<u64, Vec<u8>>) {
+    let mut requests = Vec::new();
+    let mut replies = HashMap::new();
+
+    for frame in frames {
+        match frame.direction {
+            Direction::Request => requests.push(frame.data),
+            Direction::Reply => {
+                if let Ok((header, _rest)) = fuse_out_header::try_read_from_prefix(&frame.data) {
+                    if header.unique != 0 {
+                        replies.insert(header.unique, frame.data);
+                    }
+                }
+            }
+        }
+    }
+
+    (requests, replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+    use crate::{
+        builder::Builder,
+        init::KernelConfig,
+        messages::fuse_abi::{fuse_entry_out, fuse_opcode, FUSE_ROOT_ID},
+        testing::FakeKernel,
+    };
+
+    const HELLO: u64 = 2;
+
+    /// Serve a root directory holding the file `hello` of `size` bytes. Looking up `slow` takes 200ms, `medium`
+    /// 100ms.
+    fn serve(size: u64) -> RequestTx {
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        tokio::spawn(async move {
+            while let Some(request) = request_rx.recv().await {
+                let mut entry = fuse_entry_out::new_zeroed();
+                entry.nodeid = HELLO;
+                entry.attr.ino = HELLO;
+                entry.attr.size = size;
+
+                tokio::spawn(async move {
+                    let _ = match &request.operation {
+                        Operation::Lookup(x) if x.name == "hello" => request.reply_entry(entry).await,
+                        Operation::Lookup(x) if x.name == "slow" || x.name == "medium" => {
+                            let delay = if x.name == "slow" { 200 } else { 100 };
+                            tokio::time::sleep(Duration::from_millis(delay)).await;
+                            request.send_error(Errno::ENOENT).await
+                        }
+                        Operation::Forget(_) => Ok(()),
+                        _ => request.send_error(Errno::ENOENT).await,
+                    };
+                });
+            }
+        });
+
+        request_tx
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+
+        let recorder = Recorder::create(&path).unwrap();
+        let mut builder = Builder::new();
+        builder
+            .set_outbound_fs_request_tx(&serve(11))
+            .set_kernel_config(KernelConfig {
+                max_write: 4096,
+                ..Default::default()
+            })
+            .set_recorder(recorder.clone());
+
+        let mut kernel = FakeKernel::start(&mut builder).await.unwrap();
+        kernel.lookup(FUSE_ROOT_ID, OsStr::new("hello")).await.unwrap();
+        kernel.lookup(FUSE_ROOT_ID, OsStr::new("nope")).await.unwrap_err();
+        kernel.forget(HELLO, 1).await.unwrap();
+        // Answered after the FORGET was received, so the trace is complete once it returns
+        kernel.getattr(HELLO).await.unwrap_err();
+        recorder.flush().await.unwrap();
+
+        let frames = TraceReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<Frame>>>()
+            .unwrap();
+        let directions = frames.iter().map(|frame| frame.direction).collect::<Vec<_>>();
+        use Direction::{Reply as Out, Request as In};
+        assert_eq!(directions, [In, Out, In, Out, In, Out, In, In, Out]);
+
+        // The same filesystem answers the same way
+        let report = Replayer::new(&serve(11)).replay(frames.clone()).await.unwrap();
+        assert_eq!(report.replayed, 4);
+        assert_eq!(report.mismatches, []);
+
+        // A different one does not
+        let report = Replayer::new(&serve(12)).replay(frames.clone()).await.unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].opcode, fuse_opcode::FUSE_LOOKUP as u32);
+        assert_ne!(report.mismatches[0].actual, report.mismatches[0].expected);
+    }
+
+    #[tokio::test]
+    async fn late_reply_dropped() {
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+
+        let recorder = Recorder::create(&path).unwrap();
+        let mut builder = Builder::new();
+        builder
+            .set_outbound_fs_request_tx(&serve(11))
+            .set_kernel_config(KernelConfig {
+                max_write: 4096,
+                ..Default::default()
+            })
+            .set_recorder(recorder.clone());
+
+        let mut kernel = FakeKernel::start(&mut builder).await.unwrap();
+        kernel.lookup(FUSE_ROOT_ID, OsStr::new("slow")).await.unwrap_err();
+        kernel.lookup(FUSE_ROOT_ID, OsStr::new("medium")).await.unwrap_err();
+        recorder.flush().await.unwrap();
+        let frames = TraceReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<Vec<Frame>>>()
+            .unwrap();
+
+        // The reply to `slow` turns up while `medium` is waited for and is not taken for its answer
+        let report = Replayer::new(&serve(11))
+            .set_timeout(Duration::from_millis(150))
+            .replay(frames)
+            .await
+            .unwrap();
+        assert_eq!(report.replayed, 2);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].actual, None);
+    }
+
+    #[test]
+    fn truncated_trace() {
+        let mut trace = Vec::new();
+        trace.extend_from_slice(&MAGIC);
+        trace.extend_from_slice(&VERSION.to_le_bytes());
+        trace.extend_from_slice(&[0; 4]);
+        trace.extend_from_slice(&[0; 8]);
+
+        let mut reader = TraceReader::new(trace.as_slice()).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        assert!(TraceReader::new(&b"not a trace at all"[..]).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! The channel a session exchanges messages with the kernel over.
+//!
+//! Normally that is the FUSE device opened by [crate::builder::Builder::open]. Any other [Transport] can be handed to
+//! [crate::builder::Builder::open_with_transport] to run a session without a mount, e.g. a [SocketTransport] whose
+//! peer plays the part of the kernel in tests.
+//!
+//! Splicing (see [crate::builder::Builder::set_splice_read] and [crate::messages::reply::Read::File]) needs the
+//! device and falls back to copying on any other transport.
+
+use std::{
+    io::{self, IoSlice},
+    os::fd::OwnedFd,
+};
+
+use async_trait::async_trait;
+use nix::sys::socket::{socketpair, AddressFamily, SockFlag, SockType};
+
+use crate::device::Device;
+
+/// Message-oriented duplex channel to the kernel
+///
+/// Each call moves exactly one message: a request on the way in, a reply or notification on the way out.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Receive a single message into `buffer` and return its length.
+    async fn receive(&self, buffer: &mut [u8]) -> io::Result<usize>;
+
+    /// Send a single message from `buffer`.
+    async fn send(&self, buffer: &[u8]) -> io::Result<usize>;
+
+    /// Send a single message gathered from `buffers`. Copies them into one buffer unless overridden.
+    async fn send_vectored(&self, buffers: &[IoSlice<'_>]) -> io::Result<usize> {
+        let message = buffers
+            .iter()
+            .flat_map(|buffer| buffer.iter().copied())
+            .collect::<Vec<u8>>();
+        self.send(&message).await
+    }
+}
+
+#[async_trait]
+impl Transport for Device {
+    async fn receive(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.read(buffer).await
+    }
+
+    async fn send(&self, buffer: &[u8]) -> io::Result<usize> {
+        self.write(buffer).await
+    }
+
+    async fn send_vectored(&self, buffers: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.write_vectored(buffers).await
+    }
+}
+
+/// One end of a connected `SOCK_SEQPACKET` socket pair
+///
+/// The other end stands in for the kernel: it sends requests and receives replies through the same [Transport]
+/// methods. Messages are limited to the socket send buffer size (`net.core.wmem_default`), so keep
+/// [crate::init::KernelConfig::max_write] small. Once the peer is dropped, receiving fails with `ENODEV` as reading
+/// the device does once the filesystem is unmounted.
+pub struct SocketTransport {
+    socket: Device,
+}
+
+impl SocketTransport {
+    /// Create a connected pair. Must be called from within a tokio runtime.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = socketpair(AddressFamily::Unix, SockType::SeqPacket, None, SockFlag::SOCK_CLOEXEC)?;
+
+        Ok((Self::new(a)?, Self::new(b)?))
+    }
+
+    fn new(fd: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            socket: Device::new(fd)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for SocketTransport {
+    async fn receive(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self.socket.read(buffer).await? {
+            0 => Err(io::Error::from_raw_os_error(libc::ENODEV)),
+            count => Ok(count),
+        }
+    }
+
+    async fn send(&self, buffer: &[u8]) -> io::Result<usize> {
+        self.socket.write(buffer).await
+    }
+
+    async fn send_vectored(&self, buffers: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.socket.write_vectored(buffers).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use zerocopy::{FromZeros, IntoBytes, TryFromBytes};
+
+    use super::*;
+    use crate::{builder::Builder, error::Errno, init::KernelConfig, messages::fuse_abi::*};
+
+    fn message(opcode: fuse_opcode, unique: u64, arg: &[u8]) -> Vec<u8> {
+        let header = fuse_in_header {
+            len: (size_of::<fuse_in_header>() + arg.len()) as u32,
+            opcode: opcode as u32,
+            unique,
+            nodeid: 1,
+            uid: 0,
+            gid: 0,
+            pid: 0,
+            padding: 0,
+            #[cfg(feature = "abi-7-36")]
+            total_extlen: 0,
+        };
+        [header.as_bytes(), arg].concat()
+    }
+
+    #[tokio::test]
+    async fn session_over_socket() {
+        let (kernel, transport) = SocketTransport::pair().unwrap();
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        let session = Builder::new()
+            .set_outbound_fs_request_tx(&request_tx)
+            .set_kernel_config(KernelConfig {
+                max_write: 4096,
+                ..Default::default()
+            })
+            .open_with_transport(Arc::new(transport))
+            .await
+            .unwrap();
+
+        let mut buffer = vec![0u8; 4096];
+
+        let mut init = vec![0u8; size_of::<fuse_init_in>()];
+        init[..4].copy_from_slice(&FUSE_KERNEL_VERSION.to_ne_bytes());
+        init[4..8].copy_from_slice(&FUSE_KERNEL_MINOR_VERSION.to_ne_bytes());
+        kernel.send(&message(fuse_opcode::FUSE_INIT, 1, &init)).await.unwrap();
+
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, _) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (1, 0));
+        session.initialized().await.unwrap();
+
+        kernel
+            .send(&message(fuse_opcode::FUSE_LOOKUP, 2, b"foo\0"))
+            .await
+            .unwrap();
+        let request = request_rx.recv().await.unwrap();
+        request.send_error(Errno::ENOENT).await.unwrap();
+
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, _) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (2, Errno::ENOENT.into()));
+
+        // Hanging up stops the session as unmounting does
+        drop(kernel);
+        tokio::time::timeout(Duration::from_secs(1), session.cancellation_token.cancelled())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_file_over_socket() {
+        use std::{io::Write, os::fd::OwnedFd};
+
+        use crate::messages::reply::{self, Reply};
+
+        let (kernel, transport) = SocketTransport::pair().unwrap();
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        let _session = Builder::new()
+            .set_outbound_fs_request_tx(&request_tx)
+            .open_with_transport(Arc::new(transport))
+            .await
+            .unwrap();
+
+        let mut buffer = vec![0u8; 4096];
+
+        let mut init = fuse_init_in::new_zeroed();
+        init.major = FUSE_KERNEL_VERSION;
+        init.minor = FUSE_KERNEL_MINOR_VERSION;
+        kernel
+            .send(&message(fuse_opcode::FUSE_INIT, 1, init.as_bytes()))
+            .await
+            .unwrap();
+        kernel.receive(&mut buffer).await.unwrap();
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"0123456789").unwrap();
+        let fd = Arc::new(OwnedFd::from(file));
+
+        // Read on the blocking pool and copied, as nothing is spliced into a socket
+        let mut arg = fuse_read_in::new_zeroed();
+        arg.size = 8;
+        kernel
+            .send(&message(fuse_opcode::FUSE_READ, 2, arg.as_bytes()))
+            .await
+            .unwrap();
+        let request = request_rx.recv().await.unwrap();
+        let operation = reply::Operation::Read(reply::Read::File { fd, offset: 6, len: 8 });
+        request.send(Reply::new(2, 0, Some(operation))).await.unwrap();
+
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, rest) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (2, 0));
+        assert_eq!(rest, b"6789");
+    }
+
+    /// Run `FUSE_INIT` over a fresh socket offering `SECURITY_CTX` and return the flags2 replied
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    async fn init_flags2(builder: &mut Builder) -> u32 {
+        use crate::constants::{FUSE_INIT_EXT, FUSE_SECURITY_CTX};
+
+        let (kernel, transport) = SocketTransport::pair().unwrap();
+        // The previous session cancelled the builder's token when its socket was dropped
+        builder.set_cancellation_token(&tokio_util::sync::CancellationToken::new());
+        let _session = builder.open_with_transport(Arc::new(transport)).await.unwrap();
+
+        let mut init = fuse_init_in::new_zeroed();
+        init.major = FUSE_KERNEL_VERSION;
+        init.minor = FUSE_KERNEL_MINOR_VERSION;
+        init.flags = FUSE_INIT_EXT;
+        init.flags2 = (FUSE_SECURITY_CTX >> 32) as u32;
+        kernel
+            .send(&message(fuse_opcode::FUSE_INIT, 1, init.as_bytes()))
+            .await
+            .unwrap();
+
+        let mut buffer = vec![0u8; 4096];
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, rest) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (1, 0));
+        fuse_init_out::try_read_from_prefix(rest).unwrap().0.flags2
+    }
+
+    #[cfg(all(feature = "abi-7-36", not(target_os = "macos")))]
+    #[tokio::test]
+    async fn extensions_not_kept_between_opens() {
+        use crate::constants::FUSE_SECURITY_CTX;
+
+        let (request_tx, _request_rx) = crate::create_request_channel();
+
+        let mut builder = Builder::new();
+        builder.set_outbound_fs_request_tx(&request_tx);
+        assert_eq!(init_flags2(&mut builder).await, 0);
+
+        builder.set_security_context(true);
+        assert_eq!(init_flags2(&mut builder).await, (FUSE_SECURITY_CTX >> 32) as u32);
+
+        // Turning it off again is honoured by the next open
+        builder.set_security_context(false);
+        assert_eq!(init_flags2(&mut builder).await, 0);
+    }
+
+    /// Fails every receive as the device does for a buffer the kernel finds too small
+    struct Broken;
+
+    #[async_trait]
+    impl Transport for Broken {
+        async fn receive(&self, _buffer: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::from_raw_os_error(libc::EIO))
+        }
+
+        async fn send(&self, buffer: &[u8]) -> io::Result<usize> {
+            Ok(buffer.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn receive_error_stops_session() {
+        let (request_tx, _request_rx) = crate::create_request_channel();
+
+        let session = Builder::new()
+            .set_outbound_fs_request_tx(&request_tx)
+            .set_workers(2)
+            .open_with_transport(Arc::new(Broken))
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), session.cancellation_token.cancelled())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "abi-7-12")]
+    #[tokio::test]
+    async fn cuse_over_socket() {
+        use crate::builder::CuseBuilder;
+
+        let (kernel, transport) = SocketTransport::pair().unwrap();
+        let (request_tx, mut request_rx) = crate::create_request_channel();
+
+        let mut builder = CuseBuilder::new("fusion0");
+        builder.set_dev_number(240, 1);
+        builder.builder().set_outbound_fs_request_tx(&request_tx);
+        let session = builder.open_with_transport(Arc::new(transport)).await.unwrap();
+
+        let mut buffer = vec![0u8; 4096];
+
+        let init = cuse_init_in {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            unused: 0,
+            flags: crate::constants::CUSE_UNRESTRICTED_IOCTL,
+        };
+        kernel
+            .send(&message(fuse_opcode::CUSE_INIT, 1, init.as_bytes()))
+            .await
+            .unwrap();
+
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, rest) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (1, 0));
+        let (arg, info) = cuse_init_out::try_read_from_prefix(rest).unwrap();
+        assert_eq!((arg.major, arg.dev_major, arg.dev_minor), (7, 240, 1));
+        // Not asked for
+        assert_eq!(arg.flags, 0);
+        assert_eq!(info, b"DEVNAME=fusion0\0");
+        session.initialized().await.unwrap();
+
+        let open = fuse_open_in::new_zeroed();
+        kernel
+            .send(&message(fuse_opcode::FUSE_OPEN, 2, open.as_bytes()))
+            .await
+            .unwrap();
+        let request = request_rx.recv().await.unwrap();
+        let mut arg = fuse_open_out::new_zeroed();
+        arg.fh = 3;
+        request.reply_open(arg).await.unwrap();
+
+        let count = kernel.receive(&mut buffer).await.unwrap();
+        let (header, rest) = fuse_out_header::try_read_from_prefix(&buffer[..count]).unwrap();
+        assert_eq!((header.unique, header.error), (2, 0));
+        assert_eq!(fuse_open_out::try_read_from_prefix(rest).unwrap().0.fh, 3);
+    }
+}